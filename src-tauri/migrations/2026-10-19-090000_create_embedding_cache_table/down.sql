@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS embedding_cache_last_used_at;
+DROP TABLE IF EXISTS embedding_cache;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS embedding_cache (
+    model TEXT NOT NULL DEFAULT '',
+    text_hash TEXT NOT NULL DEFAULT '',
+    dimension INTEGER NOT NULL DEFAULT 0,
+    vector BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT '',
+    last_used_at INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (model, text_hash)
+);
+
+CREATE INDEX IF NOT EXISTS embedding_cache_last_used_at ON embedding_cache (last_used_at);
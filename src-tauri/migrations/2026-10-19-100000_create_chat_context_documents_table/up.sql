@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS chat_context_documents (
+    chat_id INTEGER NOT NULL,
+    document_id INTEGER NOT NULL,
+    score REAL NOT NULL DEFAULT 0,
+    query TEXT NOT NULL DEFAULT '',
+    added_at TEXT NOT NULL DEFAULT '',
+    PRIMARY KEY (chat_id, document_id),
+    FOREIGN KEY (chat_id) REFERENCES chats (id) ON DELETE CASCADE
+);
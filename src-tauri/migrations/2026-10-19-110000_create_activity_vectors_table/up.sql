@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS activity_vectors (
+    document_id INTEGER PRIMARY KEY,
+    model TEXT NOT NULL DEFAULT '',
+    dimension INTEGER NOT NULL DEFAULT 0,
+    vector BLOB NOT NULL,
+    quantized_vector BLOB NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT '',
+    FOREIGN KEY (document_id) REFERENCES activity_full_text (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS activity_vectors_model ON activity_vectors (model, dimension);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS pending_embeddings (
+    document_id INTEGER PRIMARY KEY,
+    enqueued_at TEXT NOT NULL DEFAULT '',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    FOREIGN KEY (document_id) REFERENCES activity_full_text (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS pending_embeddings_next_attempt_at ON pending_embeddings (next_attempt_at);
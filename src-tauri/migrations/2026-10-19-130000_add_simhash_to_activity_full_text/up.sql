@@ -0,0 +1 @@
+ALTER TABLE activity_full_text ADD COLUMN simhash INTEGER;
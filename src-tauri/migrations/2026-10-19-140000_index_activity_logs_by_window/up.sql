@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS activity_logs_window ON activity_logs (window_title, window_app_name);
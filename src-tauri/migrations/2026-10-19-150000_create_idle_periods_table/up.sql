@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS idle_periods (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    locked INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1 @@
+ALTER TABLE activity_logs ADD COLUMN screenshot_hash TEXT;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE activity_logs DROP COLUMN ocr_word_boxes;
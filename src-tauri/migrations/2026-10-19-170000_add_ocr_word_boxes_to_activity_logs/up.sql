@@ -0,0 +1 @@
+ALTER TABLE activity_logs ADD COLUMN ocr_word_boxes TEXT;
@@ -1,6 +1,15 @@
 #![cfg(any(target_os = "linux"))]
 
-pub fn check_and_install_prerequisites(resources_data_dir: &str) -> &str {
-    // todo()
-    return "";
-}
\ No newline at end of file
+use log::info;
+
+use crate::engine::tesseract_ocr_engine::TesseractOcrEngine;
+
+pub fn check_and_install_prerequisites(_resources_data_dir: &str) {
+    // Installing packages needs root, so a missing Tesseract is only reported
+    if let Err(e) = TesseractOcrEngine::detect() {
+        info!(
+            "{}. Install tesseract-ocr and its language packs with the system package manager",
+            e
+        );
+    }
+}
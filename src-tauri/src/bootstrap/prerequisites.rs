@@ -1,3 +1,5 @@
+use log::info;
+
 #[cfg(any(target_os = "windows"))]
 use crate::bootstrap::{check_prerequisites_windows};
 
@@ -6,8 +8,18 @@ use crate::bootstrap::{check_prerequisites_macos};
 
 #[cfg(any(target_os = "linux"))]
 use crate::bootstrap::{check_prerequisites_linux};
+use crate::engine::text_recognition_engine::OcrEngineKind;
 
-pub fn check_and_install_prerequisites(resources_data_dir: &str) {
+/// Installs Tesseract unless the built-in engine was chosen, which reads English only with
+/// the bundled models.
+pub fn check_and_install_prerequisites(
+    resources_data_dir: &str,
+    ocr_engine: Option<OcrEngineKind>,
+) {
+    if ocr_engine == Some(OcrEngineKind::PureRust) {
+        info!("Not installing Tesseract, the built-in OCR engine was chosen");
+        return;
+    }
 
     #[cfg(any(target_os = "macos"))]
     check_prerequisites_macos::check_and_install_prerequisites(resources_data_dir);
@@ -17,4 +29,4 @@ pub fn check_and_install_prerequisites(resources_data_dir: &str) {
 
     #[cfg(any(target_os = "linux"))]
     check_prerequisites_linux::check_and_install_prerequisites(resources_data_dir);
-}
\ No newline at end of file
+}
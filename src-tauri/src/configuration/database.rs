@@ -1,17 +1,19 @@
 use std::fs;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
 
 use diesel::sqlite::SqliteConnection;
 use diesel::Connection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use log::info;
 use tauri::AppHandle;
-use tokio::sync::Mutex;
 
+use crate::engine::embedding_cache_engine::init_embedding_cache;
 use crate::engine::similarity_search_engine::{SimilaritySearch, SyncSimilaritySearch};
 use crate::HNSW;
 
-pub type SyncVectorDatabase = Arc<Mutex<Option<SimilaritySearch>>>;
+const SQLITE_DB_FILE: &str = "heelixchat.sqlite";
+pub const VECTOR_DB_DIR: &str = "hnsw";
+pub const VECTOR_COLLECTION_NAME: &str = "activity_vectors";
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
@@ -23,18 +25,31 @@ pub fn initialize_database(
         .app_data_dir()
         .expect("The app data directory should exist.");
     fs::create_dir_all(&app_dir).expect("The app data directory should be created.");
-    let sqlite_path = app_dir.join("heelixchat.sqlite");
+    let sqlite_path = get_sqlite_path(app_handle);
     info!("SQLITE_PATH: {}", sqlite_path.display());
     let db = rusqlite::Connection::open(sqlite_path.clone())?;
     let user_pragma = db.prepare("PRAGMA user_version")?;
     drop(user_pragma);
+    run_migrations(&sqlite_path);
+    init_embedding_cache(&sqlite_path)?;
+    Ok(db)
+}
+
+pub fn get_sqlite_path(app_handle: &AppHandle) -> PathBuf {
+    let app_dir = app_handle
+        .path_resolver()
+        .app_data_dir()
+        .expect("The app data directory should exist.");
+    app_dir.join(SQLITE_DB_FILE)
+}
+
+pub fn run_migrations(sqlite_path: &Path) {
     let mut connection_diesel =
         SqliteConnection::establish(sqlite_path.display().to_string().as_str())
             .unwrap_or_else(|_| panic!("Error connecting to {}", "database"));
     connection_diesel
         .run_pending_migrations(MIGRATIONS)
         .unwrap();
-    Ok(db)
 }
 
 pub async fn drop_database_handle() {
@@ -58,15 +73,36 @@ pub async fn get_vector_db(
     }
 }
 
-fn initialize_vector_database<'a>(
-    app_handle: &AppHandle,
-) -> Result<SimilaritySearch, Box<dyn std::error::Error>> {
+pub fn get_vector_db_path(app_handle: &AppHandle) -> String {
     let app_dir = app_handle
         .path_resolver()
         .app_data_dir()
         .expect("The app data directory should exist.");
-    let hnsw_db_path = app_dir.join("hnsw");
-    let collection_name = "activity_vectors";
-    let hnsw = SimilaritySearch::open(hnsw_db_path.to_str().unwrap(), collection_name)?;
+    app_dir.join(VECTOR_DB_DIR).to_str().unwrap().to_string()
+}
+
+fn initialize_vector_database<'a>(
+    app_handle: &AppHandle,
+) -> Result<SimilaritySearch, Box<dyn std::error::Error>> {
+    let hnsw_db_path = get_vector_db_path(app_handle);
+    let hnsw = SimilaritySearch::open(&hnsw_db_path, VECTOR_COLLECTION_NAME)?;
     Ok(hnsw)
 }
+
+/// A migrated SQLite database in a temporary directory. The directory is removed when it
+/// is dropped, so keep it alive while the connection or path are in use.
+#[cfg(test)]
+pub struct TestDatabase {
+    pub db: rusqlite::Connection,
+    pub path: PathBuf,
+    pub dir: tempfile::TempDir,
+}
+
+#[cfg(test)]
+pub fn open_test_database() -> anyhow::Result<TestDatabase> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join(SQLITE_DB_FILE);
+    run_migrations(&path);
+    let db = rusqlite::Connection::open(&path)?;
+    Ok(TestDatabase { db, path, dir })
+}
@@ -9,4 +9,36 @@ pub struct Settings {
     pub api_choice: String,
     pub api_key_claude: String,
     pub api_key_open_ai: String,
+    #[serde(default)]
+    pub reranker: Option<String>,
+    #[serde(default)]
+    pub near_duplicate_threshold: Option<f32>,
+    #[serde(default)]
+    pub recency_half_life_days: Option<f32>,
+    #[serde(default)]
+    pub recency_weight: Option<f32>,
+    #[serde(default)]
+    pub engagement_weight: Option<f32>,
+    #[serde(default)]
+    pub idle_threshold_minutes: Option<u32>,
+    #[serde(default)]
+    pub screenshot_change_threshold: Option<u32>,
+    #[serde(default)]
+    pub ocr_languages: Option<String>,
+    #[serde(default)]
+    pub ocr_page_segmentation_mode: Option<i32>,
+    #[serde(default)]
+    pub ocr_min_confidence: Option<f32>,
+    #[serde(default)]
+    pub ocr_engine: Option<String>,
+    #[serde(default)]
+    pub screenshot_retention: Option<String>,
+    #[serde(default)]
+    pub screenshot_retention_count: Option<u32>,
+    #[serde(default)]
+    pub screenshot_format: Option<String>,
+    #[serde(default)]
+    pub screenshot_max_width: Option<u32>,
+    #[serde(default)]
+    pub vector_store: Option<String>,
 }
\ No newline at end of file
@@ -0,0 +1,438 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use lazy_static::lazy_static;
+use log::error;
+use regex::Regex;
+use serde::Deserialize;
+
+// The defaults are compiled in; the same file ships with the resources as an example
+const BUNDLED_RULES: &str = include_str!("../../resources/app_rules.json");
+pub const USER_RULES_FILE: &str = "app_rules.json";
+// The accessibility text is used when it is longer than this share of the OCR text
+const ACCESSIBILITY_TEXT_SHARE: f64 = 0.2;
+// Sites are recognised from the address, at the start of the accessibility text
+const SITE_PREFIX_CHARS: usize = 20;
+const SKIP_MARKER_PREFIX_CHARS: usize = 300;
+
+lazy_static! {
+    // The rules are read on every capture, so they are parsed again only when the user's
+    // file changes
+    static ref LOADED_RULES: Mutex<Option<LoadedRules>> = Mutex::new(None);
+}
+
+struct LoadedRules {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    rules: Arc<AppRules>,
+}
+
+/// Where the text of a window is taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextSource {
+    /// The accessibility text when it is not much shorter than the OCR text.
+    Auto,
+    /// The accessibility text whenever there is any.
+    Accessibility,
+    Ocr,
+}
+
+impl TextSource {
+    pub fn uses_accessibility_text(&self, accessibility_len: usize, ocr_len: usize) -> bool {
+        match self {
+            TextSource::Auto => {
+                accessibility_len > (ocr_len as f64 * ACCESSIBILITY_TEXT_SHARE) as usize
+            }
+            TextSource::Accessibility => accessibility_len > 0,
+            TextSource::Ocr => false,
+        }
+    }
+}
+
+/// Where the title of a window is taken from. Titles from the accessibility text are its
+/// first word.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleSource {
+    Window,
+    AccessibilityWhenEmpty,
+    Accessibility,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditingMode {
+    /// Decided from how much the text changed since the last capture.
+    Detect,
+    Never,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<RawRule>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RawRule {
+    name: String,
+    app_name: Option<String>,
+    title: Option<String>,
+    text_source: Option<TextSource>,
+    title_source: Option<TitleSource>,
+    editing_mode: Option<EditingMode>,
+    sites: Option<Vec<String>>,
+    skip_when_contains: Option<String>,
+    activity_text: Option<String>,
+}
+
+/// A rule applies to windows whose app name and title match its patterns; a rule without
+/// patterns applies to every window.
+#[derive(Debug)]
+pub struct AppRule {
+    pub name: String,
+    app_name: Option<Regex>,
+    title: Option<Regex>,
+    text_source: Option<TextSource>,
+    title_source: Option<TitleSource>,
+    editing_mode: Option<EditingMode>,
+    sites: Option<Vec<String>>,
+    skip_when_contains: Option<String>,
+    activity_text: Option<String>,
+}
+
+impl AppRule {
+    fn compile(raw: RawRule) -> Result<Self, String> {
+        let compile = |pattern: Option<String>| match pattern {
+            Some(pattern) => Regex::new(&pattern)
+                .map(Some)
+                .map_err(|e| format!("Invalid pattern in app rule {}: {}", raw.name, e)),
+            None => Ok(None),
+        };
+        Ok(AppRule {
+            app_name: compile(raw.app_name.clone())?,
+            title: compile(raw.title.clone())?,
+            name: raw.name,
+            text_source: raw.text_source,
+            title_source: raw.title_source,
+            editing_mode: raw.editing_mode,
+            sites: raw.sites,
+            skip_when_contains: raw.skip_when_contains,
+            activity_text: raw.activity_text,
+        })
+    }
+
+    fn matches(&self, app_name: &str, title: &str) -> bool {
+        let app_name_matches = match &self.app_name {
+            Some(pattern) => pattern.is_match(app_name),
+            None => true,
+        };
+        let title_matches = match &self.title {
+            Some(pattern) => pattern.is_match(title),
+            None => true,
+        };
+        app_name_matches && title_matches
+    }
+}
+
+/// How the monitoring engine treats a window, from the rules that match it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppBehaviour {
+    pub text_source: TextSource,
+    pub title_source: TitleSource,
+    pub editing_mode: EditingMode,
+    /// Substrings of the address that name the site, which is then recorded as the app.
+    pub sites: Vec<String>,
+    /// Windows whose accessibility text contains this within its first 300 characters are
+    /// not recorded at all.
+    pub skip_when_contains: Option<String>,
+    /// Recorded instead of the window's text.
+    pub activity_text: Option<String>,
+}
+
+impl Default for AppBehaviour {
+    fn default() -> Self {
+        AppBehaviour {
+            text_source: TextSource::Auto,
+            title_source: TitleSource::Window,
+            editing_mode: EditingMode::Detect,
+            sites: Vec::new(),
+            skip_when_contains: None,
+            activity_text: None,
+        }
+    }
+}
+
+impl AppBehaviour {
+    pub fn skips(&self, element_tree_dump: &str) -> bool {
+        match &self.skip_when_contains {
+            Some(marker) => element_tree_dump
+                .chars()
+                .take(SKIP_MARKER_PREFIX_CHARS)
+                .collect::<String>()
+                .contains(marker.as_str()),
+            None => false,
+        }
+    }
+
+    /// The title and app name to record for the window. A site is only looked for when the
+    /// title is the window's own.
+    pub fn identify(
+        &self,
+        title: &str,
+        app_name: &str,
+        element_tree_dump: &str,
+    ) -> (String, String) {
+        let title_from_text = match self.title_source {
+            TitleSource::Window => false,
+            TitleSource::AccessibilityWhenEmpty => title.is_empty(),
+            TitleSource::Accessibility => true,
+        };
+        if title_from_text {
+            let title = element_tree_dump.split_whitespace().next().unwrap_or(title);
+            return (title.to_string(), app_name.to_string());
+        }
+        let prefix = element_tree_dump
+            .chars()
+            .take(SITE_PREFIX_CHARS)
+            .collect::<String>();
+        let app_name = self
+            .sites
+            .iter()
+            .find(|site| prefix.contains(site.as_str()))
+            .map_or(app_name, |site| site.as_str());
+        (title.to_string(), app_name.to_string())
+    }
+}
+
+/// Per-app rules deciding where the text and title of a window come from, whether it can be
+/// in editing mode and which sites are recorded as apps. The user's rules are checked before
+/// the bundled ones; each setting comes from the first matching rule that sets it.
+#[derive(Debug)]
+pub struct AppRules {
+    rules: Vec<AppRule>,
+}
+
+impl AppRules {
+    /// Parses the rules from JSON, leaving out rules with invalid patterns.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let file: RulesFile = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let rules = file
+            .rules
+            .into_iter()
+            .filter_map(|raw| match AppRule::compile(raw) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            })
+            .collect();
+        Ok(AppRules { rules })
+    }
+
+    pub fn bundled() -> Self {
+        AppRules::parse(BUNDLED_RULES).expect("bundled app rules are valid")
+    }
+
+    /// The bundled rules, preceded by the user's from `app_rules.json` in the app data
+    /// directory when there is one.
+    pub fn load(app_data_dir: &Path) -> Self {
+        let mut rules = AppRules::bundled();
+        let path = app_data_dir.join(USER_RULES_FILE);
+        if let Ok(json) = fs::read_to_string(&path) {
+            match AppRules::parse(&json) {
+                Ok(user_rules) => {
+                    let bundled = std::mem::replace(&mut rules.rules, user_rules.rules);
+                    rules.rules.extend(bundled);
+                }
+                Err(e) => error!("Failed to read app rules {}: {}", path.display(), e),
+            }
+        }
+        rules
+    }
+
+    /// Like `load`, but keeps the rules until the modification time of the user's file
+    /// changes, so edits still apply at the next capture.
+    pub fn cached(app_data_dir: &Path) -> Arc<AppRules> {
+        let path = app_data_dir.join(USER_RULES_FILE);
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+        let mut loaded = LOADED_RULES.lock().unwrap();
+        if let Some(current) = loaded.as_ref() {
+            if current.path == path && current.modified == modified {
+                return current.rules.clone();
+            }
+        }
+        let rules = Arc::new(AppRules::load(app_data_dir));
+        *loaded = Some(LoadedRules {
+            path,
+            modified,
+            rules: rules.clone(),
+        });
+        rules
+    }
+
+    pub fn behaviour_for(&self, app_name: &str, title: &str) -> AppBehaviour {
+        let matching: Vec<&AppRule> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(app_name, title))
+            .collect();
+        let defaults = AppBehaviour::default();
+        AppBehaviour {
+            text_source: matching
+                .iter()
+                .find_map(|rule| rule.text_source)
+                .unwrap_or(defaults.text_source),
+            title_source: matching
+                .iter()
+                .find_map(|rule| rule.title_source)
+                .unwrap_or(defaults.title_source),
+            editing_mode: matching
+                .iter()
+                .find_map(|rule| rule.editing_mode)
+                .unwrap_or(defaults.editing_mode),
+            sites: matching
+                .iter()
+                .find_map(|rule| rule.sites.clone())
+                .unwrap_or(defaults.sites),
+            skip_when_contains: matching
+                .iter()
+                .find_map(|rule| rule.skip_when_contains.clone()),
+            activity_text: matching.iter().find_map(|rule| rule.activity_text.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AppRules, EditingMode, TextSource, TitleSource, USER_RULES_FILE};
+
+    #[test]
+    fn ai_chats_take_the_title_from_the_page_and_never_edit() {
+        let rules = AppRules::bundled();
+        let behaviour = rules.behaviour_for("Google Chrome", "Claude");
+        assert_eq!(behaviour.title_source, TitleSource::Accessibility);
+        assert_eq!(behaviour.editing_mode, EditingMode::Never);
+        assert_eq!(
+            behaviour.identify("Claude", "Google Chrome", "github.com Pull request review"),
+            ("github.com".to_string(), "Google Chrome".to_string())
+        );
+    }
+
+    #[test]
+    fn browsers_fill_missing_titles_and_skip_private_windows() {
+        let rules = AppRules::bundled();
+        let safari = rules.behaviour_for("Safari", "");
+        assert_eq!(
+            safari.identify("", "Safari", "Welcome to the release notes"),
+            ("Welcome".to_string(), "Safari".to_string())
+        );
+        assert!(!safari.skips("Welcome to the release notes"));
+        assert_eq!(safari.editing_mode, EditingMode::Detect);
+
+        let firefox = rules.behaviour_for("Firefox", "New tab");
+        assert!(firefox.skips("Mozilla Firefox Private Browsing"));
+        assert_eq!(
+            firefox.identify("New tab", "Firefox", "Mozilla Firefox"),
+            ("New tab".to_string(), "Firefox".to_string())
+        );
+        assert!(!rules.behaviour_for("Notes", "").skips("Private Browsing"));
+    }
+
+    #[test]
+    fn heelix_chat_records_a_placeholder_from_ocr() {
+        let behaviour = AppRules::bundled().behaviour_for("Heelix Chat", "Heelix");
+        assert_eq!(behaviour.text_source, TextSource::Ocr);
+        assert_eq!(behaviour.editing_mode, EditingMode::Never);
+        assert_eq!(
+            behaviour.activity_text.as_deref(),
+            Some("Heelix monitoring engine")
+        );
+    }
+
+    #[test]
+    fn visual_apps_are_read_with_ocr() {
+        let rules = AppRules::bundled();
+        for app_name in ["Microsoft PowerPoint", "DataGrip"] {
+            let behaviour = rules.behaviour_for(app_name, "Quarterly review");
+            assert_eq!(behaviour.text_source, TextSource::Ocr);
+            assert!(!behaviour.text_source.uses_accessibility_text(5000, 100));
+        }
+        let other = rules.behaviour_for("PyCharm", "main.py");
+        assert_eq!(other.text_source, TextSource::Auto);
+        assert!(other.text_source.uses_accessibility_text(30, 100));
+        assert!(!other.text_source.uses_accessibility_text(10, 100));
+        assert_eq!(other.activity_text, None);
+    }
+
+    #[test]
+    fn popular_websites_are_recorded_as_the_app() {
+        let behaviour = AppRules::bundled().behaviour_for("Google Chrome", "Issues");
+        assert_eq!(
+            behaviour.identify(
+                "Issues",
+                "Google Chrome",
+                "https://github.com/heelix/issues"
+            ),
+            ("Issues".to_string(), "github".to_string())
+        );
+        // Only the address at the start counts
+        assert_eq!(
+            behaviour.identify(
+                "Mail",
+                "Google Chrome",
+                "Inbox - a link to github.com below"
+            ),
+            ("Mail".to_string(), "Google Chrome".to_string())
+        );
+    }
+
+    #[test]
+    fn user_rules_come_before_the_bundled_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(USER_RULES_FILE),
+            r#"{"rules": [
+                {"name": "slides", "app_name": "^Microsoft PowerPoint$", "text_source": "accessibility"},
+                {"name": "broken", "title": "(", "editing_mode": "never"}
+            ]}"#,
+        )
+        .unwrap();
+        let rules = AppRules::load(dir.path());
+        let behaviour = rules.behaviour_for("Microsoft PowerPoint", "Quarterly review");
+        assert_eq!(behaviour.text_source, TextSource::Accessibility);
+        assert_eq!(behaviour.editing_mode, EditingMode::Detect);
+        // Bundled rules still apply to what the user's rules leave out
+        assert!(!behaviour.sites.is_empty());
+    }
+
+    #[test]
+    fn cached_rules_are_reloaded_when_the_user_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(USER_RULES_FILE);
+        let first = AppRules::cached(dir.path());
+        assert!(Arc::ptr_eq(&first, &AppRules::cached(dir.path())));
+        assert_eq!(
+            first.behaviour_for("Notes", "").editing_mode,
+            EditingMode::Detect
+        );
+
+        std::fs::write(
+            &path,
+            r#"{"rules": [{"name": "notes", "app_name": "^Notes$", "editing_mode": "never"}]}"#,
+        )
+        .unwrap();
+        let edited = AppRules::cached(dir.path());
+        assert!(!Arc::ptr_eq(&first, &edited));
+        assert_eq!(
+            edited.behaviour_for("Notes", "").editing_mode,
+            EditingMode::Never
+        );
+        assert!(Arc::ptr_eq(&edited, &AppRules::cached(dir.path())));
+    }
+}
@@ -1,17 +1,22 @@
+use async_trait::async_trait;
 use futures::StreamExt;
 use log::{debug, error, info};
 use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::collections::HashSet;
 use std::time::Duration;
 use tauri::{AppHandle, Manager};
 
 use crate::configuration::state::ServiceAccess;
-use crate::database;
-use crate::engine::similarity_search_engine::TOPK;
-use crate::repository::activity_log_repository::get_activity_full_text_by_id;
-use crate::repository::activity_log_repository::get_additional_ids_from_sql_db;
+use crate::engine::reranking_engine::{
+    parse_ranked_ids, relevance_context, relevance_system_prompt, RerankCandidate, Reranker,
+    ScoredDocument,
+};
+use crate::engine::query_rewriting_engine::{
+    parse_query_rewrite, query_rewrite_system_prompt, query_rewrite_user_prompt, standalone_query,
+    QueryRewrite, QueryRewriter,
+};
+use crate::engine::retrieval_engine::{load_conversation_context, retrieve_relevant_documents};
 use crate::repository::settings_repository::get_setting;
 
 #[derive(Serialize)]
@@ -57,6 +62,7 @@ pub async fn send_prompt_to_llm(
     conversation_history: Vec<Message>,
     is_first_message: bool,
     combined_activity_text: String,
+    chat_id: Option<i64>,
 ) -> Result<(), String> {
     let setting =
         app_handle.db(|db| get_setting(db, "api_key_claude").expect("Failed on api_key_claude"));
@@ -73,249 +79,8 @@ pub async fn send_prompt_to_llm(
         .build()
         .map_err(|e| format!("Failed to create client: {}", e))?;
 
-    let mut filtered_context = String::new();
-    let mut window_titles = Vec::new();
     debug!("Combined activity text: {}", combined_activity_text);
 
-    if is_first_message {
-        let user_prompt = conversation_history
-            .last()
-            .map(|msg| msg.content.clone())
-            .unwrap_or_default();
-        info!("User Prompt: {}", user_prompt);
-        
-        let relevant_keywords =
-            match identify_relevant_keywords(&user_prompt, &setting.setting_value).await {
-                Ok(keywords) => keywords,
-                Err(err) => {
-                    error!(
-                        "Keyword extraction failed: {}. Using the entire prompt as fallback keywords.",
-                        err
-                    );
-                    vec![user_prompt.clone()]
-                }
-            };
-
-        info!("Getting database instance");
-        let hnsw_bind = database::get_vector_db(&app_handle)
-            .await
-            .expect("Database initialization failed!");
-        let top_k = TOPK;
-        let hnsw_guard = hnsw_bind.lock().await;
-        info!("Setting up database lock");
-        let db = hnsw_guard.as_ref().expect("HNSW database not initialized!");
-        info!("Initiating similarity search...");
-
-        let similar_ids_with_distances = db
-            .top_k(&user_prompt, top_k, &setting_openai.setting_value)
-            .await
-            .map_err(|e| format!("Similarity search failed: {}", e))?;
-
-        let similar_ids_vec: Vec<(i64, f32)> = similar_ids_with_distances
-            .into_iter()
-            .map(|(id, distance)| (id as i64, distance))
-            .collect();
-
-        let similar_ids: Vec<i64> = similar_ids_vec.iter().map(|(id, _)| *id).collect();
-
-        let additional_ids = app_handle
-            .db(|db| {
-                let result = get_additional_ids_from_sql_db(db, 3, &relevant_keywords);
-                return result;
-            })
-            .map_err(|e| format!("Failed to retrieve additional IDs from SQL database: {}", e))?;
-        debug!("Additional IDs: {:?}", additional_ids);
-
-        let mut all_ids_set = HashSet::new();
-        all_ids_set.extend(similar_ids);
-        all_ids_set.extend(additional_ids);
-
-        let mut context = String::new();
-
-        for (index, document_id) in all_ids_set.iter().enumerate() {
-            let result: Option<(String, String)> = app_handle
-                .db(|db| get_activity_full_text_by_id(db, *document_id, Some(1000)))
-                .map_err(|e| {
-                    format!(
-                        "Failed to retrieve edited full text for ID {}: {}",
-                        document_id, e
-                    )
-                })
-                .unwrap_or_else(|err| {
-                    error!("{}", err);
-                    None
-                });
-
-            if let Some((_window_title, text)) = result {
-                debug!("Document {}: ID: {}", index + 1, document_id);
-                context.push_str(&format!(
-                    "Document ID: {}\nContent:\n{}\n\n",
-                    document_id, text
-                ));
-            }
-        }
-
-        if context.is_empty() {
-            context.push_str("No relevant documents found.\n\n");
-        }
-
-        info!("Relevant Keywords: {:?}", relevant_keywords);
-
-        let relevance_system_prompt = format!( "The user's prompt is: {}\n\n. You are an intelligent and logical personal assistant. Your task is to carefully review the content of provided documents and output solely a maximum of four numerical IDs of the documents that are directly related to the user prompt and are highly likely to help in answering the user's prompt (corresponding to the Document ID at the beginning of each document). If an individual document is not extremely relevant to the user prompt and the user prompt can be successfully answered without that document, do not include it in the list of returned documents.
-
-        Examples of relevant and irrelevant documents in different business scenarios:
-        If a document is virtually identical to another one, just include one of them in the list of returned documents.
-        
-        Example 1: The user prompt is to outline effective marketing strategies for social media.
-        - Relevant document:
-            Document ID: 55
-            Content: This document details various social media marketing strategies, which is directly relevant to the user's prompt.
-        - Irrelevant document:
-            Document ID: 78
-            Content: This document describes traditional print advertising methods, which is not relevant to social media marketing strategies.
-    
-        Example 2: The user prompt is researching the best programming practices for AI development.
-        - Relevant document:
-            Document ID: 33
-            Content: This document provides best practices for AI development, which is directly relevant to the user's prompt.
-        - Irrelevant document:
-            Document ID: 47
-            Content: This document discusses basic HTML and CSS programming, which is not relevant to the user's prompt about AI development.
-    
-        Example 3: The user prompt asks for recommended books on investment strategies.
-        - Relevant documents:
-            Document ID: 17
-            Content: This document lists top-rated books on investment strategies, highly relevant to the user's prompt.
-            Document ID: 106
-            Content: This document summarizes famous investment strategies, which is also relevant to the user's prompt.
-            Document ID: 204
-            Content: This document contains interviews with successful investors discussing their strategies, directly relevant to the user's prompt.
-            Document ID: 345
-            Content: This document reviews recent books on future investment trends, relevant to the user's prompt.
-        - Irrelevant document:
-            Document ID: 88
-            Content: This document covers general finance tips, which may not be directly relevant to specific investment strategies.
-    
-        Example 4: The user prompt is to find best practices for remote team management.
-        - Relevant document:
-            Document ID: 99
-            Content: This document covers best practices for managing remote teams, directly relevant to the user's prompt.
-        - Irrelevant document:
-            Document ID: 65
-            Content: This document discusses in-office team-building activities, which are not relevant to managing remote teams.
-    
-        Example 5: The user prompt is about analyzing the latest trends in cybersecurity.
-        - Relevant documents:
-            Document ID: 120
-            Content: This document provides a detailed analysis of the latest cybersecurity trends, directly relevant to the user's prompt.
-            Document ID: 150
-            Content: This document includes recent cybersecurity reports and data, relevant to understanding current trends.
-        - Irrelevant document:
-            Document ID: 88
-            Content: This document outlines historical cybersecurity breaches, which may not be directly relevant to analyzing current trends.
-            Document ID: 200
-            Content: This document focuses on outdated cybersecurity practices, which are not relevant to the latest trends.
-    
-        Example 6: The user prompt asks for guidelines on creating an investment portfolio.
-        - Relevant document:
-            Document ID: 300
-            Content: This document provides detailed guidelines on how to create and manage an investment portfolio, highly relevant to the user's prompt.
-        - Irrelevant document:
-            Document ID: 77
-            Content: This document discusses corporate investment strategies, which may not be directly applicable to individual investment portfolios.
-    
-        Example 7: The user prompt asks for something not covered by any provided document.
-        - User prompt: Strategies for eco-friendly business operations.
-        - No documents: None of the documents provided contain information about eco-friendly business operations, so no documents should be returned.
-    
-        The user's prompt is: {}\n\nOutput the relevant document IDs as a comma-separated list of numbers only or an empty list, with absolutely no other additional text or explanations. For example: 123,456,789 or an empty list.",
-        user_prompt, user_prompt
-    );
-
-        let relevance_request_body = ClaudeRequest {
-            model: ANTRHOPIC_MODEL.to_string(),
-            max_tokens: 100,
-            messages: vec![Message {
-                role: "user".to_string(),
-                content: context,
-            }],
-            system: relevance_system_prompt,
-            stream: false,
-        };
-
-        let relevance_response = client
-            .post(ANTHROPIC_URL)
-            .header("Content-Type", "application/json")
-            .header("x-api-key", &setting.setting_value)
-            .header("anthropic-version", "2023-06-01")
-            .header("Connection", "keep-alive")  // Added keep-alive header
-            .json(&relevance_request_body)
-            .send()
-            .await
-            .map_err(|e| format!("Relevance filtering request failed: {}", e))?;
-
-        debug!("Relevance filtering response: {:?}", relevance_response);
-
-        if relevance_response.status().is_success() {
-            let relevance_result: ClaudeResponse = relevance_response
-                .json()
-                .await
-                .map_err(|e| format!("Failed to parse relevance filtering response: {}", e))?;
-
-            info!(
-                "Relevance filtering token usage - Input: {}, Output: {}",
-                relevance_result.usage.input_tokens, relevance_result.usage.output_tokens
-            );
-
-            let relevant_document_ids: Vec<i64> = relevance_result
-                .content
-                .first()
-                .map(|content| {
-                    let text = &content.text;
-                    let numbers: Vec<i64> = text
-                        .split(|c: char| !c.is_numeric())
-                        .filter_map(|s| s.parse().ok())
-                        .collect();
-                    numbers
-                })
-                .unwrap_or_default();
-
-            debug!("Relevant document IDs: {:?}", relevant_document_ids);
-
-            for document_id in relevant_document_ids {
-                let result: Option<(String, String)> = app_handle
-                    .db(|db| get_activity_full_text_by_id(db, document_id, Some(10000)))
-                    .map_err(|e| format!("Failed to retrieve edited full text: {}", e))?;
-
-                if let Some((window_title, text)) = result {
-                    filtered_context.push_str(&format!(
-                        "Document ID: {}\nContent:\n{}\n\n",
-                        document_id, text
-                    ));
-                    window_titles.push(window_title);
-                }
-            }
-
-            debug!(
-                "Filtered context for final response generation: {}",
-                filtered_context
-            );
-        } else {
-            let error_message = relevance_response
-                .text()
-                .await
-                .map_err(|e| format!("Failed to read error message: {}", e))?;
-            info!(
-                "Error from Claude API during relevance filtering: {}",
-                error_message
-            );
-            return Err(format!(
-                "Error from Claude API during relevance filtering: {}",
-                error_message
-            ));
-        }
-    }
-
     let conversation_history_content = conversation_history
         .iter()
         .rev()
@@ -332,6 +97,58 @@ pub async fn send_prompt_to_llm(
         .collect::<Vec<String>>()
         .join("\n");
 
+    let user_prompt = conversation_history
+        .last()
+        .map(|msg| msg.content.clone())
+        .unwrap_or_default();
+    info!("User Prompt: {}", user_prompt);
+
+    let rewriter = ClaudeQueryRewriter {
+        client: client.clone(),
+        api_key: setting.setting_value.clone(),
+    };
+    let query = standalone_query(
+        &rewriter,
+        &conversation_history_content,
+        &user_prompt,
+        is_first_message,
+    )
+    .await;
+
+    let mut new_documents = Vec::new();
+    if let Some(query) = &query {
+        let relevant_keywords = match identify_relevant_keywords(query, &setting.setting_value).await
+        {
+            Ok(keywords) => keywords,
+            Err(err) => {
+                error!(
+                    "Keyword extraction failed: {}. Using the entire prompt as fallback keywords.",
+                    err
+                );
+                vec![query.clone()]
+            }
+        };
+        info!("Relevant Keywords: {:?}", relevant_keywords);
+
+        let reranker = ClaudeRelevanceReranker {
+            client: client.clone(),
+            api_key: setting.setting_value.clone(),
+        };
+        new_documents = retrieve_relevant_documents(
+            &app_handle,
+            query,
+            &relevant_keywords,
+            &setting_openai.setting_value,
+            &reranker,
+        )
+        .await?;
+    }
+
+    let retrieved =
+        load_conversation_context(&app_handle, chat_id, query.as_deref(), new_documents)?;
+    let filtered_context = retrieved.context;
+    let window_titles = retrieved.window_titles;
+
     let system_prompt = format!("You are Heelix chat app that is powered by Anthropic LLM. Heelix chat is developed by Heelix Technologies. Only identify yourself as such. Provide answer in markdown format. The following documents were retrieved from the user's device and may help in answering the prompt. Review them carefully to decide if they are relevant, if they are - using them to answer the query, but if they are not relevant to query, ignore them completely when responding, respond as if they were not there without mentioning having received them at all.{}\n\n
 Attached is the conversation history for context only. When answering, only give a single assistant response, do not also continue the conversation with a user answer.):
 {}\n\n", filtered_context, conversation_history_content);
@@ -406,6 +223,146 @@ Attached is the conversation history for context only. When answering, only give
     }
 }
 
+/// The relevance filter as a reranking stage: Claude reads a preview of every candidate
+/// and lists the IDs worth passing to the final prompt.
+pub struct ClaudeRelevanceReranker {
+    pub client: Client,
+    pub api_key: String,
+}
+
+#[async_trait]
+impl Reranker for ClaudeRelevanceReranker {
+    async fn rerank(
+        &self,
+        query: &str,
+        candidates: &[RerankCandidate],
+    ) -> Result<Vec<ScoredDocument>, String> {
+        let relevance_request_body = ClaudeRequest {
+            model: ANTRHOPIC_MODEL.to_string(),
+            max_tokens: 100,
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: relevance_context(candidates),
+            }],
+            system: relevance_system_prompt(query),
+            stream: false,
+        };
+
+        let relevance_response = self
+            .client
+            .post(ANTHROPIC_URL)
+            .header("Content-Type", "application/json")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("Connection", "keep-alive")  // Added keep-alive header
+            .json(&relevance_request_body)
+            .send()
+            .await
+            .map_err(|e| format!("Relevance filtering request failed: {}", e))?;
+
+        debug!("Relevance filtering response: {:?}", relevance_response);
+
+        if relevance_response.status().is_success() {
+            let relevance_result: ClaudeResponse = relevance_response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse relevance filtering response: {}", e))?;
+
+            info!(
+                "Relevance filtering token usage - Input: {}, Output: {}",
+                relevance_result.usage.input_tokens, relevance_result.usage.output_tokens
+            );
+
+            let relevant_documents = relevance_result
+                .content
+                .first()
+                .map(|content| parse_ranked_ids(&content.text, candidates))
+                .unwrap_or_default();
+
+            debug!("Relevant document IDs: {:?}", relevant_documents);
+            Ok(relevant_documents)
+        } else {
+            let error_message = relevance_response
+                .text()
+                .await
+                .map_err(|e| format!("Failed to read error message: {}", e))?;
+            info!(
+                "Error from Claude API during relevance filtering: {}",
+                error_message
+            );
+            Err(format!(
+                "Error from Claude API during relevance filtering: {}",
+                error_message
+            ))
+        }
+    }
+}
+
+/// Rewrites follow-ups into standalone search queries with the fast Claude model.
+pub struct ClaudeQueryRewriter {
+    pub client: Client,
+    pub api_key: String,
+}
+
+#[async_trait]
+impl QueryRewriter for ClaudeQueryRewriter {
+    async fn rewrite(
+        &self,
+        conversation_history: &str,
+        follow_up: &str,
+    ) -> Result<QueryRewrite, String> {
+        let request_body = ClaudeRequest {
+            model: ANTRHOPIC_MODEL_CHEAP.to_string(),
+            max_tokens: 100,
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: query_rewrite_user_prompt(conversation_history, follow_up),
+            }],
+            system: query_rewrite_system_prompt(),
+            stream: false,
+        };
+
+        let response = self
+            .client
+            .post(ANTHROPIC_URL)
+            .header("Content-Type", "application/json")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .header("Connection", "keep-alive")
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| format!("Query rewrite request failed: {}", e))?;
+
+        if response.status().is_success() {
+            let result: ClaudeResponse = response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse query rewrite response: {}", e))?;
+
+            info!(
+                "Query rewrite token usage - Input: {}, Output: {}",
+                result.usage.input_tokens, result.usage.output_tokens
+            );
+
+            Ok(result
+                .content
+                .first()
+                .map(|content| parse_query_rewrite(&content.text))
+                .unwrap_or(QueryRewrite::Skip))
+        } else {
+            let error_message = response
+                .text()
+                .await
+                .map_err(|e| format!("Failed to read error message: {}", e))?;
+            Err(format!(
+                "Error from Claude API during query rewrite: {}",
+                error_message
+            ))
+        }
+    }
+}
+
 async fn handle_success_response(
     response: Response,
     app_handle: AppHandle,
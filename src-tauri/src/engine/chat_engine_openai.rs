@@ -1,9 +1,15 @@
 use crate::configuration::state::ServiceAccess;
-use crate::database;
-use crate::engine::similarity_search_engine::TOPK;
-use crate::repository::activity_log_repository::get_activity_full_text_by_id;
-use crate::repository::activity_log_repository::get_additional_ids_from_sql_db;
+use crate::engine::reranking_engine::{
+    parse_ranked_ids, relevance_context, relevance_system_prompt, RerankCandidate, Reranker,
+    ScoredDocument,
+};
+use crate::engine::query_rewriting_engine::{
+    parse_query_rewrite, query_rewrite_system_prompt, query_rewrite_user_prompt, standalone_query,
+    QueryRewrite, QueryRewriter,
+};
+use crate::engine::retrieval_engine::{load_conversation_context, retrieve_relevant_documents};
 use crate::repository::settings_repository::get_setting;
+use async_trait::async_trait;
 use async_openai::{
     config::OpenAIConfig,
     types::{
@@ -16,7 +22,6 @@ use futures::StreamExt;
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::collections::HashSet;
 use tauri::Manager;
 
 const MODEL_FAST: &str = "gpt-3.5-turbo";
@@ -35,225 +40,11 @@ pub async fn send_prompt_to_openai(
     conversation_history: Vec<Message>,
     is_first_message: bool,
     combined_activity_text: String,
+    chat_id: Option<i64>,
 ) -> Result<(), String> {
     let setting =
         app_handle.db(|db| get_setting(db, "api_key_open_ai").expect("Failed on api_key_open_ai"));
 
-    let relevance_client =
-        OpenAIClient::with_config(OpenAIConfig::new().with_api_key(&setting.setting_value));
-    let mut filtered_context = String::new();
-    let mut window_titles = Vec::new();
-
-    if is_first_message {
-        // Perform similarity search and relevance filtering only for the first message
-        let user_prompt = conversation_history
-            .last()
-            .map(|msg| msg.content.clone())
-            .unwrap_or_default();
-        info!("User_prompt: {}", user_prompt);
-
-        let relevant_keywords =
-            identify_relevant_keywords_gpt4(&user_prompt, &setting.setting_value).await?;
-
-        // Perform similarity search in OasysDB
-        info!("Getting database instance");
-
-        let hnsw_bind = database::get_vector_db(&app_handle)
-            .await
-            .expect("Database initialization failed!");
-        let top_k = TOPK;
-        let hnsw_guard = hnsw_bind.lock().await;
-        info!("Setting up database lock");
-        let db = hnsw_guard.as_ref().expect("HNSW database not initialized!");
-        info!("Initiating similarity search...");
-
-        let similar_ids_with_distances = db
-            .top_k(&user_prompt, top_k, &setting.setting_value)
-            .await
-            .map_err(|e| format!("Similarity search failed: {}", e))?;
-
-        // Collect the results into a vector that we can use multiple times
-        let similar_ids_vec: Vec<(i64, f32)> = similar_ids_with_distances
-            .into_iter()
-            .map(|(id, distance)| (id as i64, distance))
-            .collect();
-
-        let similar_ids: Vec<i64> = similar_ids_vec.iter().map(|(id, _)| *id).collect();
-
-        let additional_ids = app_handle
-            .db(|db| {
-                let result = get_additional_ids_from_sql_db(db, 3, &relevant_keywords);
-                return result;
-            })
-            .map_err(|e| format!("Failed to retrieve additional IDs from SQL database: {}", e))?;
-
-        let mut all_ids_set = HashSet::new();
-        all_ids_set.extend(similar_ids);
-        all_ids_set.extend(additional_ids);
-
-        // Retrieve the corresponding documents from the SQL database
-        let mut context = String::new(); // Assuming context is initialized earlier
-
-        for (index, document_id) in all_ids_set.iter().enumerate() {
-            let result: Option<(String, String)> = app_handle
-                .db(|db| get_activity_full_text_by_id(db, *document_id, Some(1000)))
-                .map_err(|e| {
-                    format!(
-                        "Failed to retrieve edited full text for ID {}: {}",
-                        document_id, e
-                    )
-                })
-                .unwrap_or_else(|err| {
-                    error!("{}", err);
-                    None
-                });
-
-            if let Some((_window_title, text)) = result {
-                debug!("Document {}: ID: {}", index + 1, document_id);
-                debug!("Document {}: Content: {}", index + 1, text);
-                context.push_str(&format!(
-                    "Document ID: {}\nContent:\n{}\n\n",
-                    document_id, text
-                ));
-            }
-        }
-
-        if context.is_empty() {
-            context.push_str("No relevant documents found.\n\n");
-        }
-        // Continue with any further processing that requires the context
-
-        // Send the documents to the OpenAI model for relevance filtering
-        let relevance_system_prompt = format!(
-            "The user's prompt is: {}\n\n. You are an intelligent and logical personal assistant. Your task is to carefully review the content of provided documents and output solely a maximum of four numerical IDs of the documents that are directly related to the user prompt and are highly likely to help in answering the user's prompt (corresponding to the Document ID at the beginning of each document). If an individual document is not extremely relevant to the user prompt and the user prompt can be successfully answered without that document, do not include it in the list of returned documents.
-
-            Examples of relevant and irrelevant documents in different business scenarios:
-        
-            Example 1: The user prompt is to outline effective marketing strategies for social media.
-            - Relevant document:
-                Document ID: 55
-                Content: This document details various social media marketing strategies, which is directly relevant to the user's prompt.
-            - Irrelevant document:
-                Document ID: 78
-                Content: This document describes traditional print advertising methods, which is not relevant to social media marketing strategies.
-        
-            Example 2: The user prompt is researching the best programming practices for AI development.
-            - Relevant document:
-                Document ID: 33
-                Content: This document provides best practices for AI development, which is directly relevant to the user's prompt.
-            - Irrelevant document:
-                Document ID: 47
-                Content: This document discusses basic HTML and CSS programming, which is not relevant to the user's prompt about AI development.
-        
-            Example 3: The user prompt asks for recommended books on investment strategies.
-            - Relevant documents:
-                Document ID: 17
-                Content: This document lists top-rated books on investment strategies, highly relevant to the user's prompt.
-                Document ID: 106
-                Content: This document summarizes famous investment strategies, which is also relevant to the user's prompt.
-                Document ID: 204
-                Content: This document contains interviews with successful investors discussing their strategies, directly relevant to the user's prompt.
-                Document ID: 345
-                Content: This document reviews recent books on future investment trends, relevant to the user's prompt.
-            - Irrelevant document:
-                Document ID: 88
-                Content: This document covers general finance tips, which may not be directly relevant to specific investment strategies.
-        
-            Example 4: The user prompt is to find best practices for remote team management.
-            - Relevant document:
-                Document ID: 99
-                Content: This document covers best practices for managing remote teams, directly relevant to the user's prompt.
-            - Irrelevant document:
-                Document ID: 65
-                Content: This document discusses in-office team-building activities, which are not relevant to managing remote teams.
-        
-            Example 5: The user prompt is about analyzing the latest trends in cybersecurity.
-            - Relevant documents:
-                Document ID: 120
-                Content: This document provides a detailed analysis of the latest cybersecurity trends, directly relevant to the user's prompt.
-                Document ID: 150
-                Content: This document includes recent cybersecurity reports and data, relevant to understanding current trends.
-            - Irrelevant document:
-                Document ID: 88
-                Content: This document outlines historical cybersecurity breaches, which may not be directly relevant to analyzing current trends.
-                Document ID: 200
-                Content: This document focuses on outdated cybersecurity practices, which are not relevant to the latest trends.
-        
-            Example 6: The user prompt asks for guidelines on creating an investment portfolio.
-            - Relevant document:
-                Document ID: 300
-                Content: This document provides detailed guidelines on how to create and manage an investment portfolio, highly relevant to the user's prompt.
-            - Irrelevant document:
-                Document ID: 77
-                Content: This document discusses corporate investment strategies, which may not be directly applicable to individual investment portfolios.
-        
-            Example 7: The user prompt asks for something not covered by any provided document.
-            - User prompt: Strategies for eco-friendly business operations.
-            - No documents: None of the documents provided contain information about eco-friendly business operations, so no documents should be returned.
-        
-            The user's prompt is: {}\n\nOutput the relevant document IDs as a comma-separated list of numbers only or an empty list, with absolutely no other additional text or explanations. For example: 123,456,789 or an empty list.",
-            user_prompt, user_prompt
-        );
-
-        let relevance_request = CreateChatCompletionRequestArgs::default()
-            .model(MODEL_FAST)
-            .messages([
-                ChatCompletionRequestSystemMessageArgs::default()
-                    .content(relevance_system_prompt)
-                    .build()
-                    .map_err(|e| format!("Failed to build system message: {}", e))?
-                    .into(),
-                ChatCompletionRequestUserMessageArgs::default()
-                    .content(context)
-                    .build()
-                    .map_err(|e| format!("Failed to build user message: {}", e))?
-                    .into(),
-            ])
-            .build()
-            .map_err(|e| format!("Failed to build request: {}", e))?;
-
-        let relevance_response = relevance_client
-            .chat()
-            .create(relevance_request)
-            .await
-            .map_err(|e| format!("Relevance filtering request failed: {}", e))?;
-
-        debug!("Relevance filtering response: {:?}", relevance_response);
-
-        if let Some(relevance_result) = relevance_response.choices.first() {
-            let relevant_document_ids: Vec<i64> = relevance_result
-                .message
-                .content
-                .as_ref()
-                .unwrap_or(&String::new())
-                .split(|c: char| !c.is_numeric())
-                .filter_map(|s| s.parse().ok())
-                .collect();
-
-            debug!("Relevant document IDs: {:?}", relevant_document_ids);
-
-            // Retrieve the full text of the highly relevant documents
-            for document_id in relevant_document_ids {
-                let result: Option<(String, String)> = app_handle
-                    .db(|db| get_activity_full_text_by_id(db, document_id, Some(10000)))
-                    .map_err(|e| format!("Failed to retrieve edited full text: {}", e))?;
-
-                if let Some((window_title, text)) = result {
-                    filtered_context.push_str(&format!(
-                        "Document ID: {}\nContent:\n{}\n\n",
-                        document_id, text
-                    ));
-                    window_titles.push(window_title);
-                }
-            }
-
-            debug!(
-                "Filtered context for final response generation: {}",
-                filtered_context
-            );
-        }
-    }
-
     // Prepare the conversation history for the OpenAI API
     let conversation_history_content = conversation_history
         .iter()
@@ -271,6 +62,45 @@ pub async fn send_prompt_to_openai(
         .collect::<Vec<String>>()
         .join("\n");
 
+    let user_prompt = conversation_history
+        .last()
+        .map(|msg| msg.content.clone())
+        .unwrap_or_default();
+    info!("User_prompt: {}", user_prompt);
+
+    let rewriter = OpenAiQueryRewriter {
+        api_key: setting.setting_value.clone(),
+    };
+    let query = standalone_query(
+        &rewriter,
+        &conversation_history_content,
+        &user_prompt,
+        is_first_message,
+    )
+    .await;
+
+    let mut new_documents = Vec::new();
+    if let Some(query) = &query {
+        let relevant_keywords = identify_relevant_keywords_gpt4(query, &setting.setting_value).await?;
+
+        let reranker = OpenAiRelevanceReranker {
+            api_key: setting.setting_value.clone(),
+        };
+        new_documents = retrieve_relevant_documents(
+            &app_handle,
+            query,
+            &relevant_keywords,
+            &setting.setting_value,
+            &reranker,
+        )
+        .await?;
+    }
+
+    let retrieved =
+        load_conversation_context(&app_handle, chat_id, query.as_deref(), new_documents)?;
+    let filtered_context = retrieved.context;
+    let window_titles = retrieved.window_titles;
+
     let system_prompt = format!(
             "You are Heelix chat app that is powered by OpenAI LLM. Heelix chat is developed by Heelix Technologies. Only identify yourself as such.
 
@@ -363,6 +193,103 @@ pub async fn send_prompt_to_openai(
     Ok(())
 }
 
+/// The relevance filter as a reranking stage, answered by the fast OpenAI model.
+pub struct OpenAiRelevanceReranker {
+    pub api_key: String,
+}
+
+#[async_trait]
+impl Reranker for OpenAiRelevanceReranker {
+    async fn rerank(
+        &self,
+        query: &str,
+        candidates: &[RerankCandidate],
+    ) -> Result<Vec<ScoredDocument>, String> {
+        let relevance_client =
+            OpenAIClient::with_config(OpenAIConfig::new().with_api_key(&self.api_key));
+        let relevance_request = CreateChatCompletionRequestArgs::default()
+            .model(MODEL_FAST)
+            .messages([
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(relevance_system_prompt(query))
+                    .build()
+                    .map_err(|e| format!("Failed to build system message: {}", e))?
+                    .into(),
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content(relevance_context(candidates))
+                    .build()
+                    .map_err(|e| format!("Failed to build user message: {}", e))?
+                    .into(),
+            ])
+            .build()
+            .map_err(|e| format!("Failed to build request: {}", e))?;
+
+        let relevance_response = relevance_client
+            .chat()
+            .create(relevance_request)
+            .await
+            .map_err(|e| format!("Relevance filtering request failed: {}", e))?;
+
+        debug!("Relevance filtering response: {:?}", relevance_response);
+
+        let relevant_documents = relevance_response
+            .choices
+            .first()
+            .and_then(|choice| choice.message.content.as_ref())
+            .map(|content| parse_ranked_ids(content, candidates))
+            .unwrap_or_default();
+
+        debug!("Relevant document IDs: {:?}", relevant_documents);
+        Ok(relevant_documents)
+    }
+}
+
+/// Rewrites follow-ups into standalone search queries with the fast OpenAI model.
+pub struct OpenAiQueryRewriter {
+    pub api_key: String,
+}
+
+#[async_trait]
+impl QueryRewriter for OpenAiQueryRewriter {
+    async fn rewrite(
+        &self,
+        conversation_history: &str,
+        follow_up: &str,
+    ) -> Result<QueryRewrite, String> {
+        let client = OpenAIClient::with_config(OpenAIConfig::new().with_api_key(&self.api_key));
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(MODEL_FAST)
+            .max_tokens(100u32)
+            .messages([
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(query_rewrite_system_prompt())
+                    .build()
+                    .map_err(|e| format!("Failed to build system message: {}", e))?
+                    .into(),
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content(query_rewrite_user_prompt(conversation_history, follow_up))
+                    .build()
+                    .map_err(|e| format!("Failed to build user message: {}", e))?
+                    .into(),
+            ])
+            .build()
+            .map_err(|e| format!("Failed to build request: {}", e))?;
+
+        let response = client
+            .chat()
+            .create(request)
+            .await
+            .map_err(|e| format!("Query rewrite request failed: {}", e))?;
+
+        Ok(response
+            .choices
+            .first()
+            .and_then(|choice| choice.message.content.as_ref())
+            .map(|content| parse_query_rewrite(content))
+            .unwrap_or(QueryRewrite::Skip))
+    }
+}
+
 pub async fn identify_relevant_keywords_gpt4(
     prompt: &str,
     api_key: &str,
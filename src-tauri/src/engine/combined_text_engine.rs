@@ -1,22 +1,129 @@
-extern crate dissimilar;
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+use strsim::normalized_levenshtein;
 
-pub fn merge_texts(left: &str, right: &str) -> String {
-    let chunks = dissimilar::diff(left, right);
-    let mut result = String::new();
+// A changed line at least this similar to the line it replaces is an edit of it, or the same
+// line misread by OCR, and supersedes it
+const EDIT_SIMILARITY: f64 = 0.6;
 
-    for chunk in chunks {
-        match chunk {
-            dissimilar::Chunk::Equal(text) => {
-                result.push_str(text);
-            }
-            dissimilar::Chunk::Delete(text) => {
-                result.push_str(text);
-            }
-            dissimilar::Chunk::Insert(text) => {
-                result.push_str(text);
+/// Adds a new capture of a window to the transcript of what the window showed before.
+///
+/// The lines of both are aligned, so the part of the capture already in the transcript is
+/// kept once, in place. Lines that scrolled into view are added before or after it in reading
+/// order, and edited lines replace their old version. Lines no longer on screen stay in the
+/// transcript, whether they scrolled away or were deleted, and a capture that shares nothing
+/// with the transcript is added after it.
+pub fn stitch_texts(transcript: &str, capture: &str) -> String {
+    let old: Vec<&str> = transcript.lines().collect();
+    let new: Vec<&str> = capture.lines().collect();
+    let old_keys: Vec<String> = old.iter().map(|line| normalize(line)).collect();
+    let new_keys: Vec<String> = new.iter().map(|line| normalize(line)).collect();
+
+    let mut stitched: Vec<&str> = Vec::with_capacity(old.len() + new.len());
+    // Patience diff anchors on lines that occur once, so blank lines and repeated
+    // boilerplate do not pull unrelated passages together
+    for op in capture_diff_slices(Algorithm::Patience, &old_keys, &new_keys) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal | DiffTag::Insert => stitched.extend(&new[new_range]),
+            DiffTag::Delete => stitched.extend(&old[old_range]),
+            DiffTag::Replace => {
+                // Old lines are paired with the new ones in order; unpaired or dissimilar
+                // ones are kept ahead of the new lines
+                for (offset, index) in old_range.enumerate() {
+                    let edited = offset < new_range.len()
+                        && is_edit(&old_keys[index], &new_keys[new_range.start + offset]);
+                    if !edited {
+                        stitched.push(old[index]);
+                    }
+                }
+                stitched.extend(&new[new_range]);
             }
         }
     }
+    stitched.join("\n")
+}
+
+fn normalize(line: &str) -> String {
+    line.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn is_edit(old: &str, new: &str) -> bool {
+    !old.is_empty() && !new.is_empty() && normalized_levenshtein(old, new) >= EDIT_SIMILARITY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stitch_texts;
+
+    const DOCUMENT: [&str; 14] = [
+        "Project kickoff notes",
+        "Attendees: Anna, Piotr, Maria",
+        "The launch moves to the second week of March.",
+        "Design review happens every Tuesday.",
+        "Marketing needs final screenshots by Friday.",
+        "Open questions",
+        "Who owns the migration of the billing data?",
+        "Do we keep the legacy export format?",
+        "Action items",
+        "Anna drafts the rollout plan.",
+        "Piotr checks the database load.",
+        "Maria books the customer interviews.",
+        "Next meeting",
+        "Thursday at ten in the small room.",
+    ];
+
+    fn lines(range: std::ops::Range<usize>) -> String {
+        DOCUMENT[range].join("\n")
+    }
 
-    result
-}
\ No newline at end of file
+    #[test]
+    fn scrolling_down_appends_only_the_new_lines() {
+        let transcript = lines(0..8);
+        // OCR misreads a line it already read correctly
+        let capture = lines(4..12).replace("Open questions", "0pen questi0ns");
+        let transcript = stitch_texts(&transcript, &capture);
+        assert_eq!(
+            transcript,
+            lines(0..12).replace("Open questions", "0pen questi0ns")
+        );
+
+        let transcript = stitch_texts(&transcript, &lines(8..14));
+        assert_eq!(
+            transcript,
+            lines(0..14).replace("Open questions", "0pen questi0ns")
+        );
+    }
+
+    #[test]
+    fn scrolling_up_adds_the_new_lines_before() {
+        let transcript = stitch_texts(&lines(7..14), &lines(3..10));
+        assert_eq!(transcript, lines(3..14));
+        assert_eq!(stitch_texts(&transcript, &lines(0..5)), lines(0..14));
+    }
+
+    #[test]
+    fn edits_in_place_replace_the_old_lines() {
+        let transcript = lines(0..10);
+        let mut edited: Vec<&str> = DOCUMENT[0..10].to_vec();
+        edited[2] = "The launch moves to the third week of March.";
+        edited.insert(8, "Who signs off on the pricing page?");
+        let capture = edited.join("\n");
+        assert_eq!(stitch_texts(&transcript, &capture), capture);
+
+        // Capturing the same screen again changes nothing
+        assert_eq!(stitch_texts(&capture, &capture), capture);
+    }
+
+    #[test]
+    fn unrelated_content_is_added_after() {
+        let other = "Inbox\nRe: quarterly budget\nLunch on Friday?";
+        assert_eq!(
+            stitch_texts(&lines(0..3), other),
+            format!("{}\n{}", lines(0..3), other)
+        );
+        assert_eq!(stitch_texts("", other), other);
+    }
+}
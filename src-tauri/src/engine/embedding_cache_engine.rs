@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use rusqlite::Connection;
+use serde_derive::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::repository::embedding_cache_repository::{
+    count_cached_embeddings, evict_least_recently_used_embeddings, get_cached_embedding,
+    save_cached_embedding,
+};
+
+pub const MAX_CACHED_EMBEDDINGS: usize = 50_000;
+
+lazy_static! {
+    static ref EMBEDDING_CACHE: Mutex<Option<Connection>> = Mutex::new(None);
+}
+
+static CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EmbeddingCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: i64,
+    pub max_entries: usize,
+}
+
+/// Opens a dedicated connection to the application database for the embedding cache, so
+/// lookups never contend with the `AppState` connection used by commands.
+pub fn init_embedding_cache(sqlite_path: &Path) -> Result<(), rusqlite::Error> {
+    let db = Connection::open(sqlite_path)?;
+    db.busy_timeout(Duration::from_secs(5))?;
+    *EMBEDDING_CACHE.lock().unwrap() = Some(db);
+    info!("Embedding cache initialized at {}", sqlite_path.display());
+    Ok(())
+}
+
+/// Collapses whitespace so that re-captured text differing only in line breaks or
+/// indentation maps to the same cache entry.
+pub fn normalize_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(normalize_text(text).as_bytes()))
+}
+
+pub fn lookup(model: &str, text: &str) -> Option<Vec<f32>> {
+    let guard = EMBEDDING_CACHE.lock().unwrap();
+    let db = guard.as_ref()?;
+    match get_cached_embedding(db, model, &content_hash(text)) {
+        Ok(Some(vector)) => {
+            CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+            Some(vector)
+        }
+        Ok(None) => {
+            CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+        Err(e) => {
+            error!("Failed to read embedding cache: {}", e);
+            CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+pub fn store(model: &str, text: &str, vector: &[f32]) {
+    let guard = EMBEDDING_CACHE.lock().unwrap();
+    let db = match guard.as_ref() {
+        Some(db) => db,
+        None => return,
+    };
+    if let Err(e) = save_cached_embedding(db, model, &content_hash(text), vector) {
+        error!("Failed to write embedding cache: {}", e);
+        return;
+    }
+    match evict_least_recently_used_embeddings(db, MAX_CACHED_EMBEDDINGS) {
+        Ok(0) => {}
+        Ok(evicted) => info!("Evicted {} entries from the embedding cache", evicted),
+        Err(e) => error!("Failed to evict embedding cache entries: {}", e),
+    }
+}
+
+pub fn stats() -> EmbeddingCacheStats {
+    let entries = EMBEDDING_CACHE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|db| count_cached_embeddings(db).ok())
+        .unwrap_or(0);
+    EmbeddingCacheStats {
+        hits: CACHE_HITS.load(Ordering::Relaxed),
+        misses: CACHE_MISSES.load(Ordering::Relaxed),
+        entries,
+        max_entries: MAX_CACHED_EMBEDDINGS,
+    }
+}
@@ -0,0 +1,490 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use futures::FutureExt;
+use lazy_static::lazy_static;
+use log::{debug, error, info};
+use rusqlite::Connection;
+use serde_derive::Serialize;
+use tauri::AppHandle;
+use tokio::sync::Notify;
+
+use crate::configuration::state::ServiceAccess;
+use crate::engine::similarity_search_engine::{panic_message, ApiEmbedder, Embedder};
+use crate::engine::vector_index_engine::is_rebuild_in_progress;
+use crate::engine::vector_store_engine::{
+    open_vector_store, vector_store_backend, VectorStore, VectorStoreBackend,
+};
+use crate::repository::activity_log_repository::amplify_document_text;
+use crate::repository::pending_embedding_repository::{
+    count_pending_embeddings, delete_pending_embeddings, enqueue_pending_embedding,
+    get_due_pending_embeddings, mark_pending_embedding_failed, PendingEmbedding,
+};
+use crate::repository::settings_repository::get_setting_value;
+use crate::repository::vector_db_repository::EMBEDDING_MODEL;
+use crate::repository::vector_store_repository::get_document_ids_without_vector;
+
+/// Documents sent to the embeddings API per request.
+pub const EMBEDDING_BATCH_SIZE: usize = 32;
+// The worker also wakes up on its own, so retries come due without new captures
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const RETRY_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60 * 60);
+const RESTART_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+// The worker gives up after this many panics in a row that did not stay up for
+// MAX_RESTART_BACKOFF
+const MAX_CONSECUTIVE_RESTARTS: u32 = 5;
+
+static WORKER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref QUEUE_CHANGED: Notify = Notify::new();
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EmbeddingQueueStatus {
+    pub pending: i64,
+    pub due: i64,
+    pub failing: i64,
+    pub oldest_enqueued_at: Option<String>,
+    pub last_error: Option<String>,
+    pub api_key_configured: bool,
+    pub worker_running: bool,
+}
+
+/// Queues a saved document for embedding and wakes the worker. The document is indexed
+/// once the embeddings API is reachable, however long that takes.
+pub fn enqueue_document(db: &Connection, document_id: i64) -> Result<(), rusqlite::Error> {
+    enqueue_pending_embedding(db, document_id)?;
+    notify_embedding_queue();
+    Ok(())
+}
+
+/// Asks the worker to look at the queue now, for example after the API key changed.
+pub fn notify_embedding_queue() {
+    QUEUE_CHANGED.notify_one();
+}
+
+/// Starts the background worker that drains `pending_embeddings` into the vector index.
+pub fn start_embedding_worker(app_handle: AppHandle) {
+    if WORKER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        let _running = WorkerRunning;
+        info!("Embedding queue worker started");
+        supervise_worker(&app_handle).await;
+    });
+}
+
+/// Clears `WORKER_RUNNING` however the worker task ends, so the status does not report a
+/// worker that is gone.
+struct WorkerRunning;
+
+impl Drop for WorkerRunning {
+    fn drop(&mut self) {
+        WORKER_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Drains the queue whenever it changes and restarts draining after a panic, backing off
+/// while the panics keep coming. The documents being embedded stay queued.
+async fn supervise_worker(app_handle: &AppHandle) {
+    let mut consecutive_restarts = 0;
+    let mut backoff = RESTART_BACKOFF;
+    loop {
+        let started = Instant::now();
+        let panic = match AssertUnwindSafe(drain_queue(app_handle)).catch_unwind().await {
+            Ok(()) => {
+                let _ = tokio::time::timeout(QUEUE_POLL_INTERVAL, QUEUE_CHANGED.notified()).await;
+                continue;
+            }
+            Err(panic) => panic_message(panic.as_ref()),
+        };
+        if started.elapsed() >= MAX_RESTART_BACKOFF {
+            consecutive_restarts = 0;
+            backoff = RESTART_BACKOFF;
+        }
+        consecutive_restarts += 1;
+        error!("Embedding queue worker panicked: {}", panic);
+        if consecutive_restarts > MAX_CONSECUTIVE_RESTARTS {
+            error!(
+                "Giving up on the embedding queue worker after {} restarts in a row",
+                MAX_CONSECUTIVE_RESTARTS
+            );
+            return;
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+    }
+}
+
+#[tauri::command]
+pub fn embedding_queue_status(app_handle: AppHandle) -> Result<EmbeddingQueueStatus, String> {
+    let counts = app_handle
+        .db(|db| count_pending_embeddings(db, now_millis()))
+        .map_err(|e| e.to_string())?;
+    Ok(EmbeddingQueueStatus {
+        pending: counts.pending,
+        due: counts.due,
+        failing: counts.failing,
+        oldest_enqueued_at: counts.oldest_enqueued_at,
+        last_error: counts.last_error,
+        api_key_configured: !read_api_key(&app_handle).is_empty(),
+        worker_running: WORKER_RUNNING.load(Ordering::SeqCst),
+    })
+}
+
+async fn drain_queue(app_handle: &AppHandle) {
+    enqueue_documents_without_vector(app_handle);
+    loop {
+        // A rebuild swaps in a new collection, which would drop what the queue adds
+        // meanwhile. The rebuild wakes the worker again when it is done
+        if is_rebuild_in_progress() && vector_store_backend(app_handle) == VectorStoreBackend::Hnsw
+        {
+            debug!("Vector index rebuild running, leaving the embedding queue for later");
+            return;
+        }
+
+        // Without a key every attempt would fail, so wait for one instead of backing off
+        let api_key = read_api_key(app_handle);
+        if api_key.is_empty() {
+            debug!("No OpenAI API key, leaving the embedding queue for later");
+            return;
+        }
+
+        let batch = match app_handle
+            .db(|db| get_due_pending_embeddings(db, now_millis(), EMBEDDING_BATCH_SIZE))
+        {
+            Ok(batch) => batch,
+            Err(e) => {
+                error!("Failed to read the embedding queue: {}", e);
+                return;
+            }
+        };
+        if batch.is_empty() {
+            return;
+        }
+
+        let failures = match embed_batch(app_handle, &batch, &api_key).await {
+            Ok(failures) => failures,
+            Err(error) => batch
+                .iter()
+                .map(|pending| (pending.document_id, error.clone()))
+                .collect(),
+        };
+        if failures.is_empty() {
+            continue;
+        }
+        error!(
+            "Failed to embed {} of {} queued documents: {}",
+            failures.len(),
+            batch.len(),
+            failures[0].1
+        );
+        reschedule_failures(app_handle, &batch, &failures);
+        // When the whole batch failed the API is likely unreachable, so stop until the
+        // retries come due
+        if failures.len() == batch.len() {
+            return;
+        }
+    }
+}
+
+/// Embeds and indexes a batch, returning the documents that failed with their error. The
+/// other documents leave the queue.
+async fn embed_batch(
+    app_handle: &AppHandle,
+    batch: &[PendingEmbedding],
+    api_key: &str,
+) -> Result<Vec<(i64, String)>, String> {
+    let documents = document_texts(batch);
+    let store = open_vector_store(app_handle).await?;
+    let embedder = ApiEmbedder {
+        api_key: api_key.to_string(),
+    };
+    let failures = index_documents(store.as_ref(), &documents, &embedder).await;
+
+    let ids: Vec<i64> = documents
+        .iter()
+        .map(|(id, _)| *id)
+        .filter(|id| !failures.iter().any(|(failed_id, _)| failed_id == id))
+        .collect();
+    app_handle
+        .db(|db| delete_pending_embeddings(db, &ids))
+        .map_err(|e| e.to_string())?;
+    info!("Embedded {} queued documents", ids.len());
+    Ok(failures)
+}
+
+/// The text embedded for each queued document.
+fn document_texts(batch: &[PendingEmbedding]) -> Vec<(i64, String)> {
+    batch
+        .iter()
+        .map(|pending| {
+            (
+                pending.document_id,
+                amplify_document_text(&pending.window_title, &pending.full_text),
+            )
+        })
+        .collect()
+}
+
+fn reschedule_failures(
+    app_handle: &AppHandle,
+    batch: &[PendingEmbedding],
+    failures: &[(i64, String)],
+) {
+    let now = now_millis();
+    app_handle.db(|db| {
+        for pending in batch {
+            let error = match failures.iter().find(|(id, _)| *id == pending.document_id) {
+                Some((_, error)) => error,
+                None => continue,
+            };
+            let next_attempt_at = now + retry_backoff(pending.attempts).as_millis() as i64;
+            if let Err(e) =
+                mark_pending_embedding_failed(db, pending.document_id, next_attempt_at, error)
+            {
+                error!(
+                    "Failed to reschedule embedding of document {}: {}",
+                    pending.document_id, e
+                );
+            }
+        }
+    });
+}
+
+/// Embeds `(id, text)` documents and adds them to the store, returning the documents that
+/// failed with their error. They are embedded with one request; when that request fails
+/// each document is retried alone, so one bad document does not fail the others.
+pub async fn index_documents(
+    store: &dyn VectorStore,
+    documents: &[(i64, String)],
+    embedder: &dyn Embedder,
+) -> Vec<(i64, String)> {
+    let texts: Vec<String> = documents.iter().map(|(_, text)| text.clone()).collect();
+    // Embed before writing so a slow embeddings API does not block searches
+    let embedded = match embedder.embed_texts(&texts).await {
+        Ok(vectors) if vectors.len() == texts.len() => Ok(vectors),
+        Ok(vectors) => Err(format!(
+            "Expected {} embeddings, received {}",
+            texts.len(),
+            vectors.len()
+        )),
+        Err(e) => Err(e.to_string()),
+    };
+    let vectors: Vec<Result<Vec<f32>, String>> = match embedded {
+        Ok(vectors) => vectors.into_iter().map(Ok).collect(),
+        Err(e) if documents.len() == 1 => vec![Err(e)],
+        Err(_) => {
+            let mut vectors = Vec::with_capacity(texts.len());
+            for text in &texts {
+                vectors.push(match embedder.embed_texts(&[text.clone()]).await {
+                    Ok(mut vector) if vector.len() == 1 => Ok(vector.remove(0)),
+                    Ok(_) => Err("The embeddings API returned no vector".to_string()),
+                    Err(e) => Err(e.to_string()),
+                });
+            }
+            vectors
+        }
+    };
+
+    let mut failures = Vec::new();
+    let mut added = false;
+    for ((id, _), vector) in documents.iter().zip(vectors) {
+        let result = match vector {
+            Ok(vector) => store.add(*id, vector).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => added = true,
+            Err(e) => failures.push((*id, e)),
+        }
+    }
+    // Added vectors are already durable in the store's log, so a failed save is retried by
+    // the next one instead of embedding the documents again
+    if added {
+        if let Err(e) = store.persist().await {
+            error!("Failed to save the vector store: {}", e);
+        }
+    }
+    failures
+}
+
+/// The SQLite store starts empty, so after switching to it every document it lacks is
+/// queued; the HNSW index is filled by its own rebuild instead.
+fn enqueue_documents_without_vector(app_handle: &AppHandle) {
+    if vector_store_backend(app_handle) == VectorStoreBackend::Hnsw {
+        return;
+    }
+    let result = app_handle.db(|db| {
+        for document_id in get_document_ids_without_vector(db, EMBEDDING_MODEL)? {
+            enqueue_pending_embedding(db, document_id)?;
+        }
+        Ok::<(), rusqlite::Error>(())
+    });
+    if let Err(e) = result {
+        error!(
+            "Failed to queue documents missing from the vector store: {}",
+            e
+        );
+    }
+}
+
+/// Delay before the next attempt of a document that already failed `attempts` times.
+fn retry_backoff(attempts: i64) -> Duration {
+    let exponent = attempts.clamp(0, 16) as u32;
+    RETRY_BACKOFF
+        .checked_mul(2u32.pow(exponent))
+        .unwrap_or(MAX_RETRY_BACKOFF)
+        .min(MAX_RETRY_BACKOFF)
+}
+
+fn read_api_key(app_handle: &AppHandle) -> String {
+    app_handle.db(|db| get_setting_value(db, "api_key_open_ai"))
+}
+
+fn now_millis() -> i64 {
+    Local::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Result};
+    use async_trait::async_trait;
+    use rusqlite::params;
+
+    use super::{
+        document_texts, index_documents, retry_backoff, MAX_RETRY_BACKOFF, RETRY_BACKOFF,
+    };
+    use crate::configuration::database::open_test_database;
+    use crate::engine::similarity_search_engine::{deterministic_embedding, Embedder};
+    use crate::engine::vector_store_engine::{SqliteSearchMode, SqliteVectorStore, VectorStore};
+    use crate::repository::pending_embedding_repository::{
+        count_pending_embeddings, delete_pending_embeddings, enqueue_pending_embedding,
+        get_due_pending_embeddings, mark_pending_embedding_failed,
+    };
+
+    /// Rejects every request holding a corrupt document, as the API does for invalid input.
+    struct RejectingEmbedder;
+
+    #[async_trait]
+    impl Embedder for RejectingEmbedder {
+        async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            if texts.iter().any(|text| text.contains("corrupt")) {
+                return Err(anyhow!("invalid input"));
+            }
+            Ok(texts
+                .iter()
+                .map(|text| deterministic_embedding(text))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(retry_backoff(0), RETRY_BACKOFF);
+        assert_eq!(retry_backoff(1), RETRY_BACKOFF * 2);
+        assert_eq!(retry_backoff(3), RETRY_BACKOFF * 8);
+        assert_eq!(retry_backoff(12), MAX_RETRY_BACKOFF);
+        assert_eq!(retry_backoff(1000), MAX_RETRY_BACKOFF);
+        assert!(retry_backoff(6) < Duration::from_secs(60 * 60));
+    }
+
+    #[test]
+    fn queue_retries_failed_documents_after_backoff() -> Result<()> {
+        let test_database = open_test_database()?;
+        let db = &test_database.db;
+        for id in 1..=3 {
+            db.execute(
+                "INSERT INTO activity_full_text (id, window_title, edited_full_text)
+                 VALUES (?1, ?2, 'text')",
+                params![id, format!("Document {}", id)],
+            )?;
+            enqueue_pending_embedding(db, id)?;
+        }
+        // Queuing twice keeps a single entry
+        enqueue_pending_embedding(db, 1)?;
+        // Deleted documents leave the queue
+        db.execute(
+            "UPDATE activity_full_text SET window_title = '' WHERE id = 3",
+            [],
+        )?;
+
+        let due = get_due_pending_embeddings(db, 0, 10)?;
+        assert_eq!(
+            due.iter()
+                .map(|pending| pending.document_id)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(due[0].window_title, "Document 1");
+
+        mark_pending_embedding_failed(db, 1, 1_000, "network is down")?;
+        delete_pending_embeddings(db, &[2])?;
+        assert!(get_due_pending_embeddings(db, 999, 10)?.is_empty());
+        let retried = get_due_pending_embeddings(db, 1_000, 10)?;
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].attempts, 1);
+
+        let counts = count_pending_embeddings(db, 999)?;
+        assert_eq!(counts.pending, 1);
+        assert_eq!(counts.due, 0);
+        assert_eq!(counts.failing, 1);
+        assert_eq!(counts.last_error.as_deref(), Some("network is down"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn one_bad_document_does_not_fail_its_batch() -> Result<()> {
+        let test_database = open_test_database()?;
+        let documents: Vec<(i64, String)> = ["meeting notes", "corrupt", "release plan"]
+            .iter()
+            .enumerate()
+            .map(|(index, text)| (index as i64 + 1, text.to_string()))
+            .collect();
+        for (id, text) in &documents {
+            test_database.db.execute(
+                "INSERT INTO activity_full_text (id, original_full_text) VALUES (?1, ?2)",
+                params![id, text],
+            )?;
+        }
+        let store =
+            SqliteVectorStore::open(&test_database.path, "test-model", SqliteSearchMode::Exact)?;
+
+        let failures = index_documents(&store, &documents, &RejectingEmbedder).await;
+        assert_eq!(failures, vec![(2, "invalid input".to_string())]);
+        assert_eq!(store.stats().await?.vector_count, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn long_non_ascii_documents_are_embedded() -> Result<()> {
+        let test_database = open_test_database()?;
+        let db = &test_database.db;
+        // Multi-byte characters straddle the byte offsets where the text used to be cut
+        let text = "Zażółć gęślą jaźń 🚀 ".repeat(400);
+        assert!(text.len() > 5000);
+        db.execute(
+            "INSERT INTO activity_full_text (id, window_title, edited_full_text)
+             VALUES (1, 'Notatki', ?1)",
+            params![text],
+        )?;
+        enqueue_pending_embedding(db, 1)?;
+        let store =
+            SqliteVectorStore::open(&test_database.path, "test-model", SqliteSearchMode::Exact)?;
+
+        let documents = document_texts(&get_due_pending_embeddings(db, 0, 10)?);
+        assert_eq!(documents.len(), 1);
+        let failures = index_documents(&store, &documents, &RejectingEmbedder).await;
+        assert!(failures.is_empty());
+        assert_eq!(store.stats().await?.vector_count, 1);
+        Ok(())
+    }
+}
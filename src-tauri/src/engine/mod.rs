@@ -1,8 +1,26 @@
 pub mod monitoring_engine;
+pub mod monitoring_scheduler_engine;
 pub mod text_recognition_engine;
 pub mod os_details_engine;
 pub mod combined_text_engine;
 pub mod chat_engine;
 pub mod similarity_search_engine;
-pub mod clean_up_engine;
-pub mod chat_engine_openai;
\ No newline at end of file
+pub mod chat_engine_openai;
+pub mod embedding_cache_engine;
+pub mod vector_index_engine;
+pub mod reranking_engine;
+pub mod retrieval_engine;
+pub mod query_rewriting_engine;
+pub mod vector_store_engine;
+pub mod embedding_queue_engine;
+pub mod near_duplicate_engine;
+pub mod perceptual_hash_engine;
+pub mod ocr_layout_engine;
+pub mod ocr_preprocessing_engine;
+pub mod tesseract_ocr_engine;
+pub mod pure_rust_ocr_engine;
+pub mod screenshot_retention_engine;
+pub mod relevance_scoring_engine;
+pub mod app_rules_engine;
+#[cfg(test)]
+mod retrieval_evaluation;
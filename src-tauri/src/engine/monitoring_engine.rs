@@ -1,11 +1,21 @@
 use crate::configuration::state::ServiceAccess;
+use crate::engine::app_rules_engine::{AppRules, EditingMode, TextSource};
 use crate::engine::combined_text_engine;
 use crate::engine::os_details_engine::get_os_and_version;
-use crate::engine::text_recognition_engine;
+use crate::engine::perceptual_hash_engine::{
+    change_threshold_from_setting, is_unchanged, PerceptualHash,
+    SCREENSHOT_CHANGE_THRESHOLD_SETTING,
+};
+use crate::engine::screenshot_retention_engine::{
+    enforce_retention, read_retention_policy, save_screenshot, screenshots_dir,
+};
+use crate::engine::text_recognition_engine::{
+    self, OcrOptions, RecognizedText, OCR_ENGINE_SETTING, OCR_LANGUAGES_SETTING,
+    OCR_MIN_CONFIDENCE_SETTING, OCR_PAGE_SEGMENTATION_MODE_SETTING,
+};
 use chrono::Local;
 use log::info;
 use std::path::Path;
-use std::path::PathBuf;
 use std::time::Instant;
 use strsim::normalized_levenshtein;
 use tauri::AppHandle;
@@ -18,9 +28,22 @@ use crate::repository::activity_log_repository::{
 };
 //use crate::repository::keypress_log_repository::{clean_older_keypress_logs, get_count_of_keypress_on_last_interval};
 use crate::repository::permissions_repository::get_permission_by_app_name;
+use crate::repository::settings_repository::get_setting_value;
 use crate::window_details_collector::window_details_collector::get_element_tree_by_window_app_name;
 
-pub async fn start_a_monitoring_cycle(handle: AppHandle, app_data_dir: &str) -> ActivityItem {
+// An unchanged screen is recognised again after this long, as the hash can miss small edits
+const MAX_UNCHANGED_DWELL_SECONDS: u32 = 5 * 60;
+
+pub enum MonitoringCycle {
+    Captured(ActivityItem),
+    /// The window looks as it did at its last capture, so recognition was skipped.
+    Unchanged {
+        window_title: String,
+        window_app_name: String,
+    },
+}
+
+pub async fn start_a_monitoring_cycle(handle: AppHandle, app_data_dir: &str) -> MonitoringCycle {
     let start = Instant::now();
     info!("A cycle of monitoring started at {:?}!", start);
     let mut active_window = active_windows::get_active_window();
@@ -30,46 +53,87 @@ pub async fn start_a_monitoring_cycle(handle: AppHandle, app_data_dir: &str) ->
         .db(|db| get_permission_by_app_name(&db, &active_window.app_name).unwrap())
         .allow;
     if !is_app_allowed {
-        return get_empty_activity_item();
+        return MonitoringCycle::Captured(get_empty_activity_item());
     }
     let (element_tree_dump, detected_actions): (String, String) =
         get_element_tree_by_window_app_name(&active_window.process_id.to_string());
-    let is_editing_mode = active_window.title != "ChatGPT" && active_window.title != "Claude";
-    // If the app_name is Safari or Chrome and the window_title is missing, assign the first text up to blank character in element_tree_dump to window_title
-    if ((active_window.app_name == "Safari" || active_window.app_name == "Google Chrome")
-        && active_window.title.is_empty())
-        || (active_window.title == "ChatGPT" || active_window.title == "Claude")
+    let behaviour = AppRules::cached(Path::new(app_data_dir))
+        .behaviour_for(&active_window.app_name, &active_window.title);
+    if behaviour.skips(&element_tree_dump) {
+        return MonitoringCycle::Captured(get_empty_activity_item());
+    }
+    let (window_title, window_app_name) = behaviour.identify(
+        &active_window.title,
+        &active_window.app_name,
+        &element_tree_dump,
+    );
+    active_window.title = window_title;
+    active_window.app_name = window_app_name;
+    let timestamp = Local::now();
+    let screenshot = take_screenshot::take_screenshot(&active_window.position);
+
+    let recent_activity_item_option = handle
+        .db(|database| {
+            get_latest_activity_log_item_with_same_window(
+                database,
+                &active_window.title,
+                &active_window.app_name,
+            )
+        })
+        .unwrap();
+
+    let screenshot_hash = screenshot.as_ref().map(PerceptualHash::of_image);
+    if let (Some(hash), Some(recent_activity_item)) =
+        (&screenshot_hash, &recent_activity_item_option)
     {
-        if let Some(first_text) = element_tree_dump.split_whitespace().next() {
-            active_window.title = first_text.to_string();
-        }
-    } else {
-        let first_20_chars = element_tree_dump.chars().take(20).collect::<String>();
-        for website in POPULAR_WEBSITES {
-            if first_20_chars.contains(website) {
-                active_window.app_name = website.to_string();
-                break;
-            }
+        let threshold = change_threshold_from_setting(
+            &handle.db(|db| get_setting_value(db, SCREENSHOT_CHANGE_THRESHOLD_SETTING)),
+        );
+        if recent_activity_item.interval_length < MAX_UNCHANGED_DWELL_SECONDS
+            && is_unchanged(
+                recent_activity_item.screenshot_hash.as_deref(),
+                hash,
+                threshold,
+            )
+        {
+            info!("Screen unchanged, skipping OCR after {:?}", start.elapsed());
+            return MonitoringCycle::Unchanged {
+                window_title: active_window.title,
+                window_app_name: active_window.app_name,
+            };
         }
     }
-    let screenshot_path = PathBuf::from(app_data_dir)
-        .join("task-mining-resources")
-        .join("screenshots");
 
-    info!("SCREENSHOTS_PATH: {}", screenshot_path.clone().display());
-    let timestamp = Local::now();
-    take_screenshot::take_screenshot(screenshot_path.clone(), timestamp);
+    if let Some(screenshot) = &screenshot {
+        let screenshots_dir = screenshots_dir(Path::new(app_data_dir));
+        let policy = read_retention_policy(&handle);
+        if save_screenshot(&screenshots_dir, timestamp, screenshot, &policy).is_some() {
+            enforce_retention(&screenshots_dir, policy.retention);
+        }
+    }
 
-    let mut ocr_text = text_recognition_engine::get_text_from_image(Path::new(
-        format!(
-            "{}.png",
-            screenshot_path
-                .clone()
-                .join(timestamp.format("%Y-%m-%d_%H-%M-%S").to_string())
-                .display()
+    let ocr_options = handle.db(|db| {
+        let read = |key: &str| get_setting_value(db, key);
+        OcrOptions::from_settings(
+            &read(OCR_LANGUAGES_SETTING),
+            &read(OCR_PAGE_SEGMENTATION_MODE_SETTING),
+            &read(OCR_MIN_CONFIDENCE_SETTING),
+            &read(OCR_ENGINE_SETTING),
         )
-        .as_str(),
-    ));
+    });
+    let resource_dir = handle.path_resolver().resource_dir().unwrap_or_default();
+    let recognized_text = match &screenshot {
+        Some(screenshot) => {
+            text_recognition_engine::get_text_from_image(screenshot, &ocr_options, &resource_dir)
+        }
+        None => RecognizedText::default(),
+    };
+    let ocr_word_boxes = if recognized_text.words.is_empty() {
+        None
+    } else {
+        serde_json::to_string(&recognized_text.words).ok()
+    };
+    let mut ocr_text = recognized_text.text;
 
     let activity_log_item = handle
         .db(|database| {
@@ -88,61 +152,32 @@ pub async fn start_a_monitoring_cycle(handle: AppHandle, app_data_dir: &str) ->
     //     return rows_deleted;
     // }).unwrap();
 
-    let recent_activity_item_option = handle
-        .db(|database| {
-            get_latest_activity_log_item_with_same_window(
-                database,
-                &active_window.title,
-                &active_window.app_name,
-            )
-        })
-        .unwrap();
-
     let combined_text = if let Some(ref recent_activity_item) = recent_activity_item_option {
-        combined_text_engine::merge_texts(&recent_activity_item.full_activity_text, &ocr_text)
+        combined_text_engine::stitch_texts(&recent_activity_item.full_activity_text, &ocr_text)
     } else {
         ocr_text.clone()
     };
-    let combined_text_length = combined_text.len();
-    let fraction_of_combined_text = (combined_text_length as f64 * 0.2) as usize; // Using element_tree dump if combined_text is too short
-
-    let full_activity_text = if active_window.app_name == "Heelix Chat" {
-        "Heelix monitoring engine".to_string()
-    } else if element_tree_dump.len() > fraction_of_combined_text {
+    // The full text always prefers a long enough accessibility text; the rules only decide
+    // which text is compared between captures
+    let accessibility_text_is_long_enough =
+        TextSource::Auto.uses_accessibility_text(element_tree_dump.len(), combined_text.len());
+    let full_activity_text = if let Some(activity_text) = &behaviour.activity_text {
+        activity_text.clone()
+    } else if accessibility_text_is_long_enough {
         element_tree_dump.clone()
     } else {
         combined_text.clone()
     };
-    // Check if the first 100 characters of element_tree_dump contain "Private Browsing" and the app is a browser
-    if element_tree_dump
-        .chars()
-        .take(300)
-        .collect::<String>()
-        .contains("Private Browsing")
-        && (active_window.app_name == "Google Chrome"
-            || active_window.app_name == "Safari"
-            || active_window.app_name == "Chrome"
-            || active_window.app_name == "Firefox")
+    if behaviour
+        .text_source
+        .uses_accessibility_text(element_tree_dump.len(), combined_text.len())
     {
-        return get_empty_activity_item();
-    }
-    // Adjust ocr_text if the app is PyCharm and element_tree_dump is significantly longer than combined_text
-    // Adjust ocr_text based on the app and content
-    if active_window.app_name != "Microsoft PowerPoint"
-        && active_window.app_name != "Heelix Chat"
-        && active_window.app_name != "DataGrip"
-    {
-        if element_tree_dump.len() > fraction_of_combined_text {
-            ocr_text = element_tree_dump.clone(); // Overwrite OCR text with element tree dump
-        }
-        // Otherwise, keep ocr_text as is
-    } else {
-        // For PowerPoint, Heelix, and DataGrip, use ocr_text as is
+        ocr_text = element_tree_dump.clone();
     }
     let score =
         normalized_levenshtein(activity_log_item.ocr_text.as_str(), ocr_text.as_str()) * 100.0;
     // this way we're not comparing the OCR to the element tree. Now something to consider is limit the size of the text passed along to the LLM for analysis for 1000 tokens max, probably enough for classification
-    let editing_mode_str = if !is_editing_mode || active_window.app_name == "Heelix Chat" {
+    let editing_mode_str = if behaviour.editing_mode == EditingMode::Never {
         "false".to_string()
     } else if score > 50.0 {
         "true".to_string()
@@ -166,7 +201,7 @@ pub async fn start_a_monitoring_cycle(handle: AppHandle, app_data_dir: &str) ->
             .unwrap_or_else(|| ocr_text.clone())
     };
 
-    return ActivityItem {
+    return MonitoringCycle::Captured(ActivityItem {
        // id: activity_log_item.id,
         timestamp: timestamp.to_rfc3339(),
         ocr_text,
@@ -182,46 +217,7 @@ pub async fn start_a_monitoring_cycle(handle: AppHandle, app_data_dir: &str) ->
         keypress_count: 0,
         element_tree_dump,
         detected_actions,
-    };
+        screenshot_hash: screenshot_hash.map(|hash| hash.to_hex()),
+        ocr_word_boxes,
+    });
 }
-const POPULAR_WEBSITES: &[&str] = &[
-    "github",
-    "stackoverflow",
-    "developer.mozilla",
-    "medium",
-    "dev.to",
-    "news.ycombinator",
-    "trello",
-    "jira",
-    "atlassian",
-    "analytics.google",
-    "ads.google",
-    "moz",
-    "ahrefs",
-    "semrush",
-    "hubspot",
-    "mailchimp",
-    "salesforce",
-    "zendesk",
-    "intercom",
-    "slack",
-    "hootsuite",
-    "buffer",
-    "sproutsocial",
-    "buzzsumo",
-    "canva",
-    "adobe/creative-cloud",
-    "adobe/photoshop",
-    "adobe/illustrator",
-    "adobe/indesign",
-    "figma",
-    "sketch",
-    "invision",
-    "zeplin",
-    "hotjar",
-    "optimizely",
-    "crazyegg",
-    "unbounce",
-    "leadpages",
-    "clickfunnels",
-];
@@ -0,0 +1,552 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde_derive::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::sync::Notify;
+
+use crate::configuration::state::ServiceAccess;
+use crate::engine::embedding_queue_engine;
+use crate::engine::monitoring_engine::{self, MonitoringCycle};
+use crate::entity::activity_item::ActivityItem;
+use crate::entity::setting::Setting;
+use crate::monitoring::idle_detection::{
+    system_idle_detector, IdleDecision, IdleDetector, IdleTracker,
+};
+use crate::repository::activity_log_repository;
+use crate::repository::idle_period_repository::save_idle_period;
+use crate::repository::settings_repository::{get_setting_value, insert_or_update_setting};
+
+pub const MONITORING_STATUS_EVENT: &str = "monitoring_status";
+pub const ACTIVITY_RECORDED_EVENT: &str = "activity_recorded";
+pub const TRAY_TOGGLE_ITEM: &str = "start_stop_recording";
+const MONITORING_USER_SETTING: &str = "monitoring_user_id";
+pub const IDLE_THRESHOLD_MINUTES_SETTING: &str = "idle_threshold_minutes";
+const DEFAULT_INTERVAL_SECONDS: u64 = 20;
+const DEFAULT_IDLE_THRESHOLD_MINUTES: u64 = 5;
+
+static SCHEDULER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::default());
+    static ref SCHEDULER_CHANGED: Notify = Notify::new();
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitoringState {
+    Stopped,
+    Running,
+    Paused,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MonitoringStatus {
+    pub state: MonitoringState,
+    pub user_id: String,
+    /// Set while paused for a limited time; monitoring resumes on its own at this time.
+    pub paused_until: Option<String>,
+    pub last_cycle_at: Option<String>,
+    pub next_cycle_at: Option<String>,
+    pub cycles: u64,
+    /// Cycles skipped because the user was away or the screen was locked.
+    pub skipped_cycles: u64,
+    pub idle_since: Option<String>,
+}
+
+/// The part of a new activity row the webview lists, without its text and word boxes.
+#[derive(Serialize, Debug, Clone)]
+struct RecordedActivity {
+    timestamp: String,
+    window_title: String,
+    window_app_name: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum SchedulerAction {
+    Capture(String),
+    /// Sleep until the given time, or until the schedule changes when there is none.
+    Wait(Option<DateTime<Local>>),
+}
+
+/// When to capture next. Kept apart from the task that runs the cycles so the transitions
+/// can be tested without an app.
+#[derive(Debug, Clone)]
+struct Scheduler {
+    state: MonitoringState,
+    user_id: String,
+    paused_until: Option<DateTime<Local>>,
+    next_cycle_at: Option<DateTime<Local>>,
+    last_cycle_at: Option<DateTime<Local>>,
+    cycles: u64,
+    skipped_cycles: u64,
+    idle_since: Option<DateTime<Local>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler {
+            state: MonitoringState::Stopped,
+            user_id: String::new(),
+            paused_until: None,
+            next_cycle_at: None,
+            last_cycle_at: None,
+            cycles: 0,
+            skipped_cycles: 0,
+            idle_since: None,
+        }
+    }
+}
+
+impl Scheduler {
+    /// Starts capturing for `user_id`. Starting while running keeps the current schedule,
+    /// so a reloaded webview does not cause an extra capture, and starting while paused
+    /// keeps the pause; only `resume` ends it.
+    fn start(&mut self, user_id: &str) {
+        self.user_id = user_id.to_string();
+        if self.state == MonitoringState::Stopped {
+            self.state = MonitoringState::Running;
+            self.paused_until = None;
+            self.next_cycle_at = None;
+        }
+    }
+
+    /// Pauses for `duration`, or until resumed when there is none.
+    fn pause(&mut self, duration: Option<Duration>, now: DateTime<Local>) -> Result<(), String> {
+        if self.state == MonitoringState::Stopped {
+            return Err("Monitoring has not been started".to_string());
+        }
+        self.state = MonitoringState::Paused;
+        self.paused_until = match duration {
+            Some(duration) => Some(
+                now + chrono::Duration::from_std(duration)
+                    .map_err(|e| format!("Invalid pause duration: {}", e))?,
+            ),
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), String> {
+        if self.user_id.is_empty() {
+            return Err("Monitoring has not been started".to_string());
+        }
+        if self.state != MonitoringState::Running {
+            self.state = MonitoringState::Running;
+            self.paused_until = None;
+            self.next_cycle_at = None;
+        }
+        Ok(())
+    }
+
+    fn next_action(&mut self, now: DateTime<Local>) -> SchedulerAction {
+        if self.state == MonitoringState::Paused {
+            match self.paused_until {
+                Some(paused_until) if paused_until <= now => {
+                    info!("Pause ended, resuming monitoring");
+                    self.state = MonitoringState::Running;
+                    self.paused_until = None;
+                    self.next_cycle_at = None;
+                }
+                paused_until => return SchedulerAction::Wait(paused_until),
+            }
+        }
+        match (self.state, self.next_cycle_at) {
+            (MonitoringState::Running, Some(next_cycle_at)) if next_cycle_at > now => {
+                SchedulerAction::Wait(Some(next_cycle_at))
+            }
+            (MonitoringState::Running, _) => SchedulerAction::Capture(self.user_id.clone()),
+            _ => SchedulerAction::Wait(None),
+        }
+    }
+
+    fn cycle_finished(&mut self, started_at: DateTime<Local>, interval: Duration, captured: bool) {
+        if captured {
+            self.cycles += 1;
+            self.last_cycle_at = Some(started_at);
+        } else {
+            self.skipped_cycles += 1;
+        }
+        if self.state == MonitoringState::Running {
+            self.next_cycle_at = Some(
+                started_at
+                    + chrono::Duration::from_std(interval).unwrap_or_else(|_| {
+                        chrono::Duration::seconds(DEFAULT_INTERVAL_SECONDS as i64)
+                    }),
+            );
+        }
+    }
+
+    fn status(&self) -> MonitoringStatus {
+        MonitoringStatus {
+            state: self.state,
+            user_id: self.user_id.clone(),
+            paused_until: self.paused_until.map(|time| time.to_rfc3339()),
+            last_cycle_at: self.last_cycle_at.map(|time| time.to_rfc3339()),
+            next_cycle_at: match self.state {
+                MonitoringState::Running => self.next_cycle_at.map(|time| time.to_rfc3339()),
+                _ => None,
+            },
+            cycles: self.cycles,
+            skipped_cycles: self.skipped_cycles,
+            idle_since: self.idle_since.map(|time| time.to_rfc3339()),
+        }
+    }
+}
+
+/// Starts the background task that runs a monitoring cycle every `interval` seconds while
+/// monitoring is running, skipping cycles while the user is away. Monitoring starts right
+/// away for the last user who started it.
+pub fn start_monitoring_scheduler(app_handle: AppHandle) {
+    if SCHEDULER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let user_id = app_handle.db(|db| get_setting_value(db, MONITORING_USER_SETTING));
+    if !user_id.is_empty() {
+        update_scheduler(&app_handle, |scheduler| {
+            scheduler.start(&user_id);
+            Ok(())
+        })
+        .ok();
+    }
+
+    let detector: Arc<dyn IdleDetector> = Arc::from(system_idle_detector());
+    tauri::async_runtime::spawn(async move {
+        info!("Monitoring scheduler started");
+        let mut idle_tracker = IdleTracker::new(read_idle_threshold(&app_handle));
+        loop {
+            let action = lock_scheduler().next_action(Local::now());
+            match action {
+                SchedulerAction::Capture(user_id) => {
+                    let started_at = Local::now();
+                    idle_tracker.set_threshold(read_idle_threshold(&app_handle));
+                    // The detectors make blocking D-Bus and X11 calls
+                    let detector = detector.clone();
+                    let idle_state =
+                        tauri::async_runtime::spawn_blocking(move || detector.idle_state())
+                            .await
+                            .unwrap_or_else(|e| Err(e.to_string()));
+                    let captured = match idle_tracker.observe(idle_state, started_at) {
+                        IdleDecision::Capture(gap) => {
+                            if let Some(gap) = gap {
+                                if let Err(e) = app_handle.db(|db| save_idle_period(db, &gap)) {
+                                    error!("Failed to save idle period: {}", e);
+                                }
+                            }
+                            match record_activity(&app_handle, &user_id).await {
+                                Ok(Some(activity_item)) => {
+                                    emit_activity_recorded(&app_handle, &activity_item)
+                                }
+                                Ok(None) => {}
+                                Err(e) => error!("Monitoring cycle failed: {}", e),
+                            }
+                            true
+                        }
+                        IdleDecision::Skip => false,
+                    };
+                    {
+                        let mut scheduler = lock_scheduler();
+                        scheduler.cycle_finished(started_at, read_interval(&app_handle), captured);
+                        scheduler.idle_since = idle_tracker.idle_since();
+                    }
+                    emit_status(&app_handle);
+                }
+                SchedulerAction::Wait(Some(until)) => {
+                    let wait = (until - Local::now()).to_std().unwrap_or_default();
+                    let _ = tokio::time::timeout(wait, SCHEDULER_CHANGED.notified()).await;
+                }
+                SchedulerAction::Wait(None) => SCHEDULER_CHANGED.notified().await,
+            }
+        }
+    });
+}
+
+/// Starts monitoring for `user_id` and remembers the user for the next launch.
+#[tauri::command]
+pub fn start_monitoring(
+    app_handle: AppHandle,
+    user_id: String,
+) -> Result<MonitoringStatus, String> {
+    if user_id.is_empty() {
+        return Err("A user is required to start monitoring".to_string());
+    }
+    app_handle
+        .db(|db| {
+            insert_or_update_setting(
+                db,
+                Setting {
+                    setting_key: String::from(MONITORING_USER_SETTING),
+                    setting_value: user_id.clone(),
+                },
+            )
+        })
+        .map_err(|e| format!("Failed to save the monitoring user: {}", e))?;
+    update_scheduler(&app_handle, |scheduler| {
+        scheduler.start(&user_id);
+        Ok(())
+    })
+}
+
+/// Pauses monitoring for `duration_seconds`, or until resumed when none is given.
+#[tauri::command]
+pub fn pause_monitoring(
+    app_handle: AppHandle,
+    duration_seconds: Option<u64>,
+) -> Result<MonitoringStatus, String> {
+    update_scheduler(&app_handle, |scheduler| {
+        scheduler.pause(duration_seconds.map(Duration::from_secs), Local::now())
+    })
+}
+
+#[tauri::command]
+pub fn resume_monitoring(app_handle: AppHandle) -> Result<MonitoringStatus, String> {
+    update_scheduler(&app_handle, |scheduler| scheduler.resume())
+}
+
+#[tauri::command]
+pub fn monitoring_status() -> MonitoringStatus {
+    lock_scheduler().status()
+}
+
+/// Pauses monitoring when it is running and resumes it otherwise, for the tray.
+pub fn toggle_monitoring(app_handle: &AppHandle) -> Result<MonitoringStatus, String> {
+    update_scheduler(app_handle, |scheduler| match scheduler.state {
+        MonitoringState::Running => scheduler.pause(None, Local::now()),
+        _ => scheduler.resume(),
+    })
+}
+
+/// Runs one monitoring cycle for `user` and saves what it captured. When the window has
+/// not changed, its last capture is credited with the interval instead and nothing is
+/// returned.
+pub async fn record_activity(
+    app_handle: &AppHandle,
+    user: &str,
+) -> Result<Option<ActivityItem>, String> {
+    let app_data_dir = app_handle
+        .path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| "The app data directory is not available".to_string())?;
+    let app_data_dir = app_data_dir
+        .to_str()
+        .ok_or_else(|| format!("The app data directory {:?} is not valid UTF-8", app_data_dir))?;
+    let cycle = monitoring_engine::start_a_monitoring_cycle(app_handle.clone(), app_data_dir).await;
+    let mut activity_item = match cycle {
+        MonitoringCycle::Captured(activity_item) => activity_item,
+        MonitoringCycle::Unchanged {
+            window_title,
+            window_app_name,
+        } => {
+            let seconds = read_interval(app_handle).as_secs() as u32;
+            if let Err(e) = app_handle.db(|db| {
+                activity_log_repository::extend_activity_dwell(
+                    db,
+                    &window_title,
+                    &window_app_name,
+                    seconds,
+                )
+            }) {
+                error!("Failed to extend the dwell time of {}: {}", window_title, e);
+            }
+            return Ok(None);
+        }
+    };
+    activity_item.user_id = String::from(user);
+    activity_item.interval_length = read_interval(app_handle).as_secs() as u32;
+    info!("USER_ID: {}", activity_item.user_id);
+    app_handle
+        .db(|db| activity_log_repository::save_activity_item(&activity_item, db))
+        .map_err(|e| format!("Failed to save activity log: {}", e))?;
+    let last_insert_rowid = app_handle
+        .db(|db| activity_log_repository::save_activity_full_text(&activity_item, db))
+        .map_err(|e| format!("Failed to save activity full text: {}", e))?;
+
+    match last_insert_rowid {
+        Some(rowid) => {
+            info!("Queueing document for embedding, row={}", rowid);
+            if let Err(e) = app_handle.db(|db| embedding_queue_engine::enqueue_document(db, rowid))
+            {
+                error!("Failed to queue document {} for embedding: {}", rowid, e);
+            }
+        }
+        None => info!("No last insert rowid available"),
+    }
+    Ok(Some(activity_item))
+}
+
+fn update_scheduler(
+    app_handle: &AppHandle,
+    update: impl FnOnce(&mut Scheduler) -> Result<(), String>,
+) -> Result<MonitoringStatus, String> {
+    let status = {
+        let mut scheduler = lock_scheduler();
+        update(&mut scheduler)?;
+        scheduler.status()
+    };
+    SCHEDULER_CHANGED.notify_one();
+    emit_status(app_handle);
+    Ok(status)
+}
+
+fn lock_scheduler() -> std::sync::MutexGuard<'static, Scheduler> {
+    // The scheduler holds plain values, so it is still usable after a panic elsewhere
+    SCHEDULER
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn read_interval(app_handle: &AppHandle) -> Duration {
+    let seconds = app_handle
+        .db(|db| get_setting_value(db, "interval"))
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .unwrap_or(DEFAULT_INTERVAL_SECONDS);
+    Duration::from_secs(seconds)
+}
+
+fn read_idle_threshold(app_handle: &AppHandle) -> Duration {
+    let minutes = app_handle
+        .db(|db| get_setting_value(db, IDLE_THRESHOLD_MINUTES_SETTING))
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|minutes| *minutes > 0)
+        .unwrap_or(DEFAULT_IDLE_THRESHOLD_MINUTES);
+    Duration::from_secs(minutes * 60)
+}
+
+fn emit_status(app_handle: &AppHandle) {
+    let status = monitoring_status();
+    let title = match status.state {
+        MonitoringState::Running => "Pause monitoring",
+        _ => "Start monitoring",
+    };
+    if let Err(e) = app_handle
+        .tray_handle()
+        .get_item(TRAY_TOGGLE_ITEM)
+        .set_title(title)
+    {
+        error!("Failed to update the tray menu: {}", e);
+    }
+    if let Some(window) = app_handle.get_window("main") {
+        if let Err(e) = window.emit(MONITORING_STATUS_EVENT, status) {
+            error!("Failed to emit monitoring status: {}", e);
+        }
+    }
+}
+
+/// Sends the webview the row a cycle added, so the activity list can grow without
+/// reloading the day's log.
+fn emit_activity_recorded(app_handle: &AppHandle, activity_item: &ActivityItem) {
+    let recorded = RecordedActivity {
+        timestamp: activity_item.timestamp.clone(),
+        window_title: activity_item.window_title.clone(),
+        window_app_name: activity_item.window_app_name.clone(),
+    };
+    if let Some(window) = app_handle.get_window("main") {
+        if let Err(e) = window.emit(ACTIVITY_RECORDED_EVENT, recorded) {
+            error!("Failed to emit the recorded activity: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{Local, TimeZone};
+
+    use super::{MonitoringState, Scheduler, SchedulerAction};
+
+    #[test]
+    fn running_scheduler_captures_every_interval() {
+        let now = Local.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        let mut scheduler = Scheduler::default();
+        assert_eq!(scheduler.next_action(now), SchedulerAction::Wait(None));
+
+        scheduler.start("user");
+        assert_eq!(
+            scheduler.next_action(now),
+            SchedulerAction::Capture("user".to_string())
+        );
+        scheduler.cycle_finished(now, Duration::from_secs(20), true);
+        let next = now + chrono::Duration::seconds(20);
+        assert_eq!(
+            scheduler.next_action(now),
+            SchedulerAction::Wait(Some(next))
+        );
+
+        // Starting again, as a reloaded webview does, keeps the schedule
+        scheduler.start("user");
+        assert_eq!(
+            scheduler.next_action(now),
+            SchedulerAction::Wait(Some(next))
+        );
+        assert_eq!(
+            scheduler.next_action(next),
+            SchedulerAction::Capture("user".to_string())
+        );
+
+        // A cycle skipped while the user is away still moves the schedule on
+        scheduler.cycle_finished(next, Duration::from_secs(20), false);
+        let status = scheduler.status();
+        assert_eq!((status.cycles, status.skipped_cycles), (1, 1));
+        assert_eq!(
+            scheduler.next_action(next),
+            SchedulerAction::Wait(Some(next + chrono::Duration::seconds(20)))
+        );
+    }
+
+    #[test]
+    fn timed_pause_resumes_on_its_own() {
+        let now = Local.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        let mut scheduler = Scheduler::default();
+        assert!(scheduler.pause(None, now).is_err());
+        assert!(scheduler.resume().is_err());
+
+        scheduler.start("user");
+        scheduler.cycle_finished(now, Duration::from_secs(20), true);
+        scheduler
+            .pause(Some(Duration::from_secs(15 * 60)), now)
+            .unwrap();
+        let paused_until = now + chrono::Duration::minutes(15);
+        assert_eq!(
+            scheduler.next_action(now + chrono::Duration::minutes(1)),
+            SchedulerAction::Wait(Some(paused_until))
+        );
+        assert_eq!(
+            scheduler.next_action(paused_until),
+            SchedulerAction::Capture("user".to_string())
+        );
+        assert_eq!(scheduler.state, MonitoringState::Running);
+    }
+
+    #[test]
+    fn open_pause_waits_for_resume() {
+        let now = Local.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        let mut scheduler = Scheduler::default();
+        scheduler.start("user");
+        scheduler.pause(None, now).unwrap();
+        assert_eq!(
+            scheduler.next_action(now + chrono::Duration::days(1)),
+            SchedulerAction::Wait(None)
+        );
+        assert_eq!(scheduler.status().state, MonitoringState::Paused);
+
+        // Starting again, as a reloaded webview does, does not end the pause
+        scheduler.start("user");
+        assert_eq!(scheduler.status().state, MonitoringState::Paused);
+
+        scheduler.resume().unwrap();
+        assert_eq!(
+            scheduler.next_action(now),
+            SchedulerAction::Capture("user".to_string())
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+/// Documents whose fingerprints agree on at least this fraction of bits are treated as
+/// versions of the same document.
+pub const DEFAULT_NEAR_DUPLICATE_THRESHOLD: f32 = 0.85;
+pub const NEAR_DUPLICATE_THRESHOLD_SETTING: &str = "near_duplicate_threshold";
+
+// Words per shingle; short enough to survive small edits, long enough to keep word order
+const SHINGLE_LENGTH: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentFingerprint {
+    pub id: i64,
+    pub simhash: u64,
+    pub dateofentry: String,
+}
+
+/// 64-bit SimHash over word shingles. Texts that share most of their shingles, such as
+/// the same page captured under two window titles, differ in only a few bits.
+pub fn simhash(text: &str) -> u64 {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    if words.is_empty() {
+        return 0;
+    }
+
+    let mut votes = [0i64; 64];
+    for shingle in words.windows(SHINGLE_LENGTH.min(words.len())) {
+        let hash = fnv1a(shingle.join(" ").as_bytes());
+        for (bit, vote) in votes.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *vote += 1;
+            } else {
+                *vote -= 1;
+            }
+        }
+    }
+    votes
+        .iter()
+        .enumerate()
+        .filter(|(_, vote)| **vote > 0)
+        .fold(0u64, |simhash, (bit, _)| simhash | (1 << bit))
+}
+
+/// Fraction of bits two fingerprints agree on, from 0.0 to 1.0.
+pub fn simhash_similarity(a: u64, b: u64) -> f32 {
+    1.0 - (a ^ b).count_ones() as f32 / 64.0
+}
+
+pub fn near_duplicate_threshold_from_setting(value: &str) -> f32 {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|threshold| (0.0..=1.0).contains(threshold))
+        .unwrap_or(DEFAULT_NEAR_DUPLICATE_THRESHOLD)
+}
+
+/// Groups near-duplicate documents and maps every id to the newest document of its group.
+/// A threshold of 1.0 only groups identical fingerprints.
+pub fn newest_representatives(
+    fingerprints: &[DocumentFingerprint],
+    threshold: f32,
+) -> HashMap<i64, i64> {
+    let mut newest_first: Vec<&DocumentFingerprint> = fingerprints.iter().collect();
+    newest_first.sort_by(|a, b| {
+        b.dateofentry
+            .cmp(&a.dateofentry)
+            .then_with(|| b.id.cmp(&a.id))
+    });
+
+    let mut representatives: Vec<&DocumentFingerprint> = Vec::new();
+    let mut mapping = HashMap::new();
+    for fingerprint in newest_first {
+        let representative = representatives
+            .iter()
+            .find(|kept| simhash_similarity(kept.simhash, fingerprint.simhash) >= threshold);
+        match representative {
+            Some(kept) => {
+                mapping.insert(fingerprint.id, kept.id);
+            }
+            None => {
+                mapping.insert(fingerprint.id, fingerprint.id);
+                representatives.push(fingerprint);
+            }
+        }
+    }
+    mapping
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        near_duplicate_threshold_from_setting, newest_representatives, simhash, simhash_similarity,
+        DocumentFingerprint, DEFAULT_NEAR_DUPLICATE_THRESHOLD,
+    };
+
+    const PAGE: &str = "The quarterly planning meeting covers the revenue forecast, hiring plan \
+        for the platform team, the migration of the billing service to the new cluster and \
+        the schedule for the security review. Action items are assigned to each owner and \
+        reviewed again at the end of the month together with the budget.";
+
+    #[test]
+    fn near_duplicates_are_similar_and_different_texts_are_not() {
+        let edited = PAGE.replace("end of the month", "end of the quarter");
+        let unrelated = "Rust borrow checker lifetimes explained with examples of iterators, \
+            closures and async functions that hold references across await points.";
+
+        let page = simhash(PAGE);
+        assert_eq!(page, simhash(&PAGE.to_uppercase()));
+        assert!(simhash_similarity(page, simhash(&edited)) >= DEFAULT_NEAR_DUPLICATE_THRESHOLD);
+        assert!(simhash_similarity(page, simhash(unrelated)) < DEFAULT_NEAR_DUPLICATE_THRESHOLD);
+    }
+
+    #[test]
+    fn groups_collapse_to_the_newest_document() {
+        let fingerprint = |id: i64, text: &str, dateofentry: &str| DocumentFingerprint {
+            id,
+            simhash: simhash(text),
+            dateofentry: dateofentry.to_string(),
+        };
+        let fingerprints = vec![
+            fingerprint(1, PAGE, "2026-10-01 09:00:00"),
+            fingerprint(2, "an unrelated shopping list", "2026-10-02 09:00:00"),
+            fingerprint(3, &format!("{} Draft", PAGE), "2026-10-03 09:00:00"),
+        ];
+
+        let representatives = newest_representatives(&fingerprints, 0.85);
+        assert_eq!(representatives[&1], 3);
+        assert_eq!(representatives[&2], 2);
+        assert_eq!(representatives[&3], 3);
+
+        let representatives = newest_representatives(&fingerprints, 1.0);
+        assert_eq!(representatives[&1], 1);
+    }
+
+    #[test]
+    fn threshold_setting_falls_back_to_default() {
+        assert_eq!(near_duplicate_threshold_from_setting("0.8"), 0.8);
+        assert_eq!(
+            near_duplicate_threshold_from_setting(""),
+            DEFAULT_NEAR_DUPLICATE_THRESHOLD
+        );
+        assert_eq!(
+            near_duplicate_threshold_from_setting("1.5"),
+            DEFAULT_NEAR_DUPLICATE_THRESHOLD
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Rebuilds the layout of a screenshot from the word boxes Tesseract reports: lines keep
+//! their indentation, paragraphs and blocks are separated by blank lines, and runs of lines
+//! that split into aligned columns become Markdown tables.
+
+use serde_derive::{Deserialize, Serialize};
+
+// Gap between two words, in character widths, that starts a new table cell
+const COLUMN_GAP_CHARS: f32 = 2.5;
+// How far apart, in character widths, cells of one column may start or end
+const COLUMN_ALIGNMENT_CHARS: f32 = 3.0;
+const MIN_TABLE_ROWS: usize = 3;
+const MAX_INDENT: usize = 40;
+const DEFAULT_CHAR_WIDTH: f32 = 10.0;
+
+/// One recognised word with its position, as reported by `image_to_data`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WordBox {
+    pub block: i32,
+    pub par: i32,
+    pub line: i32,
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+    pub conf: f32,
+    pub text: String,
+}
+
+impl WordBox {
+    fn right(&self) -> i32 {
+        self.left + self.width
+    }
+}
+
+struct Line<'a> {
+    block: i32,
+    par: i32,
+    words: Vec<&'a WordBox>,
+}
+
+impl<'a> Line<'a> {
+    fn left(&self) -> i32 {
+        self.words.iter().map(|word| word.left).min().unwrap_or(0)
+    }
+
+    /// Splits the line where words are far enough apart to be in different columns.
+    fn cells(&self, char_width: f32) -> Vec<Cell> {
+        let mut cells: Vec<Cell> = Vec::new();
+        for word in &self.words {
+            match cells.last_mut() {
+                Some(cell) if ((word.left - cell.right) as f32) < COLUMN_GAP_CHARS * char_width => {
+                    cell.text.push(' ');
+                    cell.text.push_str(word.text.trim());
+                    cell.right = word.right();
+                }
+                _ => cells.push(Cell {
+                    left: word.left,
+                    right: word.right(),
+                    text: word.text.trim().to_string(),
+                }),
+            }
+        }
+        cells
+    }
+
+    fn text(&self) -> String {
+        self.words
+            .iter()
+            .map(|word| word.text.trim())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+struct Cell {
+    left: i32,
+    right: i32,
+    text: String,
+}
+
+/// Lays out the words as text. Words are expected in the order Tesseract reports them.
+pub fn layout_text(words: &[WordBox]) -> String {
+    let words: Vec<&WordBox> = words
+        .iter()
+        .filter(|word| !word.text.trim().is_empty())
+        .collect();
+    let char_width = median_char_width(&words);
+
+    let mut lines: Vec<Line> = Vec::new();
+    for word in words {
+        match lines.last_mut() {
+            Some(line)
+                if line.block == word.block
+                    && line.par == word.par
+                    && line.words[0].line == word.line =>
+            {
+                line.words.push(word)
+            }
+            _ => lines.push(Line {
+                block: word.block,
+                par: word.par,
+                words: vec![word],
+            }),
+        }
+    }
+
+    let mut sections: Vec<String> = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let block = lines[start].block;
+        let end = start
+            + lines[start..]
+                .iter()
+                .take_while(|line| line.block == block)
+                .count();
+        layout_block(&lines[start..end], char_width, &mut sections);
+        start = end;
+    }
+    sections.join("\n\n")
+}
+
+/// Appends the paragraphs and tables of one block to `sections`.
+fn layout_block(lines: &[Line], char_width: f32, sections: &mut Vec<String>) {
+    let block_left = lines.iter().map(|line| line.left()).min().unwrap_or(0);
+    let cells: Vec<Vec<Cell>> = lines.iter().map(|line| line.cells(char_width)).collect();
+
+    let mut paragraph: Vec<String> = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let rows = table_rows(&cells[index..], char_width);
+        if rows >= MIN_TABLE_ROWS {
+            if !paragraph.is_empty() {
+                sections.push(paragraph.join("\n"));
+                paragraph.clear();
+            }
+            sections.push(markdown_table(&cells[index..index + rows]));
+            index += rows;
+            continue;
+        }
+
+        if index > 0 && lines[index].par != lines[index - 1].par && !paragraph.is_empty() {
+            sections.push(paragraph.join("\n"));
+            paragraph.clear();
+        }
+        let indent = (((lines[index].left() - block_left) as f32 / char_width).round() as usize)
+            .min(MAX_INDENT);
+        paragraph.push(format!("{}{}", " ".repeat(indent), lines[index].text()));
+        index += 1;
+    }
+    if !paragraph.is_empty() {
+        sections.push(paragraph.join("\n"));
+    }
+}
+
+/// Number of lines from the start of `lines` that form a table: each has the same number
+/// of cells, at least two, and every column lines up on its left or right edge.
+fn table_rows(lines: &[Vec<Cell>], char_width: f32) -> usize {
+    let first = match lines.first() {
+        Some(first) if first.len() >= 2 => first,
+        _ => return 0,
+    };
+    let tolerance = (COLUMN_ALIGNMENT_CHARS * char_width) as i32;
+    lines
+        .iter()
+        .take_while(|cells| {
+            cells.len() == first.len()
+                && cells.iter().zip(first.iter()).all(|(cell, column)| {
+                    (cell.left - column.left).abs() <= tolerance
+                        || (cell.right - column.right).abs() <= tolerance
+                })
+        })
+        .count()
+}
+
+fn markdown_table(rows: &[Vec<Cell>]) -> String {
+    let render = |cells: &Vec<Cell>| {
+        let cells: Vec<String> = cells
+            .iter()
+            .map(|cell| cell.text.replace('|', "\\|"))
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut table = vec![render(&rows[0])];
+    table.push(format!("|{}", " --- |".repeat(rows[0].len())));
+    table.extend(rows[1..].iter().map(render));
+    table.join("\n")
+}
+
+fn median_char_width(words: &[&WordBox]) -> f32 {
+    let mut widths: Vec<f32> = words
+        .iter()
+        .filter_map(|word| {
+            let chars = word.text.trim().chars().count();
+            if chars == 0 || word.width <= 0 {
+                None
+            } else {
+                Some(word.width as f32 / chars as f32)
+            }
+        })
+        .collect();
+    if widths.is_empty() {
+        return DEFAULT_CHAR_WIDTH;
+    }
+    widths.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    widths[widths.len() / 2].max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layout_text, WordBox};
+
+    const CHAR_WIDTH: i32 = 10;
+
+    /// Lays out `text` on a 10 px character grid: each string is a line, leading spaces
+    /// indent it and every word keeps its column.
+    fn words(block: i32, par: i32, first_line: i32, lines: &[&str]) -> Vec<WordBox> {
+        let mut words = Vec::new();
+        for (offset, line) in lines.iter().enumerate() {
+            let line_num = first_line + offset as i32;
+            let mut column = 0;
+            for token in line.split(' ') {
+                if !token.is_empty() {
+                    words.push(WordBox {
+                        block,
+                        par,
+                        line: line_num,
+                        left: column * CHAR_WIDTH,
+                        top: line_num * 20,
+                        width: token.chars().count() as i32 * CHAR_WIDTH,
+                        height: 14,
+                        conf: 90.0,
+                        text: token.to_string(),
+                    });
+                }
+                column += token.chars().count() as i32 + 1;
+            }
+        }
+        words
+    }
+
+    #[test]
+    fn paragraphs_and_indentation_are_kept() {
+        let mut page = words(1, 1, 0, &["Release notes"]);
+        page.extend(words(
+            2,
+            1,
+            1,
+            &["fn main() {", "    println!(\"hi\");", "}"],
+        ));
+        page.extend(words(2, 2, 4, &["Second paragraph here."]));
+        assert_eq!(
+            layout_text(&page),
+            "Release notes\n\nfn main() {\n    println!(\"hi\");\n}\n\nSecond paragraph here."
+        );
+    }
+
+    #[test]
+    fn aligned_columns_become_a_markdown_table() {
+        let page = words(
+            1,
+            1,
+            0,
+            &[
+                "Quarterly results",
+                "Region     Revenue     Growth",
+                "North     1.2M        4%",
+                "S|E       980K        -2%",
+                "Total for all regions     2.18M       1%",
+            ],
+        );
+        assert_eq!(
+            layout_text(&page),
+            "Quarterly results\n\n\
+             | Region | Revenue | Growth |\n\
+             | --- | --- | --- |\n\
+             | North | 1.2M | 4% |\n\
+             | S\\|E | 980K | -2% |\n\n\
+             Total for all regions 2.18M 1%"
+        );
+    }
+
+    #[test]
+    fn short_runs_of_columns_stay_text() {
+        let page = words(1, 1, 0, &["Name      Ada", "Role      Engineer"]);
+        assert_eq!(layout_text(&page), "Name Ada\nRole Engineer");
+    }
+}
@@ -0,0 +1,162 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage};
+use imageproc::contrast::adaptive_threshold;
+
+const THRESHOLD_BLOCK_RADIUS: u32 = 15;
+// Tesseract is most accurate on lines of text 20 to 30 px tall; smaller ones are scaled up
+const MIN_TEXT_HEIGHT: u32 = 16;
+const TARGET_TEXT_HEIGHT: u32 = 24;
+const MAX_UPSCALE: u32 = 3;
+const MAX_UPSCALED_PIXELS: u64 = 40_000_000;
+// Text heights are measured per vertical strip so that side-by-side columns do not merge
+const STRIP_WIDTH: u32 = 200;
+// How much darker than the background a pixel must be to count as ink
+const INK_CONTRAST: u8 = 60;
+// Runs of ink rows outside this range are rules, borders or pictures rather than text
+const MIN_LINE_HEIGHT: u32 = 4;
+const MAX_LINE_HEIGHT: u32 = 120;
+
+/// Turns a screenshot into the dark-on-light, binarised image Tesseract reads best. Dark
+/// themes are inverted, light ones left as they are, and small text is scaled up. Returns the
+/// image and the factor it was scaled by.
+pub fn preprocess_image(image: &DynamicImage) -> (DynamicImage, u32) {
+    let mut grayscale_image = image.to_luma8();
+    if has_dark_background(&grayscale_image) {
+        imageops::invert(&mut grayscale_image);
+    }
+    let scale = upscale_factor(
+        estimate_text_height(&grayscale_image),
+        grayscale_image.width(),
+        grayscale_image.height(),
+    );
+    if scale > 1 {
+        grayscale_image = imageops::resize(
+            &grayscale_image,
+            grayscale_image.width() * scale,
+            grayscale_image.height() * scale,
+            FilterType::CatmullRom,
+        );
+    }
+    let image =
+        DynamicImage::ImageLuma8(adaptive_threshold(&grayscale_image, THRESHOLD_BLOCK_RADIUS));
+    (image, scale)
+}
+
+/// Most of a screenshot is background, so its median brightness tells the theme.
+pub fn has_dark_background(image: &GrayImage) -> bool {
+    median_brightness(image) < 128
+}
+
+fn median_brightness(image: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in image.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let half = (image.width() as u64 * image.height() as u64 + 1) / 2;
+    let mut seen = 0;
+    for (value, count) in histogram.iter().enumerate() {
+        seen += count;
+        if seen >= half {
+            return value as u8;
+        }
+    }
+    255
+}
+
+/// Median height in pixels of the lines of text on a dark-on-light image, measured as runs
+/// of rows containing ink. None when no text-like runs are found.
+pub fn estimate_text_height(image: &GrayImage) -> Option<u32> {
+    let ink_below = median_brightness(image).saturating_sub(INK_CONTRAST);
+    let mut heights = Vec::new();
+    let mut strip_left = 0;
+    while strip_left < image.width() {
+        let strip_right = (strip_left + STRIP_WIDTH).min(image.width());
+        let mut run = 0;
+        for y in 0..image.height() {
+            let has_ink = (strip_left..strip_right).any(|x| image.get_pixel(x, y).0[0] < ink_below);
+            if has_ink {
+                run += 1;
+            } else {
+                if (MIN_LINE_HEIGHT..=MAX_LINE_HEIGHT).contains(&run) {
+                    heights.push(run);
+                }
+                run = 0;
+            }
+        }
+        if (MIN_LINE_HEIGHT..=MAX_LINE_HEIGHT).contains(&run) {
+            heights.push(run);
+        }
+        strip_left = strip_right;
+    }
+    if heights.is_empty() {
+        return None;
+    }
+    heights.sort_unstable();
+    Some(heights[heights.len() / 2])
+}
+
+/// Whole factor to scale the image by so its text reaches a readable height, limited so the
+/// result stays a reasonable size.
+pub fn upscale_factor(text_height: Option<u32>, width: u32, height: u32) -> u32 {
+    let text_height = match text_height {
+        Some(text_height) if text_height < MIN_TEXT_HEIGHT => text_height,
+        _ => return 1,
+    };
+    let mut scale = ((TARGET_TEXT_HEIGHT + text_height - 1) / text_height).min(MAX_UPSCALE);
+    while scale > 1 && width as u64 * height as u64 * (scale * scale) as u64 > MAX_UPSCALED_PIXELS {
+        scale -= 1;
+    }
+    scale
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, GrayImage, Luma};
+
+    use super::{estimate_text_height, has_dark_background, preprocess_image, upscale_factor};
+
+    // Lines of word-like blocks `line_height` px tall, in `ink` on `background`
+    fn page(line_height: u32, background: u8, ink: u8) -> GrayImage {
+        GrayImage::from_fn(600, 12 * line_height, |x, y| {
+            let row = y / (2 * line_height);
+            let in_line = y % (2 * line_height) < line_height && row < 5;
+            let in_word = x > 20 && x < 560 && (x / 6) % 7 != 0;
+            if in_line && in_word && (x + y) % 3 != 0 {
+                Luma([ink])
+            } else {
+                Luma([background])
+            }
+        })
+    }
+
+    #[test]
+    fn dark_themes_are_inverted_and_light_ones_kept() {
+        let dark = page(20, 30, 220);
+        let light = page(20, 250, 40);
+        assert!(has_dark_background(&dark));
+        assert!(!has_dark_background(&light));
+
+        for image in [dark, light] {
+            let (processed, scale) = preprocess_image(&DynamicImage::ImageLuma8(image));
+            let processed = processed.to_luma8();
+            assert_eq!(scale, 1);
+            // The empty corner is background and must come out white
+            assert_eq!(processed.get_pixel(595, processed.height() - 2).0[0], 255);
+        }
+    }
+
+    #[test]
+    fn small_text_is_scaled_up() {
+        let small = page(8, 255, 50);
+        assert_eq!(estimate_text_height(&small), Some(8));
+        assert_eq!(upscale_factor(Some(8), 600, 96), 3);
+        let (processed, scale) = preprocess_image(&DynamicImage::ImageLuma8(small));
+        assert_eq!(scale, 3);
+        assert_eq!((processed.width(), processed.height()), (1800, 288));
+
+        assert_eq!(estimate_text_height(&page(22, 255, 50)), Some(22));
+        assert_eq!(upscale_factor(Some(22), 600, 264), 1);
+        assert_eq!(upscale_factor(None, 600, 264), 1);
+        assert_eq!(upscale_factor(Some(8), 5120, 2880), 1);
+    }
+}
@@ -0,0 +1,145 @@
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+pub const SCREENSHOT_CHANGE_THRESHOLD_SETTING: &str = "screenshot_change_threshold";
+/// Screenshots whose hashes differ in at most this many of their 256 bits count as
+/// unchanged. Two captures of a still screen hash the same; a blinking cursor flips a bit
+/// or two at most.
+pub const DEFAULT_SCREENSHOT_CHANGE_THRESHOLD: u32 = 2;
+
+// dHash compares each cell of a (HASH_SIZE + 1) x HASH_SIZE thumbnail with its neighbour
+const HASH_SIZE: u32 = 16;
+const HASH_WORDS: usize = (HASH_SIZE * HASH_SIZE / 64) as usize;
+
+/// A 256-bit difference hash of a screenshot. Similar images have hashes a small Hamming
+/// distance apart, so it tells whether the screen changed without running OCR. Changes
+/// smaller than a thumbnail cell, such as a few typed characters, may not show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerceptualHash([u64; HASH_WORDS]);
+
+impl PerceptualHash {
+    pub fn of_image(image: &DynamicImage) -> Self {
+        let thumbnail = image
+            .grayscale()
+            .resize_exact(HASH_SIZE + 1, HASH_SIZE, FilterType::Triangle)
+            .to_luma8();
+        let mut words = [0u64; HASH_WORDS];
+        let mut bit = 0;
+        for y in 0..HASH_SIZE {
+            for x in 0..HASH_SIZE {
+                if thumbnail.get_pixel(x, y).0[0] < thumbnail.get_pixel(x + 1, y).0[0] {
+                    words[bit / 64] |= 1 << (bit % 64);
+                }
+                bit += 1;
+            }
+        }
+        PerceptualHash(words)
+    }
+
+    pub fn distance(&self, other: &PerceptualHash) -> u32 {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|word| format!("{:016x}", word)).collect()
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != HASH_WORDS * 16 || !hex.is_ascii() {
+            return None;
+        }
+        let mut words = [0u64; HASH_WORDS];
+        for (index, word) in words.iter_mut().enumerate() {
+            *word = u64::from_str_radix(&hex[index * 16..(index + 1) * 16], 16).ok()?;
+        }
+        Some(PerceptualHash(words))
+    }
+}
+
+/// Whether `current` shows the same screen as the stored hash of the previous capture.
+/// A missing or unreadable previous hash counts as changed.
+pub fn is_unchanged(previous: Option<&str>, current: &PerceptualHash, threshold: u32) -> bool {
+    match previous.and_then(PerceptualHash::from_hex) {
+        Some(previous) => previous.distance(current) <= threshold,
+        None => false,
+    }
+}
+
+pub fn change_threshold_from_setting(value: &str) -> u32 {
+    value
+        .trim()
+        .parse()
+        .unwrap_or(DEFAULT_SCREENSHOT_CHANGE_THRESHOLD)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    use super::{is_unchanged, PerceptualHash, DEFAULT_SCREENSHOT_CHANGE_THRESHOLD};
+
+    // A window with dark text lines on a light background
+    fn document(lines: u32, shift: u32) -> RgbImage {
+        RgbImage::from_fn(800, 600, |x, y| {
+            let row = y / 30;
+            let in_line = y % 30 < 14
+                && row < lines
+                && x > 40 + shift
+                && x < 40 + shift + 600 - row * 37 % 300;
+            if in_line && (x / 7) % 5 != 0 {
+                Rgb([30, 30, 30])
+            } else {
+                Rgb([245, 245, 245])
+            }
+        })
+    }
+
+    fn hash(image: RgbImage) -> PerceptualHash {
+        PerceptualHash::of_image(&DynamicImage::ImageRgb8(image))
+    }
+
+    #[test]
+    fn identical_and_slightly_noisy_screens_are_unchanged() {
+        let original = hash(document(15, 0));
+        assert_eq!(original.distance(&hash(document(15, 0))), 0);
+
+        let mut with_cursor = document(15, 0);
+        for y in 300..318 {
+            with_cursor.put_pixel(420, y, Rgb([0, 0, 0]));
+        }
+        let stored = original.to_hex();
+        assert!(is_unchanged(
+            Some(&stored),
+            &hash(with_cursor),
+            DEFAULT_SCREENSHOT_CHANGE_THRESHOLD
+        ));
+    }
+
+    #[test]
+    fn new_content_or_scrolling_is_a_change() {
+        let original = hash(document(10, 0));
+        let threshold = DEFAULT_SCREENSHOT_CHANGE_THRESHOLD;
+        assert!(!is_unchanged(
+            Some(&original.to_hex()),
+            &hash(document(16, 0)),
+            threshold
+        ));
+        assert!(!is_unchanged(
+            Some(&original.to_hex()),
+            &hash(document(10, 90)),
+            threshold
+        ));
+        assert!(!is_unchanged(None, &original, threshold));
+        assert!(!is_unchanged(Some("not a hash"), &original, threshold));
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        let original = hash(document(12, 0));
+        assert_eq!(PerceptualHash::from_hex(&original.to_hex()), Some(original));
+    }
+}
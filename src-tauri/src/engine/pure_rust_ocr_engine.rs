@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use image::DynamicImage;
+use ocrs::{ImageSource, OcrEngineParams, TextItem};
+use rten::Model;
+
+use crate::engine::ocr_layout_engine::WordBox;
+use crate::engine::text_recognition_engine::{OcrEngine, OcrOptions};
+
+// Models from https://github.com/robertknight/ocrs-models, shipped with the app resources
+const DETECTION_MODEL: &str = "text-detection.rten";
+const RECOGNITION_MODEL: &str = "text-recognition.rten";
+// A gap between lines taller than this share of a line starts a new paragraph
+const PARAGRAPH_GAP: f32 = 0.8;
+
+/// Directory of the bundled OCR models within the app's resource directory.
+pub fn models_dir(resource_dir: &Path) -> PathBuf {
+    resource_dir.join("resources")
+}
+
+/// Recognises text with the `ocrs` models, so OCR works without Tesseract installed. The
+/// models are trained on English; other languages and the page segmentation mode are
+/// ignored, and words carry no confidence.
+pub struct PureRustOcrEngine {
+    engine: ocrs::OcrEngine,
+}
+
+impl PureRustOcrEngine {
+    pub fn load(models_dir: &Path) -> Result<Self, String> {
+        let load_model = |file: &str| {
+            Model::load_file(models_dir.join(file))
+                .map_err(|e| format!("Failed to load OCR model {}: {}", file, e))
+        };
+        let engine = ocrs::OcrEngine::new(OcrEngineParams {
+            detection_model: Some(load_model(DETECTION_MODEL)?),
+            recognition_model: Some(load_model(RECOGNITION_MODEL)?),
+            ..Default::default()
+        })
+        .map_err(|e| e.to_string())?;
+        Ok(PureRustOcrEngine { engine })
+    }
+}
+
+impl OcrEngine for PureRustOcrEngine {
+    fn name(&self) -> &'static str {
+        "pure_rust"
+    }
+
+    fn recognize(
+        &self,
+        image: &DynamicImage,
+        _options: &OcrOptions,
+    ) -> Result<Vec<WordBox>, String> {
+        let image = image.to_rgb8();
+        let source = ImageSource::from_bytes(image.as_raw(), image.dimensions())
+            .map_err(|e| e.to_string())?;
+        let input = self
+            .engine
+            .prepare_input(source)
+            .map_err(|e| e.to_string())?;
+        let word_rects = self
+            .engine
+            .detect_words(&input)
+            .map_err(|e| e.to_string())?;
+        let line_rects = self.engine.find_text_lines(&input, &word_rects);
+        let lines = self
+            .engine
+            .recognize_text(&input, &line_rects)
+            .map_err(|e| e.to_string())?;
+
+        let lines: Vec<Vec<WordBox>> = lines
+            .iter()
+            .flatten()
+            .map(|line| {
+                line.words()
+                    .map(|word| {
+                        let rect = word.bounding_rect();
+                        WordBox {
+                            block: 1,
+                            par: 1,
+                            line: 0,
+                            left: rect.left(),
+                            top: rect.top(),
+                            width: rect.width(),
+                            height: rect.height(),
+                            conf: 100.0,
+                            text: word.to_string(),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|words| !words.is_empty())
+            .collect();
+        Ok(number_lines(lines))
+    }
+}
+
+/// Numbers the lines, which come in reading order, and splits them into paragraphs where
+/// the gap to the previous line is unusually tall.
+fn number_lines(lines: Vec<Vec<WordBox>>) -> Vec<WordBox> {
+    let mut words = Vec::new();
+    let mut par = 1;
+    let mut previous: Option<(i32, i32)> = None;
+    for (index, line) in lines.into_iter().enumerate() {
+        let top = line.iter().map(|word| word.top).min().unwrap_or(0);
+        let bottom = line
+            .iter()
+            .map(|word| word.top + word.height)
+            .max()
+            .unwrap_or(0);
+        if let Some((previous_top, previous_bottom)) = previous {
+            let gap = (top - previous_bottom) as f32;
+            if gap > PARAGRAPH_GAP * (previous_bottom - previous_top) as f32 {
+                par += 1;
+            }
+        }
+        previous = Some((top, bottom));
+        words.extend(line.into_iter().map(|word| WordBox {
+            par,
+            line: index as i32 + 1,
+            ..word
+        }));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{number_lines, WordBox};
+
+    fn line(top: i32, words: &[&str]) -> Vec<WordBox> {
+        words
+            .iter()
+            .enumerate()
+            .map(|(index, text)| WordBox {
+                block: 1,
+                par: 1,
+                line: 0,
+                left: index as i32 * 60,
+                top,
+                width: 50,
+                height: 20,
+                conf: 100.0,
+                text: text.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tall_gaps_between_lines_start_paragraphs() {
+        let words = number_lines(vec![
+            line(0, &["Release", "notes"]),
+            line(60, &["First", "change"]),
+            line(86, &["continues", "here"]),
+            line(112, &["and", "ends"]),
+        ]);
+        let numbers: Vec<(i32, i32)> = words.iter().map(|word| (word.par, word.line)).collect();
+        assert_eq!(
+            numbers,
+            vec![
+                (1, 1),
+                (1, 1),
+                (2, 2),
+                (2, 2),
+                (2, 3),
+                (2, 3),
+                (2, 4),
+                (2, 4)
+            ]
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+use log::{error, info};
+
+/// Answer the rewriter gives when the follow-up can be answered from the conversation and
+/// the documents already attached to it.
+const NO_RETRIEVAL: &str = "NO_RETRIEVAL";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryRewrite {
+    /// The follow-up rewritten so that it can be searched without the conversation.
+    Standalone(String),
+    /// No new documents are needed for this turn.
+    Skip,
+}
+
+#[async_trait]
+pub trait QueryRewriter: Send + Sync {
+    async fn rewrite(
+        &self,
+        conversation_history: &str,
+        follow_up: &str,
+    ) -> Result<QueryRewrite, String>;
+}
+
+/// Decides what to search for on this turn. The first message is searched as typed; later
+/// messages are rewritten from the conversation, falling back to the raw follow-up when the
+/// rewrite fails so that a flaky request never costs the user their context.
+pub async fn standalone_query(
+    rewriter: &dyn QueryRewriter,
+    conversation_history: &str,
+    follow_up: &str,
+    is_first_message: bool,
+) -> Option<String> {
+    if is_first_message || conversation_history.trim().is_empty() {
+        return Some(follow_up.to_string());
+    }
+
+    match rewriter.rewrite(conversation_history, follow_up).await {
+        Ok(QueryRewrite::Standalone(query)) => {
+            info!("Rewrote follow-up into standalone query: {}", query);
+            Some(query)
+        }
+        Ok(QueryRewrite::Skip) => {
+            info!("Query rewrite decided no new context is needed");
+            None
+        }
+        Err(err) => {
+            error!(
+                "Query rewrite failed: {}. Searching with the follow-up as typed.",
+                err
+            );
+            Some(follow_up.to_string())
+        }
+    }
+}
+
+pub fn query_rewrite_system_prompt() -> String {
+    format!(
+        r#"You are a search query writer for a personal assistant that searches documents captured on the user's device. Given a conversation and the user's follow-up message, rewrite the follow-up into a single standalone search query that can be understood without the conversation. Resolve pronouns and references such as "it", "that file" or "the contract" to the names used earlier in the conversation, and keep file names, proper names and function names exactly as written.
+If the follow-up can be answered from the conversation alone, for example a request to rephrase, shorten, translate or continue the previous answer, output exactly {} instead.
+Examples:
+Conversation: User: Summarize the Acme master services agreement. Assistant: The agreement covers ...
+Follow-up: and what did the contract say about termination?
+Output: Acme master services agreement termination clause
+Conversation: User: Investigate the bug in loginUser in auth.js. Assistant: The bug is caused by ...
+Follow-up: Is there a pull request for it?
+Output: pull request fixing loginUser bug in auth.js
+Conversation: User: Draft an email to Jackson about the onboarding changes. Assistant: Hi Jackson, ...
+Follow-up: make it shorter
+Output: {}
+Output only the query or {}, with absolutely no other additional text or explanations."#,
+        NO_RETRIEVAL, NO_RETRIEVAL, NO_RETRIEVAL
+    )
+}
+
+pub fn query_rewrite_user_prompt(conversation_history: &str, follow_up: &str) -> String {
+    format!(
+        "Conversation:\n{}\n\nFollow-up: {}",
+        conversation_history, follow_up
+    )
+}
+
+pub fn parse_query_rewrite(text: &str) -> QueryRewrite {
+    let query = text
+        .trim()
+        .trim_start_matches("Output:")
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'' || c == '`')
+        .trim();
+    if query.is_empty() || query.eq_ignore_ascii_case(NO_RETRIEVAL) {
+        QueryRewrite::Skip
+    } else {
+        QueryRewrite::Standalone(query.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_query_rewrite, QueryRewrite};
+
+    #[test]
+    fn parses_standalone_query() {
+        assert_eq!(
+            parse_query_rewrite("  \"Acme contract termination clause\"\n"),
+            QueryRewrite::Standalone("Acme contract termination clause".to_string())
+        );
+        assert_eq!(
+            parse_query_rewrite("Output: pull request for auth.js"),
+            QueryRewrite::Standalone("pull request for auth.js".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_skip() {
+        assert_eq!(parse_query_rewrite("NO_RETRIEVAL"), QueryRewrite::Skip);
+        assert_eq!(parse_query_rewrite(" no_retrieval "), QueryRewrite::Skip);
+        assert_eq!(parse_query_rewrite(""), QueryRewrite::Skip);
+    }
+}
@@ -0,0 +1,221 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+
+use crate::engine::reranking_engine::ScoredDocument;
+
+pub const RECENCY_HALF_LIFE_DAYS_SETTING: &str = "recency_half_life_days";
+pub const RECENCY_WEIGHT_SETTING: &str = "recency_weight";
+pub const ENGAGEMENT_WEIGHT_SETTING: &str = "engagement_weight";
+
+const DEFAULT_RECENCY_HALF_LIFE_DAYS: f32 = 14.0;
+const DEFAULT_RECENCY_WEIGHT: f32 = 0.2;
+const DEFAULT_ENGAGEMENT_WEIGHT: f32 = 0.1;
+// Engagement stops growing at this many saves and this much time spent in the window
+const SATURATING_SAVE_COUNT: f32 = 20.0;
+const SATURATING_DWELL_SECONDS: f32 = 2.0 * 60.0 * 60.0;
+const DATE_OF_ENTRY_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How much recency and engagement count next to the relevance score from reranking.
+/// Similarity gets whatever weight the two leave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelevanceWeights {
+    pub recency_weight: f32,
+    pub engagement_weight: f32,
+    /// Age at which a document's recency score halves.
+    pub half_life_days: f32,
+}
+
+impl Default for RelevanceWeights {
+    fn default() -> Self {
+        RelevanceWeights {
+            recency_weight: DEFAULT_RECENCY_WEIGHT,
+            engagement_weight: DEFAULT_ENGAGEMENT_WEIGHT,
+            half_life_days: DEFAULT_RECENCY_HALF_LIFE_DAYS,
+        }
+    }
+}
+
+impl RelevanceWeights {
+    /// Builds weights from setting values, keeping the default for any that is missing or
+    /// invalid. Recency and engagement are scaled down if together they exceed 1.
+    pub fn from_settings(
+        half_life_days: &str,
+        recency_weight: &str,
+        engagement_weight: &str,
+    ) -> Self {
+        let defaults = RelevanceWeights::default();
+        let weight = |value: &str, default: f32| {
+            value
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|weight| (0.0..=1.0).contains(weight))
+                .unwrap_or(default)
+        };
+        let recency_weight = weight(recency_weight, defaults.recency_weight);
+        let engagement_weight = weight(engagement_weight, defaults.engagement_weight);
+        let total = recency_weight + engagement_weight;
+        let scale = if total > 1.0 { 1.0 / total } else { 1.0 };
+        RelevanceWeights {
+            recency_weight: recency_weight * scale,
+            engagement_weight: engagement_weight * scale,
+            half_life_days: half_life_days
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|days| *days > 0.0)
+                .unwrap_or(defaults.half_life_days),
+        }
+    }
+
+    fn similarity_weight(&self) -> f32 {
+        (1.0 - self.recency_weight - self.engagement_weight).max(0.0)
+    }
+}
+
+/// Query-independent facts about a document that make it more or less likely to matter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentSignals {
+    pub id: i64,
+    pub dateofentry: String,
+    pub save_count: i64,
+    /// Total capture interval spent in the document's window, from `activity_logs`.
+    pub dwell_seconds: i64,
+}
+
+/// Exponential decay from 1.0 for a document saved `now` to 0.5 after one half-life.
+/// Documents without a readable date score 0.
+pub fn recency_score(dateofentry: &str, now: NaiveDateTime, half_life_days: f32) -> f32 {
+    match NaiveDateTime::parse_from_str(dateofentry, DATE_OF_ENTRY_FORMAT) {
+        Ok(saved_at) => {
+            let age_days = (now - saved_at).num_seconds().max(0) as f32 / 86_400.0;
+            0.5f32.powf(age_days / half_life_days)
+        }
+        Err(_) => 0.0,
+    }
+}
+
+/// Grows with the number of saves and the time spent in the window, from 0.0 to 1.0.
+pub fn engagement_score(save_count: i64, dwell_seconds: i64) -> f32 {
+    let saves = (1.0 + save_count.max(0) as f32).ln() / (1.0 + SATURATING_SAVE_COUNT).ln();
+    let dwell = dwell_seconds.max(0) as f32 / SATURATING_DWELL_SECONDS;
+    0.5 * saves.min(1.0) + 0.5 * dwell.min(1.0)
+}
+
+/// Combines the relevance of a document to the query with how recent and how used it is.
+pub fn relevance_score(
+    similarity: f32,
+    signals: &DocumentSignals,
+    now: NaiveDateTime,
+    weights: &RelevanceWeights,
+) -> f32 {
+    weights.similarity_weight() * similarity
+        + weights.recency_weight * recency_score(&signals.dateofentry, now, weights.half_life_days)
+        + weights.engagement_weight * engagement_score(signals.save_count, signals.dwell_seconds)
+}
+
+/// Rescores reranked documents with their signals and orders them best first. Documents
+/// without signals count as old and unused.
+pub fn weight_by_signals(
+    documents: Vec<ScoredDocument>,
+    signals: &[DocumentSignals],
+    now: NaiveDateTime,
+    weights: &RelevanceWeights,
+) -> Vec<ScoredDocument> {
+    let signals: HashMap<i64, &DocumentSignals> = signals
+        .iter()
+        .map(|signals| (signals.id, signals))
+        .collect();
+    let mut weighted: Vec<ScoredDocument> = documents
+        .into_iter()
+        .map(|document| {
+            let score = match signals.get(&document.id) {
+                Some(signals) => relevance_score(document.score, signals, now, weights),
+                None => weights.similarity_weight() * document.score,
+            };
+            ScoredDocument {
+                id: document.id,
+                score,
+            }
+        })
+        .collect();
+    weighted.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    weighted
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::{
+        engagement_score, recency_score, weight_by_signals, DocumentSignals, RelevanceWeights,
+    };
+    use crate::engine::reranking_engine::ScoredDocument;
+
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("2026-10-19 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn signals(id: i64, dateofentry: &str, save_count: i64, dwell_seconds: i64) -> DocumentSignals {
+        DocumentSignals {
+            id,
+            dateofentry: dateofentry.to_string(),
+            save_count,
+            dwell_seconds,
+        }
+    }
+
+    #[test]
+    fn recency_halves_every_half_life() {
+        assert!((recency_score("2026-10-19 12:00:00", now(), 14.0) - 1.0).abs() < 1e-6);
+        assert!((recency_score("2026-10-05 12:00:00", now(), 14.0) - 0.5).abs() < 1e-6);
+        assert!((recency_score("2026-09-21 12:00:00", now(), 14.0) - 0.25).abs() < 1e-6);
+        assert_eq!(recency_score("", now(), 14.0), 0.0);
+    }
+
+    #[test]
+    fn engagement_saturates() {
+        assert_eq!(engagement_score(0, 0), 0.0);
+        assert!(engagement_score(2, 600) < engagement_score(10, 3600));
+        assert_eq!(engagement_score(1000, 100_000), 1.0);
+    }
+
+    #[test]
+    fn recent_and_used_documents_overtake_slightly_better_matches() {
+        let documents = vec![
+            ScoredDocument { id: 1, score: 0.80 },
+            ScoredDocument { id: 2, score: 0.75 },
+            ScoredDocument { id: 3, score: 0.10 },
+        ];
+        let signals = vec![
+            signals(1, "2025-10-19 12:00:00", 2, 0),
+            signals(2, "2026-10-19 11:00:00", 12, 5400),
+            signals(3, "2026-10-19 11:30:00", 30, 20_000),
+        ];
+
+        let ids = |weights: &RelevanceWeights| -> Vec<i64> {
+            weight_by_signals(documents.clone(), &signals, now(), weights)
+                .iter()
+                .map(|document| document.id)
+                .collect()
+        };
+        assert_eq!(ids(&RelevanceWeights::default()), vec![2, 1, 3]);
+        assert_eq!(
+            ids(&RelevanceWeights::from_settings("", "0", "0")),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn weights_from_settings_are_validated() {
+        let weights = RelevanceWeights::from_settings("7", "0.8", "0.6");
+        assert_eq!(weights.half_life_days, 7.0);
+        assert!((weights.recency_weight + weights.engagement_weight - 1.0).abs() < 1e-6);
+        assert_eq!(
+            RelevanceWeights::from_settings("-1", "abc", ""),
+            RelevanceWeights::default()
+        );
+    }
+}
@@ -0,0 +1,501 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use log::{debug, info};
+
+/// Maximum number of documents any reranker passes on to the final prompt.
+pub const MAX_RELEVANT_DOCUMENTS: usize = 4;
+const SIMPLE_QUERY_MAX_WORDS: usize = 6;
+
+#[derive(Debug, Clone)]
+pub struct RerankCandidate {
+    pub id: i64,
+    pub preview: String,
+    /// Cosine distance from the vector search; `None` for keyword or recency matches.
+    pub distance: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredDocument {
+    pub id: i64,
+    pub score: f32,
+}
+
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    /// Returns the relevant candidates ordered by descending score.
+    async fn rerank(
+        &self,
+        query: &str,
+        candidates: &[RerankCandidate],
+    ) -> Result<Vec<ScoredDocument>, String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RerankStrategy {
+    Llm,
+    ScoreThreshold,
+    Local,
+    /// Uses the local reranker for simple queries and the LLM for the rest.
+    Auto,
+}
+
+impl RerankStrategy {
+    pub fn from_setting(value: &str) -> Self {
+        match value {
+            "threshold" => RerankStrategy::ScoreThreshold,
+            "local" => RerankStrategy::Local,
+            "auto" => RerankStrategy::Auto,
+            _ => RerankStrategy::Llm,
+        }
+    }
+}
+
+/// Keeps vector matches closer than `max_distance`; candidates without a distance are dropped.
+pub struct ScoreThresholdReranker {
+    pub max_distance: f32,
+    pub max_results: usize,
+}
+
+impl Default for ScoreThresholdReranker {
+    fn default() -> Self {
+        ScoreThresholdReranker {
+            max_distance: 0.6,
+            max_results: MAX_RELEVANT_DOCUMENTS,
+        }
+    }
+}
+
+#[async_trait]
+impl Reranker for ScoreThresholdReranker {
+    async fn rerank(
+        &self,
+        _query: &str,
+        candidates: &[RerankCandidate],
+    ) -> Result<Vec<ScoredDocument>, String> {
+        let scored = candidates
+            .iter()
+            .filter_map(|candidate| {
+                let distance = candidate.distance?;
+                (distance <= self.max_distance).then(|| ScoredDocument {
+                    id: candidate.id,
+                    score: similarity_from_distance(distance),
+                })
+            })
+            .collect();
+        Ok(top_scored(scored, self.max_results))
+    }
+}
+
+/// Scores candidates without a network call by mixing query term overlap with the
+/// vector similarity already computed during the search.
+pub struct LocalReranker {
+    pub lexical_weight: f32,
+    pub similarity_weight: f32,
+    pub min_score: f32,
+    pub max_results: usize,
+}
+
+impl Default for LocalReranker {
+    fn default() -> Self {
+        LocalReranker {
+            lexical_weight: 0.5,
+            similarity_weight: 0.5,
+            min_score: 0.25,
+            max_results: MAX_RELEVANT_DOCUMENTS,
+        }
+    }
+}
+
+#[async_trait]
+impl Reranker for LocalReranker {
+    async fn rerank(
+        &self,
+        query: &str,
+        candidates: &[RerankCandidate],
+    ) -> Result<Vec<ScoredDocument>, String> {
+        let query_terms = tokenize(query);
+        let scored = candidates
+            .iter()
+            .map(|candidate| {
+                let lexical = lexical_overlap(&query_terms, &candidate.preview);
+                let score = match candidate.distance {
+                    Some(distance) => {
+                        self.lexical_weight * lexical
+                            + self.similarity_weight * similarity_from_distance(distance)
+                    }
+                    None => lexical,
+                };
+                ScoredDocument {
+                    id: candidate.id,
+                    score,
+                }
+            })
+            .filter(|scored| scored.score >= self.min_score)
+            .collect();
+        Ok(top_scored(scored, self.max_results))
+    }
+}
+
+/// Reranks with the configured strategy, falling back to `llm_reranker` for `Llm` and for
+/// non-simple queries under `Auto`. The local rerankers keep up to `max_results` documents;
+/// the LLM keeps the ones it judges relevant.
+pub async fn rerank_candidates(
+    strategy: RerankStrategy,
+    llm_reranker: &dyn Reranker,
+    query: &str,
+    keywords: &[String],
+    candidates: &[RerankCandidate],
+    max_results: usize,
+) -> Result<Vec<ScoredDocument>, String> {
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let strategy = match strategy {
+        RerankStrategy::Auto if is_simple_query(query, keywords) => RerankStrategy::Local,
+        RerankStrategy::Auto => RerankStrategy::Llm,
+        strategy => strategy,
+    };
+    info!("Reranking {} candidates with {:?}", candidates.len(), strategy);
+
+    let scored = match strategy {
+        RerankStrategy::ScoreThreshold => {
+            ScoreThresholdReranker {
+                max_results,
+                ..ScoreThresholdReranker::default()
+            }
+            .rerank(query, candidates)
+            .await?
+        }
+        RerankStrategy::Local => {
+            LocalReranker {
+                max_results,
+                ..LocalReranker::default()
+            }
+            .rerank(query, candidates)
+            .await?
+        }
+        _ => llm_reranker.rerank(query, candidates).await?,
+    };
+    debug!("Reranked documents: {:?}", scored);
+    Ok(scored)
+}
+
+/// A query is simple when it is short and names nothing that has to match exactly.
+pub fn is_simple_query(query: &str, keywords: &[String]) -> bool {
+    keywords.is_empty() && query.split_whitespace().count() <= SIMPLE_QUERY_MAX_WORDS
+}
+
+/// Formats candidates the way the relevance prompt refers to them.
+pub fn relevance_context(candidates: &[RerankCandidate]) -> String {
+    let mut context = String::new();
+    for candidate in candidates {
+        context.push_str(&format!(
+            "Document ID: {}\nContent:\n{}\n\n",
+            candidate.id, candidate.preview
+        ));
+    }
+    if context.is_empty() {
+        context.push_str("No relevant documents found.\n\n");
+    }
+    context
+}
+
+/// Extracts candidate IDs from an LLM answer, ignoring numbers that are not candidates
+/// and scoring by the order the model listed them in.
+pub fn parse_ranked_ids(text: &str, candidates: &[RerankCandidate]) -> Vec<ScoredDocument> {
+    let candidate_ids: HashSet<i64> = candidates.iter().map(|candidate| candidate.id).collect();
+    let mut seen = HashSet::new();
+    let ids: Vec<i64> = text
+        .split(|c: char| !c.is_numeric())
+        .filter_map(|s| s.parse().ok())
+        .filter(|id| candidate_ids.contains(id) && seen.insert(*id))
+        .take(MAX_RELEVANT_DOCUMENTS)
+        .collect();
+    let count = ids.len() as f32;
+    ids.into_iter()
+        .enumerate()
+        .map(|(rank, id)| ScoredDocument {
+            id,
+            score: 1.0 - rank as f32 / count,
+        })
+        .collect()
+}
+
+pub fn relevance_system_prompt(user_prompt: &str) -> String {
+    format!( "The user's prompt is: {}\n\n. You are an intelligent and logical personal assistant. Your task is to carefully review the content of provided documents and output solely a maximum of four numerical IDs of the documents that are directly related to the user prompt and are highly likely to help in answering the user's prompt (corresponding to the Document ID at the beginning of each document). If an individual document is not extremely relevant to the user prompt and the user prompt can be successfully answered without that document, do not include it in the list of returned documents.
+
+        Examples of relevant and irrelevant documents in different business scenarios:
+        If a document is virtually identical to another one, just include one of them in the list of returned documents.
+
+        Example 1: The user prompt is to outline effective marketing strategies for social media.
+        - Relevant document:
+            Document ID: 55
+            Content: This document details various social media marketing strategies, which is directly relevant to the user's prompt.
+        - Irrelevant document:
+            Document ID: 78
+            Content: This document describes traditional print advertising methods, which is not relevant to social media marketing strategies.
+
+        Example 2: The user prompt is researching the best programming practices for AI development.
+        - Relevant document:
+            Document ID: 33
+            Content: This document provides best practices for AI development, which is directly relevant to the user's prompt.
+        - Irrelevant document:
+            Document ID: 47
+            Content: This document discusses basic HTML and CSS programming, which is not relevant to the user's prompt about AI development.
+
+        Example 3: The user prompt asks for recommended books on investment strategies.
+        - Relevant documents:
+            Document ID: 17
+            Content: This document lists top-rated books on investment strategies, highly relevant to the user's prompt.
+            Document ID: 106
+            Content: This document summarizes famous investment strategies, which is also relevant to the user's prompt.
+            Document ID: 204
+            Content: This document contains interviews with successful investors discussing their strategies, directly relevant to the user's prompt.
+            Document ID: 345
+            Content: This document reviews recent books on future investment trends, relevant to the user's prompt.
+        - Irrelevant document:
+            Document ID: 88
+            Content: This document covers general finance tips, which may not be directly relevant to specific investment strategies.
+
+        Example 4: The user prompt is to find best practices for remote team management.
+        - Relevant document:
+            Document ID: 99
+            Content: This document covers best practices for managing remote teams, directly relevant to the user's prompt.
+        - Irrelevant document:
+            Document ID: 65
+            Content: This document discusses in-office team-building activities, which are not relevant to managing remote teams.
+
+        Example 5: The user prompt is about analyzing the latest trends in cybersecurity.
+        - Relevant documents:
+            Document ID: 120
+            Content: This document provides a detailed analysis of the latest cybersecurity trends, directly relevant to the user's prompt.
+            Document ID: 150
+            Content: This document includes recent cybersecurity reports and data, relevant to understanding current trends.
+        - Irrelevant document:
+            Document ID: 88
+            Content: This document outlines historical cybersecurity breaches, which may not be directly relevant to analyzing current trends.
+            Document ID: 200
+            Content: This document focuses on outdated cybersecurity practices, which are not relevant to the latest trends.
+
+        Example 6: The user prompt asks for guidelines on creating an investment portfolio.
+        - Relevant document:
+            Document ID: 300
+            Content: This document provides detailed guidelines on how to create and manage an investment portfolio, highly relevant to the user's prompt.
+        - Irrelevant document:
+            Document ID: 77
+            Content: This document discusses corporate investment strategies, which may not be directly applicable to individual investment portfolios.
+
+        Example 7: The user prompt asks for something not covered by any provided document.
+        - User prompt: Strategies for eco-friendly business operations.
+        - No documents: None of the documents provided contain information about eco-friendly business operations, so no documents should be returned.
+
+        The user's prompt is: {}\n\nOutput the relevant document IDs as a comma-separated list of numbers only or an empty list, with absolutely no other additional text or explanations. For example: 123,456,789 or an empty list.",
+        user_prompt, user_prompt
+    )
+}
+
+pub fn tokenize(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| token.chars().count() > 2)
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+fn lexical_overlap(query_terms: &HashSet<String>, text: &str) -> f32 {
+    if query_terms.is_empty() {
+        return 0.0;
+    }
+    let document_terms = tokenize(text);
+    let matched = query_terms
+        .iter()
+        .filter(|term| document_terms.contains(*term))
+        .count();
+    matched as f32 / query_terms.len() as f32
+}
+
+fn similarity_from_distance(distance: f32) -> f32 {
+    (1.0 - distance).clamp(0.0, 1.0)
+}
+
+fn top_scored(mut scored: Vec<ScoredDocument>, max_results: usize) -> Vec<ScoredDocument> {
+    scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    scored.truncate(max_results);
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+
+    use super::{
+        parse_ranked_ids, relevance_context, rerank_candidates, LocalReranker, RerankCandidate,
+        RerankStrategy, Reranker, ScoreThresholdReranker, ScoredDocument, MAX_RELEVANT_DOCUMENTS,
+    };
+
+    /// Answers with a canned completion, parsed the way the LLM rerankers parse theirs.
+    struct StubLlmReranker {
+        response: &'static str,
+        calls: AtomicUsize,
+    }
+
+    impl StubLlmReranker {
+        fn answering(response: &'static str) -> Self {
+            StubLlmReranker {
+                response,
+                calls: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Reranker for StubLlmReranker {
+        async fn rerank(
+            &self,
+            _query: &str,
+            candidates: &[RerankCandidate],
+        ) -> Result<Vec<ScoredDocument>, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(parse_ranked_ids(self.response, candidates))
+        }
+    }
+
+    fn candidate(id: i64, preview: &str, distance: Option<f32>) -> RerankCandidate {
+        RerankCandidate {
+            id,
+            preview: preview.to_string(),
+            distance,
+        }
+    }
+
+    fn candidates() -> Vec<RerankCandidate> {
+        vec![
+            candidate(
+                7,
+                "quarterly revenue report for the finance team",
+                Some(0.2),
+            ),
+            candidate(12, "finance team budget spreadsheet", Some(0.3)),
+            candidate(30, "holiday schedule and vacation planning", Some(0.7)),
+            candidate(41, "rust borrow checker lifetimes explained", None),
+            candidate(55, "finance offsite agenda", None),
+        ]
+    }
+
+    fn ids(scored: &[ScoredDocument]) -> Vec<i64> {
+        scored.iter().map(|document| document.id).collect()
+    }
+
+    #[test]
+    fn parse_keeps_candidate_ids_in_the_order_listed() {
+        let scored = parse_ranked_ids("12, 99, 7 and 12 again; 2026", &candidates());
+        assert_eq!(
+            scored,
+            vec![
+                ScoredDocument { id: 12, score: 1.0 },
+                ScoredDocument { id: 7, score: 0.5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_caps_the_answer_and_accepts_an_empty_list() {
+        assert!(parse_ranked_ids("[]", &candidates()).is_empty());
+        assert!(parse_ranked_ids("None of the documents are relevant.", &candidates()).is_empty());
+        let scored = parse_ranked_ids("55,41,30,12,7", &candidates());
+        assert_eq!(scored.len(), MAX_RELEVANT_DOCUMENTS);
+        assert_eq!(ids(&scored), vec![55, 41, 30, 12]);
+        assert!(scored.windows(2).all(|pair| pair[0].score > pair[1].score));
+    }
+
+    #[test]
+    fn context_lists_every_candidate_by_id() {
+        let context = relevance_context(&candidates()[..2]);
+        assert_eq!(
+            context,
+            "Document ID: 7\nContent:\nquarterly revenue report for the finance team\n\n\
+             Document ID: 12\nContent:\nfinance team budget spreadsheet\n\n"
+        );
+        assert_eq!(relevance_context(&[]), "No relevant documents found.\n\n");
+    }
+
+    #[tokio::test]
+    async fn llm_strategy_returns_the_stubbed_ranking() {
+        let llm = StubLlmReranker::answering("30,7");
+        let keywords = vec!["finance".to_string()];
+        let scored = rerank_candidates(
+            RerankStrategy::Llm,
+            &llm,
+            "what did the finance team plan",
+            &keywords,
+            &candidates(),
+            MAX_RELEVANT_DOCUMENTS,
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(&scored), vec![30, 7]);
+        assert_eq!(llm.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn auto_strategy_skips_the_llm_for_simple_queries() {
+        let llm = StubLlmReranker::answering("30");
+        let scored = rerank_candidates(
+            RerankStrategy::Auto,
+            &llm,
+            "finance team",
+            &[],
+            &candidates(),
+            MAX_RELEVANT_DOCUMENTS,
+        )
+        .await
+        .unwrap();
+        assert_eq!(llm.calls.load(Ordering::SeqCst), 0);
+        assert_eq!(ids(&scored), vec![7, 12, 55]);
+
+        let keywords = vec!["Q3".to_string()];
+        rerank_candidates(
+            RerankStrategy::Auto,
+            &llm,
+            "finance team",
+            &keywords,
+            &candidates(),
+            MAX_RELEVANT_DOCUMENTS,
+        )
+        .await
+        .unwrap();
+        assert_eq!(llm.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn local_reranker_mixes_term_overlap_with_similarity() {
+        let scored = LocalReranker::default()
+            .rerank("finance team", &candidates())
+            .await
+            .unwrap();
+        // Both terms match 7 and 12, so the closer vector match wins; 55 matches one term
+        // and has no distance; 30 and 41 match nothing
+        assert_eq!(ids(&scored), vec![7, 12, 55]);
+        assert!((scored[0].score - 0.9).abs() < 1e-6);
+        assert!((scored[2].score - 0.5).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn score_threshold_keeps_close_vector_matches() {
+        let reranker = ScoreThresholdReranker {
+            max_distance: 0.5,
+            max_results: 1,
+        };
+        let scored = reranker.rerank("anything", &candidates()).await.unwrap();
+        assert_eq!(ids(&scored), vec![7]);
+
+        let scored = ScoreThresholdReranker::default()
+            .rerank("anything", &candidates())
+            .await
+            .unwrap();
+        assert_eq!(ids(&scored), vec![7, 12]);
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{NaiveDateTime, Utc};
+use log::{debug, error, info};
+use rusqlite::Connection;
+use tauri::AppHandle;
+
+use crate::configuration::state::ServiceAccess;
+use crate::engine::near_duplicate_engine::{
+    near_duplicate_threshold_from_setting, newest_representatives, NEAR_DUPLICATE_THRESHOLD_SETTING,
+};
+use crate::engine::relevance_scoring_engine::{
+    weight_by_signals, DocumentSignals, RelevanceWeights, ENGAGEMENT_WEIGHT_SETTING,
+    RECENCY_HALF_LIFE_DAYS_SETTING, RECENCY_WEIGHT_SETTING,
+};
+use crate::engine::reranking_engine::{
+    rerank_candidates, RerankCandidate, RerankStrategy, Reranker, ScoredDocument,
+    MAX_RELEVANT_DOCUMENTS,
+};
+use crate::engine::similarity_search_engine::{embed_text, SearchParameters, TOPK};
+use crate::engine::vector_store_engine::open_vector_store;
+use crate::repository::activity_log_repository::{
+    get_activity_full_text_by_id, get_additional_ids_from_sql_db, get_document_fingerprints,
+    get_document_signals, get_recent_activity_ids,
+};
+use crate::repository::chat_db_repository::{
+    get_chat_context_documents, save_chat_context_document,
+};
+use crate::repository::settings_repository::get_setting_value;
+
+const PREVIEW_LENGTH: usize = 1000;
+const DOCUMENT_LENGTH: usize = 10000;
+/// Upper bound on documents carried in the prompt across turns of one chat.
+const MAX_CONVERSATION_DOCUMENTS: usize = 8;
+// The most recent documents are added to the candidates of every query
+const RECENT_CANDIDATES: usize = 3;
+
+#[derive(Debug, Default, Clone)]
+pub struct RetrievedContext {
+    pub context: String,
+    pub window_titles: Vec<String>,
+    pub documents: Vec<ScoredDocument>,
+}
+
+/// Runs vector and keyword search for `query`, reranks the candidates and weights them by
+/// how recent and how used each document is.
+pub async fn retrieve_relevant_documents(
+    app_handle: &AppHandle,
+    query: &str,
+    keywords: &[String],
+    embedding_api_key: &str,
+    llm_reranker: &dyn Reranker,
+) -> Result<Vec<ScoredDocument>, String> {
+    // Embed before locking so a slow embeddings API does not block captures
+    let query_vector = embed_text(query, embedding_api_key)
+        .await
+        .map_err(|e| format!("Failed to compute query embedding: {}", e))?;
+
+    let store = open_vector_store(app_handle).await?;
+    info!("Initiating similarity search...");
+    let similar_ids = store
+        .search(&query_vector, SearchParameters::with_k(TOPK), None)
+        .await
+        .map_err(|e| format!("Similarity search failed: {}", e))?;
+
+    let mut additional_ids = app_handle
+        .db(|db| get_additional_ids_from_sql_db(db, keywords))
+        .map_err(|e| format!("Failed to retrieve additional IDs from SQL database: {}", e))?;
+    // Recent documents compete like any other candidate; the weighting below decides
+    // whether they make the cut
+    let recent_ids = app_handle
+        .db(|db| get_recent_activity_ids(db, RECENT_CANDIDATES))
+        .map_err(|e| format!("Failed to retrieve recent documents: {}", e))?;
+    additional_ids.extend(recent_ids);
+    debug!("Additional IDs: {:?}", additional_ids);
+
+    let threshold = near_duplicate_threshold_from_setting(
+        &app_handle.db(|db| get_setting_value(db, NEAR_DUPLICATE_THRESHOLD_SETTING)),
+    );
+    let candidates = app_handle.db(|db| {
+        let candidates = load_candidates(db, &similar_ids, &additional_ids);
+        collapse_near_duplicates(db, candidates, threshold)
+    });
+
+    let strategy =
+        RerankStrategy::from_setting(&app_handle.db(|db| get_setting_value(db, "reranker")));
+    // Keep every relevant candidate so that recency and engagement can reorder them before
+    // the cut to the final count
+    let reranked = rerank_candidates(
+        strategy,
+        llm_reranker,
+        query,
+        keywords,
+        &candidates,
+        candidates.len(),
+    )
+    .await?;
+
+    let setting = |key: &str| app_handle.db(|db| get_setting_value(db, key));
+    let weights = RelevanceWeights::from_settings(
+        &setting(RECENCY_HALF_LIFE_DAYS_SETTING),
+        &setting(RECENCY_WEIGHT_SETTING),
+        &setting(ENGAGEMENT_WEIGHT_SETTING),
+    );
+    let ids: Vec<i64> = reranked.iter().map(|document| document.id).collect();
+    let signals = app_handle
+        .db(|db| get_document_signals(db, &ids))
+        .unwrap_or_else(|err| {
+            error!("Failed to load document signals: {}", err);
+            Vec::new()
+        });
+    Ok(rank_by_signals(
+        reranked,
+        &signals,
+        Utc::now().naive_utc(),
+        &weights,
+        MAX_RELEVANT_DOCUMENTS,
+    ))
+}
+
+/// Weights reranked documents by their signals and keeps the best `max_results`.
+pub fn rank_by_signals(
+    reranked: Vec<ScoredDocument>,
+    signals: &[DocumentSignals],
+    now: NaiveDateTime,
+    weights: &RelevanceWeights,
+    max_results: usize,
+) -> Vec<ScoredDocument> {
+    let mut weighted = weight_by_signals(reranked, signals, now, weights);
+    weighted.truncate(max_results);
+    weighted
+}
+
+/// Builds the context for this turn from the documents just retrieved and the ones the
+/// chat already had, and remembers the new documents for later turns.
+pub fn load_conversation_context(
+    app_handle: &AppHandle,
+    chat_id: Option<i64>,
+    query: Option<&str>,
+    new_documents: Vec<ScoredDocument>,
+) -> Result<RetrievedContext, String> {
+    let previous_documents = match chat_id {
+        Some(chat_id) => app_handle
+            .db(|db| get_chat_context_documents(db, chat_id))
+            .map_err(|e| format!("Failed to load conversation context: {}", e))?
+            .into_iter()
+            .map(|(id, score)| ScoredDocument { id, score })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    if let (Some(chat_id), Some(query)) = (chat_id, query) {
+        for document in &new_documents {
+            app_handle
+                .db(|db| {
+                    save_chat_context_document(db, chat_id, document.id, document.score, query)
+                })
+                .map_err(|e| format!("Failed to save conversation context: {}", e))?;
+        }
+    }
+
+    let documents = merge_context_documents(
+        &new_documents,
+        &previous_documents,
+        MAX_CONVERSATION_DOCUMENTS,
+    );
+    debug!(
+        "Conversation context: {} new, {} previous, {} kept",
+        new_documents.len(),
+        previous_documents.len(),
+        documents.len()
+    );
+    load_documents(app_handle, documents)
+}
+
+/// Newly retrieved documents come first; earlier ones fill the remaining slots in the
+/// order they were added.
+pub fn merge_context_documents(
+    new_documents: &[ScoredDocument],
+    previous_documents: &[ScoredDocument],
+    max_documents: usize,
+) -> Vec<ScoredDocument> {
+    let mut seen = HashSet::new();
+    new_documents
+        .iter()
+        .chain(previous_documents.iter())
+        .filter(|document| seen.insert(document.id))
+        .take(max_documents)
+        .cloned()
+        .collect()
+}
+
+/// Merges vector matches (in distance order) with keyword matches, loading a preview of
+/// each document for the reranker.
+pub fn load_candidates(
+    db: &Connection,
+    similar_ids: &[(i64, f32)],
+    additional_ids: &[i64],
+) -> Vec<RerankCandidate> {
+    let mut seen = HashSet::new();
+    let ids = similar_ids
+        .iter()
+        .map(|(id, distance)| (*id, Some(*distance)))
+        .chain(additional_ids.iter().map(|id| (*id, None)))
+        .filter(|(id, _)| seen.insert(*id));
+
+    let mut candidates = Vec::new();
+    for (id, distance) in ids {
+        let result =
+            get_activity_full_text_by_id(db, id, Some(PREVIEW_LENGTH)).unwrap_or_else(|err| {
+                error!("Failed to retrieve edited full text for ID {}: {}", id, err);
+                None
+            });
+
+        if let Some((_window_title, preview)) = result {
+            debug!("Candidate document ID: {}", id);
+            candidates.push(RerankCandidate {
+                id,
+                preview,
+                distance,
+            });
+        }
+    }
+    candidates
+}
+
+/// Replaces near-duplicate candidates, such as one page saved under several window titles,
+/// with the newest version. The group takes the position and best distance of its
+/// highest-ranked member.
+pub fn collapse_near_duplicates(
+    db: &Connection,
+    candidates: Vec<RerankCandidate>,
+    threshold: f32,
+) -> Vec<RerankCandidate> {
+    let ids: Vec<i64> = candidates.iter().map(|candidate| candidate.id).collect();
+    let fingerprints = match get_document_fingerprints(db, &ids) {
+        Ok(fingerprints) => fingerprints,
+        Err(err) => {
+            error!("Failed to load document fingerprints: {}", err);
+            return candidates;
+        }
+    };
+    let representatives = newest_representatives(&fingerprints, threshold);
+
+    let by_id: HashMap<i64, &RerankCandidate> = candidates
+        .iter()
+        .map(|candidate| (candidate.id, candidate))
+        .collect();
+    let mut positions: HashMap<i64, usize> = HashMap::new();
+    let mut collapsed: Vec<RerankCandidate> = Vec::new();
+    for candidate in &candidates {
+        let representative = representatives
+            .get(&candidate.id)
+            .copied()
+            .unwrap_or(candidate.id);
+        match positions.get(&representative) {
+            Some(position) => {
+                let kept = &mut collapsed[*position];
+                kept.distance = closest(kept.distance, candidate.distance);
+            }
+            None => {
+                let mut kept = (*by_id.get(&representative).unwrap_or(&candidate)).clone();
+                kept.distance = closest(kept.distance, candidate.distance);
+                positions.insert(representative, collapsed.len());
+                collapsed.push(kept);
+            }
+        }
+    }
+    if collapsed.len() < candidates.len() {
+        info!(
+            "Collapsed {} near-duplicate candidates",
+            candidates.len() - collapsed.len()
+        );
+    }
+    collapsed
+}
+
+fn closest(a: Option<f32>, b: Option<f32>) -> Option<f32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn load_documents(
+    app_handle: &AppHandle,
+    documents: Vec<ScoredDocument>,
+) -> Result<RetrievedContext, String> {
+    let mut retrieved = RetrievedContext::default();
+    for document in documents {
+        let result: Option<(String, String)> = app_handle
+            .db(|db| get_activity_full_text_by_id(db, document.id, Some(DOCUMENT_LENGTH)))
+            .map_err(|e| format!("Failed to retrieve edited full text: {}", e))?;
+
+        if let Some((window_title, text)) = result {
+            retrieved.context.push_str(&format!(
+                "Document ID: {}\nContent:\n{}\n\n",
+                document.id, text
+            ));
+            retrieved.window_titles.push(window_title);
+            retrieved.documents.push(document);
+        }
+    }
+
+    debug!(
+        "Filtered context for final response generation: {}",
+        retrieved.context
+    );
+    Ok(retrieved)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use rusqlite::params;
+
+    use chrono::NaiveDateTime;
+
+    use super::{collapse_near_duplicates, merge_context_documents, rank_by_signals};
+    use crate::configuration::database::open_test_database;
+    use crate::engine::relevance_scoring_engine::{DocumentSignals, RelevanceWeights};
+    use crate::engine::reranking_engine::{RerankCandidate, ScoredDocument};
+
+    fn documents(ids: &[i64]) -> Vec<ScoredDocument> {
+        ids.iter()
+            .map(|id| ScoredDocument {
+                id: *id,
+                score: 1.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recent_documents_outside_the_reranked_top_can_make_the_cut() {
+        let reranked: Vec<ScoredDocument> = [(1, 0.9), (2, 0.85), (3, 0.8), (4, 0.75), (5, 0.6)]
+            .iter()
+            .map(|(id, score)| ScoredDocument {
+                id: *id,
+                score: *score,
+            })
+            .collect();
+        let signals: Vec<DocumentSignals> = reranked
+            .iter()
+            .map(|document| DocumentSignals {
+                id: document.id,
+                dateofentry: if document.id == 5 {
+                    "2026-10-19 11:00:00".to_string()
+                } else {
+                    "2025-10-19 11:00:00".to_string()
+                },
+                save_count: 1,
+                dwell_seconds: 0,
+            })
+            .collect();
+        let now =
+            NaiveDateTime::parse_from_str("2026-10-19 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let weights = RelevanceWeights {
+            recency_weight: 0.5,
+            engagement_weight: 0.0,
+            half_life_days: 7.0,
+        };
+
+        let ranked = rank_by_signals(reranked, &signals, now, &weights, 4);
+        let ids: Vec<i64> = ranked.iter().map(|document| document.id).collect();
+        assert_eq!(ids, vec![5, 1, 2, 3]);
+    }
+
+    #[test]
+    fn merge_puts_new_documents_first_without_duplicates() {
+        let merged = merge_context_documents(&documents(&[7, 3]), &documents(&[3, 1, 2]), 4);
+        let ids: Vec<i64> = merged.iter().map(|document| document.id).collect();
+        assert_eq!(ids, vec![7, 3, 1, 2]);
+    }
+
+    #[test]
+    fn merge_keeps_previous_documents_when_nothing_new() {
+        let merged = merge_context_documents(&[], &documents(&[5, 6, 7]), 2);
+        let ids: Vec<i64> = merged.iter().map(|document| document.id).collect();
+        assert_eq!(ids, vec![5, 6]);
+    }
+
+    #[test]
+    fn near_duplicates_collapse_to_the_newest_version() -> Result<()> {
+        let test_database = open_test_database()?;
+        let db = &test_database.db;
+        let page = "Release checklist: bump the version, update the changelog, tag the \
+            release, build the installers for every platform, upload them and announce the \
+            release on the mailing list once the download links work.";
+        let documents = [
+            (
+                1,
+                "Release checklist - Notes",
+                page.to_string(),
+                "2026-10-01 09:00:00",
+            ),
+            (
+                2,
+                "Grocery list",
+                "milk, eggs and bread".to_string(),
+                "2026-10-02 09:00:00",
+            ),
+            (
+                3,
+                "Release checklist (edited)",
+                format!("{} Done.", page),
+                "2026-10-03 09:00:00",
+            ),
+        ];
+        for (id, title, text, dateofentry) in &documents {
+            db.execute(
+                "INSERT INTO activity_full_text (id, dateofentry, window_title, edited_full_text)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![id, dateofentry, title, text],
+            )?;
+        }
+
+        let candidate = |id: i64, distance: Option<f32>| RerankCandidate {
+            id,
+            preview: format!("preview {}", id),
+            distance,
+        };
+        let collapsed = collapse_near_duplicates(
+            db,
+            vec![
+                candidate(1, Some(0.1)),
+                candidate(2, Some(0.2)),
+                candidate(3, None),
+            ],
+            0.85,
+        );
+        let collapsed: Vec<(i64, Option<f32>, String)> = collapsed
+            .into_iter()
+            .map(|candidate| (candidate.id, candidate.distance, candidate.preview))
+            .collect();
+        assert_eq!(
+            collapsed,
+            vec![
+                (3, Some(0.1), "preview 3".to_string()),
+                (2, Some(0.2), "preview 2".to_string())
+            ]
+        );
+        Ok(())
+    }
+}
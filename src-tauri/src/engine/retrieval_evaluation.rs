@@ -0,0 +1,305 @@
+//! Offline evaluation of the retrieval pipeline against a fixed corpus.
+//!
+//! The corpus in `fixtures/retrieval_corpus.json` holds a set of captured documents and
+//! queries labelled with the documents that should answer them. Embeddings come from the
+//! deterministic test embedder, so the report is reproducible and needs no API key. Every
+//! `VectorStore` backend is evaluated against the same floors. Run with
+//! `cargo test retrieval_evaluation -- --nocapture` to see the per-query report, and extend
+//! the corpus when a retrieval regression is found.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use serde_derive::Deserialize;
+
+use crate::configuration::database::open_test_database;
+use crate::engine::reranking_engine::{
+    rerank_candidates, LocalReranker, RerankStrategy, MAX_RELEVANT_DOCUMENTS,
+};
+use crate::engine::retrieval_engine::load_candidates;
+use crate::engine::similarity_search_engine::{
+    embed_text, SearchParameters, SimilaritySearch, TOPK,
+};
+use crate::engine::vector_store_engine::{SqliteSearchMode, SqliteVectorStore, VectorStore};
+use crate::repository::activity_log_repository::{
+    amplify_document_text, get_additional_ids_from_sql_db,
+};
+use crate::repository::vector_db_repository::EMBEDDING_MODEL;
+
+const CORPUS: &str = include_str!("fixtures/retrieval_corpus.json");
+
+// Floors sit a little below the current scores so that tuning the pipeline is caught
+// when it makes retrieval worse, not when it shuffles equally good results.
+const MIN_VECTOR_RECALL_AT_10: f64 = 0.9;
+const MIN_VECTOR_MRR: f64 = 0.8;
+const MIN_RERANKED_RECALL_AT_4: f64 = 0.75;
+const MIN_RERANKED_NDCG_AT_4: f64 = 0.7;
+
+#[derive(Deserialize)]
+struct Corpus {
+    documents: Vec<CorpusDocument>,
+    queries: Vec<CorpusQuery>,
+}
+
+#[derive(Deserialize)]
+struct CorpusDocument {
+    id: i64,
+    window_title: String,
+    window_app_name: String,
+    dateofentry: String,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct CorpusQuery {
+    query: String,
+    keywords: Vec<String>,
+    expected_ids: Vec<i64>,
+}
+
+#[derive(Default)]
+struct Metrics {
+    recall_at_4: f64,
+    recall_at_10: f64,
+    mrr: f64,
+    ndcg_at_4: f64,
+}
+
+impl Metrics {
+    fn measure(ranked: &[i64], expected: &HashSet<i64>) -> Self {
+        Metrics {
+            recall_at_4: recall_at_k(ranked, expected, 4),
+            recall_at_10: recall_at_k(ranked, expected, 10),
+            mrr: reciprocal_rank(ranked, expected),
+            ndcg_at_4: ndcg_at_k(ranked, expected, 4),
+        }
+    }
+
+    fn accumulate(&mut self, other: &Metrics) {
+        self.recall_at_4 += other.recall_at_4;
+        self.recall_at_10 += other.recall_at_10;
+        self.mrr += other.mrr;
+        self.ndcg_at_4 += other.ndcg_at_4;
+    }
+
+    fn averaged(&self, count: usize) -> Metrics {
+        let count = count.max(1) as f64;
+        Metrics {
+            recall_at_4: self.recall_at_4 / count,
+            recall_at_10: self.recall_at_10 / count,
+            mrr: self.mrr / count,
+            ndcg_at_4: self.ndcg_at_4 / count,
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "recall@4 {:.3}  recall@10 {:.3}  MRR {:.3}  nDCG@4 {:.3}",
+            self.recall_at_4, self.recall_at_10, self.mrr, self.ndcg_at_4
+        )
+    }
+}
+
+fn recall_at_k(ranked: &[i64], expected: &HashSet<i64>, k: usize) -> f64 {
+    if expected.is_empty() {
+        return 1.0;
+    }
+    let found = ranked
+        .iter()
+        .take(k)
+        .filter(|id| expected.contains(id))
+        .count();
+    found as f64 / expected.len() as f64
+}
+
+fn reciprocal_rank(ranked: &[i64], expected: &HashSet<i64>) -> f64 {
+    ranked
+        .iter()
+        .position(|id| expected.contains(id))
+        .map(|rank| 1.0 / (rank + 1) as f64)
+        .unwrap_or(0.0)
+}
+
+/// Binary-relevance nDCG: every expected document counts as equally relevant.
+fn ndcg_at_k(ranked: &[i64], expected: &HashSet<i64>, k: usize) -> f64 {
+    let discount = |rank: usize| 1.0 / ((rank + 2) as f64).log2();
+    let dcg: f64 = ranked
+        .iter()
+        .take(k)
+        .enumerate()
+        .filter(|(_, id)| expected.contains(id))
+        .map(|(rank, _)| discount(rank))
+        .sum();
+    let ideal: f64 = (0..expected.len().min(k)).map(discount).sum();
+    if ideal == 0.0 {
+        0.0
+    } else {
+        dcg / ideal
+    }
+}
+
+fn insert_documents(db: &Connection, documents: &[CorpusDocument]) -> Result<()> {
+    for document in documents {
+        db.execute(
+            "INSERT INTO activity_full_text
+             (id, dateofentry, window_title, window_app_name, original_full_text, edited_full_text, save_count)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5, 2)",
+            params![
+                document.id,
+                document.dateofentry,
+                document.window_title,
+                document.window_app_name,
+                document.text
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the averaged vector search and reranked metrics of `store` over the corpus.
+async fn evaluate_store(
+    name: &str,
+    store: &dyn VectorStore,
+    db: &Connection,
+    corpus: &Corpus,
+) -> Result<(Metrics, Metrics)> {
+    let reranker = LocalReranker::default();
+    let mut vector_total = Metrics::default();
+    let mut reranked_total = Metrics::default();
+    for query in &corpus.queries {
+        let expected: HashSet<i64> = query.expected_ids.iter().copied().collect();
+
+        let query_vector = embed_text(&query.query, "").await?;
+        let similar_ids = store
+            .search(&query_vector, SearchParameters::with_k(TOPK), None)
+            .await?;
+        let vector_ranking: Vec<i64> = similar_ids.iter().map(|(id, _)| *id).collect();
+
+        let additional_ids = get_additional_ids_from_sql_db(db, &query.keywords)
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let candidates = load_candidates(db, &similar_ids, &additional_ids);
+        let reranked_ranking: Vec<i64> = rerank_candidates(
+            RerankStrategy::Local,
+            &reranker,
+            &query.query,
+            &query.keywords,
+            &candidates,
+            MAX_RELEVANT_DOCUMENTS,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .into_iter()
+        .map(|document| document.id)
+        .collect();
+
+        let vector_metrics = Metrics::measure(&vector_ranking, &expected);
+        let reranked_metrics = Metrics::measure(&reranked_ranking, &expected);
+        println!(
+            "[{}] {:<60} expected {:?}\n  vector   {:?} {}\n  reranked {:?} {}",
+            name,
+            query.query,
+            query.expected_ids,
+            vector_ranking,
+            vector_metrics.summary(),
+            reranked_ranking,
+            reranked_metrics.summary()
+        );
+        vector_total.accumulate(&vector_metrics);
+        reranked_total.accumulate(&reranked_metrics);
+    }
+    Ok((
+        vector_total.averaged(corpus.queries.len()),
+        reranked_total.averaged(corpus.queries.len()),
+    ))
+}
+
+#[tokio::test]
+async fn evaluate_retrieval_on_fixture_corpus() -> Result<()> {
+    let corpus: Corpus = serde_json::from_str(CORPUS)?;
+    let test_database = open_test_database()?;
+    let (db, sqlite_path) = (&test_database.db, &test_database.path);
+    insert_documents(db, &corpus.documents)?;
+
+    let index_path = test_database.dir.path().join("hnsw");
+    std::fs::create_dir_all(&index_path)?;
+    let stores: Vec<(&str, Box<dyn VectorStore>)> = vec![
+        (
+            "hnsw",
+            Box::new(SimilaritySearch::open(
+                index_path.to_str().unwrap(),
+                "evaluation",
+            )?),
+        ),
+        (
+            "sqlite exact",
+            Box::new(SqliteVectorStore::open(
+                sqlite_path,
+                EMBEDDING_MODEL,
+                SqliteSearchMode::Exact,
+            )?),
+        ),
+        (
+            "sqlite quantized",
+            Box::new(SqliteVectorStore::open(
+                sqlite_path,
+                EMBEDDING_MODEL,
+                SqliteSearchMode::Quantized,
+            )?),
+        ),
+    ];
+    for document in &corpus.documents {
+        let text = amplify_document_text(&document.window_title, &document.text);
+        let vector = embed_text(&text, "").await?;
+        for (_, store) in &stores {
+            store.add(document.id, vector.clone()).await?;
+        }
+    }
+
+    for (name, store) in &stores {
+        let (vector, reranked) = evaluate_store(name, store.as_ref(), db, &corpus).await?;
+        store.persist().await?;
+        println!("[{}] vector search:  {}", name, vector.summary());
+        println!("[{}] local reranker: {}", name, reranked.summary());
+
+        assert!(
+            vector.recall_at_10 >= MIN_VECTOR_RECALL_AT_10,
+            "{}: {}",
+            name,
+            vector.summary()
+        );
+        assert!(
+            vector.mrr >= MIN_VECTOR_MRR,
+            "{}: {}",
+            name,
+            vector.summary()
+        );
+        assert!(
+            reranked.recall_at_4 >= MIN_RERANKED_RECALL_AT_4,
+            "{}: {}",
+            name,
+            reranked.summary()
+        );
+        assert!(
+            reranked.ndcg_at_4 >= MIN_RERANKED_NDCG_AT_4,
+            "{}: {}",
+            name,
+            reranked.summary()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn ranking_metrics_match_hand_computed_values() {
+    let expected: HashSet<i64> = [1, 2].iter().copied().collect();
+    let ranked = [3, 1, 4, 2];
+    assert_eq!(recall_at_k(&ranked, &expected, 2), 0.5);
+    assert_eq!(recall_at_k(&ranked, &expected, 4), 1.0);
+    assert_eq!(reciprocal_rank(&ranked, &expected), 0.5);
+    let ndcg = ndcg_at_k(&ranked, &expected, 4);
+    let dcg = 1.0 / 3f64.log2() + 1.0 / 5f64.log2();
+    let ideal = 1.0 + 1.0 / 3f64.log2();
+    assert!((ndcg - dcg / ideal).abs() < 1e-9);
+    assert_eq!(reciprocal_rank(&[5, 6], &expected), 0.0);
+}
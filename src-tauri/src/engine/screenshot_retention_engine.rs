@@ -0,0 +1,318 @@
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use log::{error, info};
+use tauri::AppHandle;
+
+use crate::configuration::state::ServiceAccess;
+use crate::repository::settings_repository::get_setting_value;
+
+pub const SCREENSHOT_RETENTION_SETTING: &str = "screenshot_retention";
+pub const SCREENSHOT_RETENTION_COUNT_SETTING: &str = "screenshot_retention_count";
+pub const SCREENSHOT_FORMAT_SETTING: &str = "screenshot_format";
+pub const SCREENSHOT_MAX_WIDTH_SETTING: &str = "screenshot_max_width";
+const RETENTION_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const JPEG_QUALITY: u8 = 80;
+
+/// Which screenshots are kept on disk. By default none are: a capture is only needed in
+/// memory to recognise its text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenshotRetention {
+    None,
+    /// The most recent screenshots, up to this many.
+    Last(usize),
+    /// Screenshots taken within this many days.
+    Days(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenshotFormat {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl ScreenshotFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Jpeg => "jpg",
+            ScreenshotFormat::WebP => "webp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub retention: ScreenshotRetention,
+    pub format: ScreenshotFormat,
+    /// Wider screenshots are scaled down to this width before they are saved.
+    pub max_width: Option<u32>,
+}
+
+impl RetentionPolicy {
+    /// Reads the policy from its settings: the retention is `none`, `last` or `days`, with the
+    /// number of screenshots or days as the count. Anything invalid keeps no screenshots.
+    pub fn from_settings(retention: &str, count: &str, format: &str, max_width: &str) -> Self {
+        let count = count.trim().parse::<u32>().ok().filter(|count| *count > 0);
+        let retention = match (retention.trim(), count) {
+            ("last", Some(count)) => ScreenshotRetention::Last(count as usize),
+            ("days", Some(count)) => ScreenshotRetention::Days(count),
+            _ => ScreenshotRetention::None,
+        };
+        let format = match format.trim() {
+            "jpeg" | "jpg" => ScreenshotFormat::Jpeg,
+            "webp" => ScreenshotFormat::WebP,
+            _ => ScreenshotFormat::Png,
+        };
+        RetentionPolicy {
+            retention,
+            format,
+            max_width: max_width.trim().parse().ok().filter(|width| *width > 0),
+        }
+    }
+}
+
+pub fn read_retention_policy(app_handle: &AppHandle) -> RetentionPolicy {
+    app_handle.db(|db| {
+        let read = |key: &str| get_setting_value(db, key);
+        RetentionPolicy::from_settings(
+            &read(SCREENSHOT_RETENTION_SETTING),
+            &read(SCREENSHOT_RETENTION_COUNT_SETTING),
+            &read(SCREENSHOT_FORMAT_SETTING),
+            &read(SCREENSHOT_MAX_WIDTH_SETTING),
+        )
+    })
+}
+
+pub fn screenshots_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir
+        .join("task-mining-resources")
+        .join("screenshots")
+}
+
+/// Writes the screenshot to `dir` as the policy asks, named after `timestamp`. Returns the
+/// path, or None when screenshots are not kept or saving failed.
+pub fn save_screenshot(
+    dir: &Path,
+    timestamp: DateTime<Local>,
+    image: &DynamicImage,
+    policy: &RetentionPolicy,
+) -> Option<PathBuf> {
+    if policy.retention == ScreenshotRetention::None {
+        return None;
+    }
+    let resized;
+    let image = match policy.max_width {
+        Some(max_width) if image.width() > max_width => {
+            let height = (image.height() as u64 * max_width as u64 / image.width() as u64).max(1);
+            resized = image.resize_exact(max_width, height as u32, FilterType::Triangle);
+            &resized
+        }
+        _ => image,
+    };
+    let path = dir.join(format!(
+        "{}.{}",
+        timestamp.format("%Y-%m-%d_%H-%M-%S"),
+        policy.format.extension()
+    ));
+    let result = match policy.format {
+        ScreenshotFormat::Png => image.save_with_format(&path, ImageFormat::Png),
+        // Neither format stores the alpha channel of the capture usefully
+        ScreenshotFormat::WebP => {
+            DynamicImage::ImageRgb8(image.to_rgb8()).save_with_format(&path, ImageFormat::WebP)
+        }
+        ScreenshotFormat::Jpeg => File::create(&path)
+            .map_err(image::ImageError::from)
+            .and_then(|file| {
+                JpegEncoder::new_with_quality(BufWriter::new(file), JPEG_QUALITY)
+                    .encode_image(&image.to_rgb8())
+            }),
+    };
+    match result {
+        Ok(()) => Some(path),
+        Err(error) => {
+            error!("Failed to save screenshot {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+/// The screenshots the retention no longer allows, from a list of files and when each was
+/// written.
+pub fn expired_screenshots(
+    mut files: Vec<(PathBuf, SystemTime)>,
+    retention: ScreenshotRetention,
+    now: SystemTime,
+) -> Vec<PathBuf> {
+    // Newest first
+    files.sort_by_key(|(_, written)| Reverse(*written));
+    match retention {
+        ScreenshotRetention::None => files.into_iter().map(|(path, _)| path).collect(),
+        ScreenshotRetention::Last(count) => files
+            .into_iter()
+            .skip(count)
+            .map(|(path, _)| path)
+            .collect(),
+        ScreenshotRetention::Days(days) => {
+            let max_age = Duration::from_secs(days as u64 * 24 * 60 * 60);
+            files
+                .into_iter()
+                .filter(|(_, written)| match now.duration_since(*written) {
+                    Ok(age) => age > max_age,
+                    Err(_) => false,
+                })
+                .map(|(path, _)| path)
+                .collect()
+        }
+    }
+}
+
+/// Deletes the screenshots in `dir` that the retention no longer allows.
+pub fn enforce_retention(dir: &Path, retention: ScreenshotRetention) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let files: Vec<(PathBuf, SystemTime)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            Some((entry.path(), metadata.modified().ok()?))
+        })
+        .collect();
+    let expired = expired_screenshots(files, retention, SystemTime::now());
+    if !expired.is_empty() {
+        info!("Deleting {} expired screenshots", expired.len());
+    }
+    for path in expired {
+        if let Err(error) = fs::remove_file(&path) {
+            error!("Failed to remove screenshot {}: {}", path.display(), error);
+        }
+    }
+}
+
+/// Applies the retention setting now and then every few minutes, so screenshots do not
+/// outlive it while the app keeps running.
+pub fn start_retention_task(app_handle: AppHandle) {
+    let dir = match app_handle.path_resolver().app_data_dir() {
+        Some(app_data_dir) => screenshots_dir(&app_data_dir),
+        None => return,
+    };
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let retention = read_retention_policy(&app_handle).retention;
+            let dir = dir.clone();
+            let _ =
+                tauri::async_runtime::spawn_blocking(move || enforce_retention(&dir, retention))
+                    .await;
+            tokio::time::sleep(RETENTION_CHECK_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use chrono::Local;
+    use image::{DynamicImage, RgbaImage};
+
+    use super::{
+        expired_screenshots, save_screenshot, RetentionPolicy, ScreenshotFormat,
+        ScreenshotRetention,
+    };
+
+    fn files(now: SystemTime, ages_in_hours: &[u64]) -> Vec<(PathBuf, SystemTime)> {
+        ages_in_hours
+            .iter()
+            .map(|hours| {
+                (
+                    PathBuf::from(format!("{}h.png", hours)),
+                    now - Duration::from_secs(hours * 60 * 60),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn retention_decides_which_screenshots_expire() {
+        let now = SystemTime::now();
+        let names = |paths: Vec<PathBuf>| -> Vec<String> {
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect()
+        };
+
+        let all = files(now, &[30, 1, 50, 5]);
+        assert_eq!(
+            names(expired_screenshots(
+                all.clone(),
+                ScreenshotRetention::None,
+                now
+            )),
+            vec!["1h.png", "5h.png", "30h.png", "50h.png"]
+        );
+        assert_eq!(
+            names(expired_screenshots(
+                all.clone(),
+                ScreenshotRetention::Last(2),
+                now
+            )),
+            vec!["30h.png", "50h.png"]
+        );
+        assert_eq!(
+            names(expired_screenshots(all, ScreenshotRetention::Days(1), now)),
+            vec!["30h.png", "50h.png"]
+        );
+    }
+
+    #[test]
+    fn policy_is_read_from_settings() {
+        assert_eq!(
+            RetentionPolicy::from_settings("last", "20", "webp", "1280"),
+            RetentionPolicy {
+                retention: ScreenshotRetention::Last(20),
+                format: ScreenshotFormat::WebP,
+                max_width: Some(1280),
+            }
+        );
+        assert_eq!(
+            RetentionPolicy::from_settings("days", "0", "", ""),
+            RetentionPolicy {
+                retention: ScreenshotRetention::None,
+                format: ScreenshotFormat::Png,
+                max_width: None,
+            }
+        );
+    }
+
+    #[test]
+    fn screenshots_are_saved_only_when_kept_and_downscaled() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(2000, 1000));
+        let mut policy = RetentionPolicy::from_settings("", "", "jpeg", "500");
+        assert_eq!(
+            save_screenshot(dir.path(), Local::now(), &image, &policy),
+            None
+        );
+
+        policy.retention = ScreenshotRetention::Last(5);
+        let path = save_screenshot(dir.path(), Local::now(), &image, &policy).unwrap();
+        assert_eq!(path.extension().unwrap(), "jpg");
+        let saved = image::open(&path).unwrap();
+        assert_eq!((saved.width(), saved.height()), (500, 250));
+    }
+}
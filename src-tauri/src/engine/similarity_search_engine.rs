@@ -1,104 +1,753 @@
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::create_dir_all;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, bail, Error, Result};
+use async_trait::async_trait;
+use futures::FutureExt;
 use hnsw_rs::prelude::*;
-use log::{debug, error, info};
+use lazy_static::lazy_static;
+use log::{debug, error, info, warn};
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::Mutex;
 
-use crate::repository::vector_db_repository::compute_vector_embedding;
+use crate::engine::embedding_cache_engine;
+use crate::engine::vector_store_engine::VectorFilter;
+use crate::repository::vector_db_repository::{
+    compute_vector_embedding, compute_vector_embeddings, EMBEDDING_MODEL,
+};
+use crate::repository::vector_log_repository::{LogRecord, VectorLog};
 
+/// Default number of results for a search.
 pub const TOPK: usize = 10;
-pub const MAX_NB_CONNECTION: usize = TOPK;
+pub const MAX_NB_CONNECTION: usize = 16;
 pub const MAX_ELEMENTS: usize = 100_000;
 pub const MAX_LAYERS: usize = 24;
 pub const EF_CONSTRUCTION: usize = 400;
+pub const EF_SEARCH: usize = 64;
+
+// A segment is rebuilt without its tombstoned and superseded points once they make up this
+// share of it
+const COMPACTION_DEAD_SHARE: f64 = 0.2;
+
+// Collections saved before graph parameters were recorded were built with M = TOPK
+const LEGACY_MAX_NB_CONNECTION: usize = 10;
 
 pub const MAX_INFLIGHT_COMMANDS: usize = 100;
 
-fn get_db<'a>() -> Hnsw<'a, f32, DistCosine> {
-    Hnsw::new(
-        MAX_NB_CONNECTION,
-        MAX_ELEMENTS,
-        MAX_LAYERS,
-        EF_CONSTRUCTION,
-        DistCosine,
-    )
+// A command that stops the worker this many times is dropped instead of retried again
+const MAX_COMMAND_ATTEMPTS: u32 = 2;
+const RESTART_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+// The supervisor gives up after this many restarts in a row that did not stay up for
+// MAX_RESTART_BACKOFF
+const MAX_CONSECUTIVE_RESTARTS: u32 = 5;
+
+/// Parameters fixed when the graph is built. Changing them requires a rebuild.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct GraphParameters {
+    pub max_nb_connection: usize,
+    pub ef_construction: usize,
+    pub max_elements: usize,
+    pub max_layers: usize,
+}
+
+impl Default for GraphParameters {
+    fn default() -> Self {
+        GraphParameters {
+            max_nb_connection: MAX_NB_CONNECTION,
+            ef_construction: EF_CONSTRUCTION,
+            max_elements: MAX_ELEMENTS,
+            max_layers: MAX_LAYERS,
+        }
+    }
+}
+
+impl GraphParameters {
+    fn legacy() -> Self {
+        GraphParameters {
+            max_nb_connection: LEGACY_MAX_NB_CONNECTION,
+            ..GraphParameters::default()
+        }
+    }
+
+    fn build<'a>(&self) -> Hnsw<'a, f32, DistCosine> {
+        Hnsw::new(
+            self.max_nb_connection,
+            self.max_elements,
+            self.max_layers,
+            self.ef_construction,
+            DistCosine,
+        )
+    }
+}
+
+/// Parameters chosen per query, independent of how the graph was built.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchParameters {
+    pub k: usize,
+    /// Size of the dynamic candidate list; larger values trade speed for recall.
+    pub ef_search: usize,
+}
+
+impl SearchParameters {
+    pub fn with_k(k: usize) -> Self {
+        SearchParameters {
+            k,
+            ef_search: EF_SEARCH.max(k),
+        }
+    }
+}
+
+/// Bookkeeping persisted next to the HNSW dump as `<collection>.meta.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IndexMetadata {
+    pub model: Option<String>,
+    // hnsw_rs cannot delete points, so removed IDs are filtered out of search results
+    pub tombstones: BTreeSet<usize>,
+    #[serde(default)]
+    pub graph: Option<GraphParameters>,
+    /// Last log record included in the snapshot this metadata belongs to.
+    #[serde(default)]
+    pub wal_sequence: u64,
+}
+
+impl IndexMetadata {
+    fn load(dir_path: &Path, collection_name: &str) -> Self {
+        let path = dir_path.join(format!("{}.meta.json", collection_name));
+        std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writing `<collection>_new.meta.json` is what commits a save; `finalize_pending_save`
+    /// then moves the files into place.
+    fn save(&self, dir_path: &Path, collection_name: &str) -> Result<()> {
+        let temp_path = dir_path.join(format!("{}_tmp.meta.json", collection_name));
+        std::fs::write(&temp_path, serde_json::to_string(self)?)?;
+        sync_file(&temp_path)?;
+        std::fs::rename(
+            temp_path,
+            dir_path.join(format!("{}_new.meta.json", collection_name)),
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IndexStats {
+    pub model: Option<String>,
+    pub graph: GraphParameters,
+    pub segment_count: usize,
+    pub point_count: usize,
+    pub tombstoned_ids: Vec<usize>,
+    pub indexed_ids: Vec<usize>,
+    /// Number of points per vector dimension; more than one entry means mixed models.
+    pub dimensions: BTreeMap<usize, usize>,
 }
 
+#[derive(Clone)]
 enum HnswCommand {
-    Save,
-    Add(Vec<f32>, usize),
-    Lookup(Vec<f32>, usize, Sender<Result<Vec<(usize, f32)>, Error>>),
+    Save(Option<Sender<Result<(), Error>>>),
+    Add(Vec<f32>, usize, Sender<Result<(), Error>>),
+    Lookup(
+        Vec<f32>,
+        SearchParameters,
+        Option<VectorFilter>,
+        Sender<Result<Vec<(usize, f32)>, Error>>,
+    ),
+    Tombstone(Vec<usize>, Sender<Result<(), Error>>),
+    Stats(Sender<Result<IndexStats, Error>>),
     Shutdown,
+    #[cfg(test)]
+    Crash,
+    #[cfg(test)]
+    AddThenCrash(Vec<f32>, usize),
+}
+
+/// A command the worker has taken off the queue but not finished, kept so that it can be
+/// retried if the worker dies while processing it.
+struct InFlightCommand {
+    command: HnswCommand,
+    attempts: u32,
+    /// Set once the change the command makes is in the log.
+    logged: bool,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerStatus {
+    Running,
+    Restarting,
+    Failed,
+}
+
+/// Reported whenever the worker of a collection stops unexpectedly or comes back up.
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkerHealth {
+    pub collection: String,
+    pub status: WorkerStatus,
+    pub restarts: u32,
+    pub error: Option<String>,
+}
+
+lazy_static! {
+    static ref WORKER_HEALTH: broadcast::Sender<WorkerHealth> = broadcast::channel(16).0;
+}
+
+/// Receives a `WorkerHealth` for every restart of any collection's worker.
+pub fn subscribe_worker_health() -> broadcast::Receiver<WorkerHealth> {
+    WORKER_HEALTH.subscribe()
+}
+
+fn report_health(health: WorkerHealth) {
+    // Sending only fails when nobody is listening
+    let _ = WORKER_HEALTH.send(health);
+}
+
+/// In-memory state of an open collection. Segments loaded from disk borrow the `HnswIo`
+/// they were read with, hence the lifetime.
+struct LoadedCollection<'a> {
+    dir_path: PathBuf,
+    collection_name: String,
+    graph: GraphParameters,
+    segments: Vec<Hnsw<'a, f32, DistCosine>>,
+    dirty: Vec<bool>,
+    // Segment holding the current vector of each id; copies in other segments are stale
+    current_segments: HashMap<usize, usize>,
+    metadata: IndexMetadata,
+    log: VectorLog,
+}
+
+impl<'a> LoadedCollection<'a> {
+    /// Takes the segments read from disk, creating the first one for a new collection, and
+    /// replays the log records that the snapshot does not cover yet.
+    fn open(
+        dir_path: &Path,
+        collection_name: &str,
+        graph_parameters: GraphParameters,
+        mut segments: Vec<Hnsw<'a, f32, DistCosine>>,
+    ) -> Result<Self> {
+        let mut metadata = IndexMetadata::load(dir_path, collection_name);
+        let is_new = segments.is_empty();
+        let graph = if is_new {
+            let graph = *metadata.graph.get_or_insert(graph_parameters);
+            segments.push(graph.build());
+            graph
+        } else {
+            // The dumps carry their own graph parameters; only record them if unknown
+            *metadata.graph.get_or_insert_with(GraphParameters::legacy)
+        };
+        let (log, records) = VectorLog::open(
+            &dir_path.join(format!("{}.wal", collection_name)),
+            metadata.wal_sequence,
+        )?;
+
+        let mut collection = LoadedCollection {
+            dir_path: dir_path.to_path_buf(),
+            collection_name: collection_name.to_string(),
+            graph,
+            // A new collection is written on the first save even if it stays empty
+            dirty: vec![is_new; segments.len()],
+            current_segments: current_segments(&segments),
+            segments,
+            metadata,
+            log,
+        };
+        let snapshot_sequence = collection.metadata.wal_sequence;
+        let mut replayed = 0;
+        for (sequence, record) in records {
+            if sequence > snapshot_sequence {
+                collection.apply(record);
+                replayed += 1;
+            }
+        }
+        if replayed > 0 {
+            info!(
+                "Replayed {} logged changes into collection {}",
+                replayed, collection_name
+            );
+        }
+        Ok(collection)
+    }
+
+    /// Logs a change before applying it, so that it survives a crash before the next save.
+    /// A change that cannot be logged is not applied.
+    fn record(&mut self, record: LogRecord) -> Result<()> {
+        self.log_change(&record)?;
+        self.apply(record);
+        Ok(())
+    }
+
+    fn log_change(&mut self, record: &LogRecord) -> Result<()> {
+        self.log.append(record).map_err(|e| {
+            anyhow!(
+                "Failed to log change to collection {}: {}",
+                self.collection_name,
+                e
+            )
+        })?;
+        Ok(())
+    }
+
+    fn apply(&mut self, record: LogRecord) {
+        match record {
+            LogRecord::Add { id, vector } => self.insert(vector, id),
+            LogRecord::Tombstone { id } => {
+                self.metadata.tombstones.insert(id);
+            }
+        }
+    }
+
+    fn insert(&mut self, vector: Vec<f32>, id: usize) {
+        // hnsw_rs sizes its layers for max_elements, so a full segment is left as is and
+        // new points go to a fresh one
+        if self
+            .segments
+            .last()
+            .map_or(true, |db| db.get_nb_point() >= self.graph.max_elements)
+        {
+            info!(
+                "Starting HNSW segment {} of collection {}",
+                self.segments.len(),
+                self.collection_name
+            );
+            self.segments.push(self.graph.build());
+            self.dirty.push(true);
+        }
+        let last = self.segments.len() - 1;
+        self.segments[last].insert((&vector, id));
+        self.dirty[last] = true;
+        self.current_segments.insert(id, last);
+        self.metadata.tombstones.remove(&id);
+        if self.metadata.model.is_none() {
+            self.metadata.model = Some(EMBEDDING_MODEL.to_string());
+        }
+    }
+
+    /// Writes the changed segments, commits them with the metadata and moves them into
+    /// place; only then is the log emptied. Promoting every save right away means the
+    /// pending files on disk never mix two saves.
+    fn snapshot(&mut self) -> Result<()> {
+        self.compact();
+        for (segment, db) in self.segments.iter().enumerate() {
+            if self.dirty[segment] {
+                dump_segment(
+                    db,
+                    &self.dir_path,
+                    &segment_name(&self.collection_name, segment),
+                )?;
+            }
+        }
+        self.metadata.wal_sequence = self.log.sequence();
+        self.metadata.save(&self.dir_path, &self.collection_name)?;
+        sync_dir(&self.dir_path);
+        finalize_pending_save(&self.dir_path, &self.collection_name)?;
+        self.dirty.iter_mut().for_each(|dirty| *dirty = false);
+        self.log.truncate()?;
+        Ok(())
+    }
+
+    /// Rebuilds the segments in which many points are tombstoned or superseded, leaving
+    /// those points out. Tombstones of ids that no segment holds anymore are dropped.
+    fn compact(&mut self) {
+        let mut compacted = 0;
+        for segment in 0..self.segments.len() {
+            let mut point_count = 0;
+            let mut live_points = Vec::new();
+            for point in self.segments[segment].get_point_indexation().into_iter() {
+                point_count += 1;
+                let id = point.get_origin_id();
+                if !self.metadata.tombstones.contains(&id)
+                    && self.current_segments.get(&id) == Some(&segment)
+                {
+                    live_points.push((point.get_v().to_vec(), id));
+                }
+            }
+            // A segment with no live point left is kept, so that no empty segment is dumped
+            let dead_count = point_count - live_points.len();
+            if live_points.is_empty()
+                || dead_count == 0
+                || (dead_count as f64) < COMPACTION_DEAD_SHARE * point_count as f64
+            {
+                continue;
+            }
+            let db = self.graph.build();
+            for (vector, id) in &live_points {
+                db.insert((vector, *id));
+            }
+            self.segments[segment] = db;
+            self.dirty[segment] = true;
+            compacted += 1;
+        }
+        if compacted > 0 {
+            self.current_segments = current_segments(&self.segments);
+            let current_segments = &self.current_segments;
+            self.metadata
+                .tombstones
+                .retain(|id| current_segments.contains_key(id));
+            info!(
+                "Compacted {} segments of collection {}",
+                compacted, self.collection_name
+            );
+        }
+    }
+
+    fn lookup(
+        &self,
+        vector: &[f32],
+        parameters: SearchParameters,
+        filter: Option<&VectorFilter>,
+    ) -> Vec<(usize, f32)> {
+        let tombstones = &self.metadata.tombstones;
+        let point_count: usize = self.segments.iter().map(|db| db.get_nb_point()).sum();
+        // Fetch a few more to make up for dead points, which compaction keeps to a small
+        // share; the search widens below if they still crowd out too many
+        let dead_count = point_count.saturating_sub(self.current_segments.len());
+        let mut k = parameters.k + dead_count.max(tombstones.len()).min(parameters.k);
+        loop {
+            let mut results = self
+                .segments
+                .iter()
+                .enumerate()
+                .flat_map(|(segment, db)| {
+                    db.search(vector, k, parameters.ef_search.max(k))
+                        .into_iter()
+                        .map(move |result| (segment, result))
+                })
+                .collect::<Vec<_>>();
+            results.sort_by(|(_, a), (_, b)| {
+                a.distance
+                    .partial_cmp(&b.distance)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            let mut seen = BTreeSet::new();
+            let found: Vec<(usize, f32)> = results
+                .iter()
+                .filter(|(segment, result)| {
+                    self.current_segments.get(&result.d_id) == Some(segment)
+                })
+                .map(|(_, result)| result)
+                .filter(|result| !tombstones.contains(&result.d_id))
+                .filter(|result| filter.map_or(true, |filter| filter.accepts(result.d_id as i64)))
+                .filter(|result| seen.insert(result.d_id))
+                .take(parameters.k)
+                .map(|result| (result.d_id, result.distance))
+                .collect();
+            // Filtered, tombstoned and stale points can crowd out the live ones, so widen the
+            // search until enough pass or the whole graph has been searched
+            if found.len() >= parameters.k || k >= point_count {
+                return found;
+            }
+            k = (k * 2).min(point_count);
+        }
+    }
+
+    fn stats(&self) -> IndexStats {
+        let indexed_ids: BTreeSet<usize> = self.current_segments.keys().copied().collect();
+        let mut dimensions = BTreeMap::new();
+        for db in &self.segments {
+            for point in db.get_point_indexation().into_iter() {
+                *dimensions.entry(point.get_v().len()).or_insert(0) += 1;
+            }
+        }
+        IndexStats {
+            model: self.metadata.model.clone(),
+            graph: self.graph,
+            segment_count: self.segments.len(),
+            point_count: self.segments.iter().map(|db| db.get_nb_point()).sum(),
+            tombstoned_ids: self.metadata.tombstones.iter().copied().collect(),
+            indexed_ids: indexed_ids
+                .difference(&self.metadata.tombstones)
+                .copied()
+                .collect(),
+            dimensions,
+        }
+    }
+}
+
+/// Maps every id to the segment holding its current vector. Ids are only ever added to the
+/// newest segment, so a copy in a later segment supersedes the earlier ones.
+fn current_segments(segments: &[Hnsw<f32, DistCosine>]) -> HashMap<usize, usize> {
+    let mut current_segments = HashMap::new();
+    for (segment, db) in segments.iter().enumerate() {
+        for point in db.get_point_indexation().into_iter() {
+            current_segments.insert(point.get_origin_id(), segment);
+        }
+    }
+    current_segments
 }
 
 async fn hnsw_thread_worker(
     db_path: &str,
     collection_name: &str,
-    mut command_reader: Receiver<HnswCommand>,
+    graph_parameters: GraphParameters,
+    command_reader: &mut Receiver<HnswCommand>,
+    in_flight: &mut Option<InFlightCommand>,
+    restarts: u32,
 ) -> Result<()> {
-    let mut reloader = HnswIo::new(std::path::Path::new(db_path), collection_name);
-    let db_res = reloader.load_hnsw::<f32, DistCosine>();
-    let db = match db_res {
-        Ok(db) => db,
-        Err(_) => get_db(),
-    };
+    let dir_path = Path::new(db_path);
+    let mut reloaders: Vec<HnswIo> = (0..segment_count(dir_path, collection_name))
+        .map(|segment| HnswIo::new(dir_path, &segment_name(collection_name, segment)))
+        .collect();
+    let mut segments: Vec<Hnsw<f32, DistCosine>> = Vec::new();
+    for reloader in reloaders.iter_mut() {
+        match reloader.load_hnsw::<f32, DistCosine>() {
+            Ok(db) => segments.push(db),
+            Err(e) => {
+                if !segments.is_empty() {
+                    error!(
+                        "Failed to load HNSW segment {} of collection {}: {}",
+                        segments.len(),
+                        collection_name,
+                        e
+                    );
+                }
+                break;
+            }
+        }
+    }
+    let mut collection =
+        LoadedCollection::open(dir_path, collection_name, graph_parameters, segments)?;
+    if restarts > 0 {
+        info!(
+            "HNSW worker for collection {} restarted after {} failures",
+            collection_name, restarts
+        );
+        report_health(WorkerHealth {
+            collection: collection_name.to_string(),
+            status: WorkerStatus::Running,
+            restarts,
+            error: None,
+        });
+    }
 
     loop {
-        let command = command_reader.recv().await.ok_or(anyhow!(
-            "Failed to receive command, probably the remote peer is no longer  available"
-        ))?;
+        let (command, attempts, logged) = match in_flight.take() {
+            Some(retried) => (retried.command, retried.attempts + 1, retried.logged),
+            None => match command_reader.recv().await {
+                Some(command) => (command, 1, false),
+                None => {
+                    info!("All handles to the HNSW worker were dropped");
+                    return Ok(());
+                }
+            },
+        };
+        *in_flight = Some(InFlightCommand {
+            command: command.clone(),
+            attempts,
+            logged,
+        });
         match command {
-            HnswCommand::Save => {
-                let resulting_name = format!("{}_new", collection_name);
-                let save_res = db.file_dump(std::path::Path::new(db_path), &resulting_name);
-                if let Err(e) = save_res {
+            HnswCommand::Save(responder) => {
+                // A failed save leaves the log in place, so nothing is lost and the
+                // worker keeps serving
+                let result = collection.snapshot();
+                if let Err(e) = &result {
                     error!(
                         "Failed to save HNSW index to path={}, collection={}: {}",
                         db_path, collection_name, e
                     );
-                    return Err(e.into());
                 }
-                let actual_resulting_name = save_res.unwrap();
-
-                let saved_data_file_name = format!("{}.hnsw.data", actual_resulting_name);
-                let saved_graph_file_name = format!("{}.hnsw.graph", actual_resulting_name);
-
-                let save_data_path = std::path::Path::new(db_path).join(saved_data_file_name);
-                let save_graph_path = std::path::Path::new(db_path).join(saved_graph_file_name);
-
-                let new_data_file_name = format!("{}_new.hnsw.data", collection_name);
-                let new_graph_file_name = format!("{}_new.hnsw.graph", collection_name);
-
-                let new_data_path = std::path::Path::new(db_path).join(new_data_file_name);
-                let new_graph_path = std::path::Path::new(db_path).join(new_graph_file_name);
-
-                std::fs::rename(&save_data_path, &new_data_path)?;
-                std::fs::rename(&save_graph_path, &new_graph_path)?;
+                if let Some(responder) = responder {
+                    let _ = responder.send(result).await;
+                }
             }
-            HnswCommand::Add(vector, id) => {
-                // trace!("Adding vector to HNSW index.");
-                db.insert((&vector, id));
+            HnswCommand::Add(vector, id, responder) => {
+                let result = add_once(&mut collection, vector, id, in_flight);
+                let _ = responder.send(result).await;
             }
-            HnswCommand::Lookup(vector, top_k, sender) => {
-                let results = db.search(&vector, top_k, MAX_NB_CONNECTION);
-                let candidates = results
-                    .iter()
-                    .map(|result| (result.d_id, result.distance))
-                    .collect::<Vec<_>>();
-                sender.send(Ok(candidates)).await?;
+            HnswCommand::Lookup(vector, parameters, filter, sender) => {
+                let _ = sender
+                    .send(Ok(collection.lookup(&vector, parameters, filter.as_ref())))
+                    .await;
+            }
+            HnswCommand::Tombstone(ids, responder) => {
+                let result = ids
+                    .into_iter()
+                    .try_for_each(|id| collection.record(LogRecord::Tombstone { id }));
+                let _ = responder.send(result).await;
+            }
+            HnswCommand::Stats(sender) => {
+                let _ = sender.send(Ok(collection.stats())).await;
             }
             HnswCommand::Shutdown => {
                 info!("Shutting down HNSW thread worker");
+                *in_flight = None;
                 break;
             }
+            #[cfg(test)]
+            HnswCommand::Crash => panic!("HNSW worker crash requested by test"),
+            #[cfg(test)]
+            HnswCommand::AddThenCrash(vector, id) => {
+                add_once(&mut collection, vector, id, in_flight)?;
+                if !logged {
+                    panic!("HNSW worker crash requested by test");
+                }
+            }
         }
+        *in_flight = None;
     }
     Ok(())
 }
 
+/// Logs and applies an add. An add that an earlier attempt already logged is skipped: the
+/// restarted worker replayed it from the log, and logging it again would insert it twice on
+/// the next replay.
+fn add_once(
+    collection: &mut LoadedCollection,
+    vector: Vec<f32>,
+    id: usize,
+    in_flight: &mut Option<InFlightCommand>,
+) -> Result<()> {
+    if let Some(InFlightCommand { logged: true, .. }) = in_flight {
+        return Ok(());
+    }
+    let record = LogRecord::Add { id, vector };
+    collection.log_change(&record)?;
+    if let Some(command) = in_flight.as_mut() {
+        command.logged = true;
+    }
+    collection.apply(record);
+    Ok(())
+}
+
+/// Runs the worker of a collection and restarts it whenever it panics or fails. A restarted
+/// worker reloads the collection from the last snapshot plus the log, so only the command
+/// being processed when it died can be affected; that command is retried once more and
+/// the commands queued behind it are served by the new worker.
+async fn supervise_hnsw_worker(
+    db_path: String,
+    collection_name: String,
+    graph_parameters: GraphParameters,
+    mut command_reader: Receiver<HnswCommand>,
+) {
+    let mut in_flight: Option<InFlightCommand> = None;
+    let mut restarts = 0;
+    let mut consecutive_restarts = 0;
+    let mut backoff = RESTART_BACKOFF;
+    loop {
+        let started = Instant::now();
+        let outcome = AssertUnwindSafe(hnsw_thread_worker(
+            &db_path,
+            &collection_name,
+            graph_parameters,
+            &mut command_reader,
+            &mut in_flight,
+            restarts,
+        ))
+        .catch_unwind()
+        .await;
+        let reason = match outcome {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => e.to_string(),
+            Err(panic) => format!("worker panicked: {}", panic_message(panic.as_ref())),
+        };
+        restarts += 1;
+        if started.elapsed() >= MAX_RESTART_BACKOFF {
+            consecutive_restarts = 0;
+            backoff = RESTART_BACKOFF;
+        }
+        consecutive_restarts += 1;
+        error!(
+            "HNSW worker for collection {} stopped: {}",
+            collection_name, reason
+        );
+
+        if let Some(command) = in_flight.take() {
+            if command.attempts >= MAX_COMMAND_ATTEMPTS {
+                error!(
+                    "Dropping HNSW command that stopped the worker {} times",
+                    command.attempts
+                );
+                fail_command(command.command, &reason).await;
+            } else {
+                in_flight = Some(command);
+            }
+        }
+
+        if consecutive_restarts > MAX_CONSECUTIVE_RESTARTS {
+            error!(
+                "Giving up on HNSW worker for collection {} after {} restarts",
+                collection_name, restarts
+            );
+            report_health(WorkerHealth {
+                collection: collection_name.clone(),
+                status: WorkerStatus::Failed,
+                restarts,
+                error: Some(reason.clone()),
+            });
+            command_reader.close();
+            if let Some(command) = in_flight.take() {
+                fail_command(command.command, &reason).await;
+            }
+            while let Some(command) = command_reader.recv().await {
+                fail_command(command, &reason).await;
+            }
+            return;
+        }
+
+        report_health(WorkerHealth {
+            collection: collection_name.clone(),
+            status: WorkerStatus::Restarting,
+            restarts,
+            error: Some(reason),
+        });
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+
+        // The dead worker may have committed a save that was not moved into place yet
+        if let Err(e) = finalize_pending_save(Path::new(&db_path), &collection_name) {
+            error!(
+                "Failed to finalize pending save of collection {}: {}",
+                collection_name, e
+            );
+        }
+    }
+}
+
+/// Answers the caller of a command that will not be processed.
+async fn fail_command(command: HnswCommand, reason: &str) {
+    let error = || anyhow!("HNSW worker failed: {}", reason);
+    match command {
+        HnswCommand::Save(Some(responder)) => {
+            let _ = responder.send(Err(error())).await;
+        }
+        HnswCommand::Lookup(_, _, _, sender) => {
+            let _ = sender.send(Err(error())).await;
+        }
+        HnswCommand::Stats(sender) => {
+            let _ = sender.send(Err(error())).await;
+        }
+        HnswCommand::Add(_, id, responder) => {
+            error!("Dropped vector for document {}: {}", id, reason);
+            let _ = responder.send(Err(error())).await;
+        }
+        HnswCommand::Tombstone(ids, responder) => {
+            error!("Dropped tombstones for documents {:?}: {}", ids, reason);
+            let _ = responder.send(Err(error())).await;
+        }
+        _ => {}
+    }
+}
+
+pub fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
 pub struct SimilaritySearch(
     Option<tokio::task::JoinHandle<()>>,
     Option<Sender<HnswCommand>>,
@@ -116,7 +765,7 @@ impl Drop for SimilaritySearch {
             match tokio::runtime::Handle::try_current() {
                 Ok(handle) => {
                     handle.spawn(async move {
-                        if let Err(e) = sc.send(HnswCommand::Save).await {
+                        if let Err(e) = sc.send(HnswCommand::Save(None)).await {
                             error!("Failed to send HnswCommand::Save: {}", e);
                         }
                         if let Err(e) = sc.send(HnswCommand::Shutdown).await {
@@ -140,71 +789,144 @@ pub type SyncSimilaritySearch = Arc<Mutex<Option<SimilaritySearch>>>;
 const IS_TEST: bool = cfg!(test);
 
 const MAX_CHARS: usize = 7900;
+const TEST_EMBEDDING_DIMENSION: usize = 512;
+
+/// Feature-hashed bag of words used instead of the embeddings API in tests, so that
+/// retrieval behaves like a lexical search and needs no network.
+pub fn deterministic_embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0; TEST_EMBEDDING_DIMENSION];
+    for token in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+    {
+        // FNV-1a, stable across platforms and runs
+        let hash = token.to_lowercase().bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        vector[(hash % TEST_EMBEDDING_DIMENSION as u64) as usize] += 1.0;
+    }
+    vector
+}
+
+/// Computes the embeddings of documents being indexed. The app embeds with the API; tests
+/// can put a slow or failing embedder in its place.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Embeds with the embeddings API, through the embedding cache.
+pub struct ApiEmbedder {
+    pub api_key: String,
+}
 
-async fn get_embedding(text: &str, api_key: &str) -> Result<Vec<f32>> {
+#[async_trait]
+impl Embedder for ApiEmbedder {
+    async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        embed_texts(texts, &self.api_key).await
+    }
+}
+
+/// Computes the embedding of `text`. Call this before locking the shared index: it may
+/// make a network round trip, and nothing in it needs the index.
+pub async fn embed_text(text: &str, api_key: &str) -> Result<Vec<f32>> {
     if IS_TEST {
-        return Ok(vec![0.0; 512]);
+        return Ok(deterministic_embedding(text));
     }
 
-    let truncated_text = if text.len() > MAX_CHARS {
-        &text[..MAX_CHARS]
-    } else {
-        text
-    };
+    let truncated_text = truncate_for_embedding(text);
+
+    if let Some(vector) = embedding_cache_engine::lookup(EMBEDDING_MODEL, truncated_text) {
+        debug!("Embedding cache hit");
+        return Ok(vector);
+    }
 
-    compute_vector_embedding(truncated_text, api_key)
+    let vector = compute_vector_embedding(truncated_text, api_key)
         .await
-        .map_err(|e| anyhow!("{}", e))
+        .map_err(|e| anyhow!("{}", e))?;
+    embedding_cache_engine::store(EMBEDDING_MODEL, truncated_text, &vector);
+    Ok(vector)
+}
+
+/// Computes the embeddings of several texts, sending the ones missing from the cache in
+/// a single request. The vectors are returned in the order of `texts`.
+pub async fn embed_texts(texts: &[String], api_key: &str) -> Result<Vec<Vec<f32>>> {
+    if IS_TEST {
+        return Ok(texts
+            .iter()
+            .map(|text| deterministic_embedding(text))
+            .collect());
+    }
+
+    let truncated_texts: Vec<&str> = texts
+        .iter()
+        .map(|text| truncate_for_embedding(text))
+        .collect();
+    let mut vectors: Vec<Option<Vec<f32>>> = truncated_texts
+        .iter()
+        .map(|text| embedding_cache_engine::lookup(EMBEDDING_MODEL, text))
+        .collect();
+    let missing: Vec<usize> = (0..vectors.len())
+        .filter(|index| vectors[*index].is_none())
+        .collect();
+    debug!(
+        "Embedding cache hits: {} of {}",
+        texts.len() - missing.len(),
+        texts.len()
+    );
+
+    if !missing.is_empty() {
+        let inputs: Vec<String> = missing
+            .iter()
+            .map(|index| truncated_texts[*index].to_string())
+            .collect();
+        let computed = compute_vector_embeddings(&inputs, api_key)
+            .await
+            .map_err(|e| anyhow!("{}", e))?;
+        for (index, vector) in missing.into_iter().zip(computed) {
+            embedding_cache_engine::store(EMBEDDING_MODEL, truncated_texts[index], &vector);
+            vectors[index] = Some(vector);
+        }
+    }
+    Ok(vectors.into_iter().flatten().collect())
+}
+
+fn truncate_for_embedding(text: &str) -> &str {
+    // Cut on a character boundary, as slicing inside a multi-byte character panics
+    text.char_indices()
+        .nth(MAX_CHARS)
+        .map_or(text, |(index, _)| &text[..index])
 }
 
 impl SimilaritySearch {
     pub fn open(db_path: &str, collection_name: &str) -> Result<Self> {
+        Self::open_with_parameters(db_path, collection_name, GraphParameters::default())
+    }
+
+    /// Opens a collection, building a new one with `graph_parameters` if none exists yet.
+    /// An existing collection keeps the parameters recorded in its metadata.
+    pub fn open_with_parameters(
+        db_path: &str,
+        collection_name: &str,
+        graph_parameters: GraphParameters,
+    ) -> Result<Self> {
         info!(
             "Opening HNSW instance: {}, collection: {}",
             db_path, collection_name
         );
-        let dir_path = std::path::Path::new(db_path);
+        let dir_path = Path::new(db_path);
 
         if !dir_path.exists() {
             create_dir_all(dir_path)?;
         }
 
-        // if exist 'collection_name_new.hnsw.{data, graph}' rename them to 'collection_name.hnsw.{data, graph}'
-        let new_data_path = format!("{}_new.hnsw.data", collection_name);
-        let new_graph_path = format!("{}_new.hnsw.graph", collection_name);
-
-        let data_path = format!("{}.hnsw.data", collection_name);
-        let graph_path = format!("{}.hnsw.graph", collection_name);
-
-        let new_data_path = dir_path.join(new_data_path);
-        let new_graph_path = dir_path.join(new_graph_path);
-
-        let data_path = dir_path.join(data_path);
-        let graph_path = dir_path.join(graph_path);
-
-        if new_data_path.exists() {
-            std::fs::rename(&new_data_path, &data_path)?;
-        }
-
-        if new_graph_path.exists() {
-            std::fs::rename(&new_graph_path, &graph_path)?;
-        }
+        finalize_pending_save(dir_path, collection_name)?;
 
         let (command_sender, command_receiver) = tokio::sync::mpsc::channel(MAX_INFLIGHT_COMMANDS);
-        async fn worker(
-            db_path: String,
-            collection_name: String,
-            command_receiver: Receiver<HnswCommand>,
-        ) {
-            let res = hnsw_thread_worker(&db_path, &collection_name, command_receiver).await;
-            if let Err(e) = res {
-                panic!("HNSW thread worker failed: {}", e);
-            }
-        }
-
-        let db = tokio::spawn(worker(
+        let db = tokio::spawn(supervise_hnsw_worker(
             db_path.to_string(),
             collection_name.to_string(),
+            graph_parameters,
             command_receiver,
         ));
 
@@ -213,13 +935,87 @@ impl SimilaritySearch {
 
     pub async fn sync(&self) -> Result<()> {
         info!("Sending HnswCommand::Save");
-        self.1.as_ref().unwrap().send(HnswCommand::Save).await?;
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        self.1
+            .as_ref()
+            .unwrap()
+            .send(HnswCommand::Save(Some(sender)))
+            .await?;
         info!("Waiting for HnswCommand::Save");
+        receiver.recv().await.ok_or(anyhow!(
+            "Failed to receive save result, probably the remote peer is no longer available"
+        ))?
+    }
+
+    /// Hides the given document IDs from future lookups, once the change is logged.
+    pub async fn tombstone(&self, ids: Vec<usize>) -> Result<()> {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        self.1
+            .as_ref()
+            .ok_or(anyhow!("Command sender is None"))?
+            .send(HnswCommand::Tombstone(ids, sender))
+            .await?;
+        receiver.recv().await.ok_or(anyhow!(
+            "Failed to receive tombstone result, probably the remote peer is no longer available"
+        ))?
+    }
+
+    pub async fn stats(&self) -> Result<IndexStats> {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        self.1
+            .as_ref()
+            .ok_or(anyhow!("Command sender is None"))?
+            .send(HnswCommand::Stats(sender))
+            .await?;
+        receiver.recv().await.ok_or(anyhow!(
+            "Failed to receive index stats, probably the remote peer is no longer available"
+        ))?
+    }
+
+    #[cfg(test)]
+    async fn crash_worker(&self) -> Result<()> {
+        self.1
+            .as_ref()
+            .ok_or(anyhow!("Command sender is None"))?
+            .send(HnswCommand::Crash)
+            .await?;
+        Ok(())
+    }
+
+    /// Makes the worker log an add and then crash before it answers.
+    #[cfg(test)]
+    async fn add_then_crash_worker(&self, id: i64, text: &str) -> Result<()> {
+        self.1
+            .as_ref()
+            .ok_or(anyhow!("Command sender is None"))?
+            .send(HnswCommand::AddThenCrash(
+                deterministic_embedding(text),
+                id as usize,
+            ))
+            .await?;
+        Ok(())
+    }
+
+    /// Saves the index and stops the worker, waiting for both to complete.
+    pub async fn close(&mut self) -> Result<()> {
+        self.sync().await?;
+        self.discard().await
+    }
+
+    /// Stops the worker without saving. Changes made since the last save stay in the log
+    /// and are replayed on the next open.
+    pub async fn discard(&mut self) -> Result<()> {
+        if let Some(sender) = self.1.take() {
+            sender.send(HnswCommand::Shutdown).await?;
+        }
+        if let Some(handle) = self.0.take() {
+            handle.await?;
+        }
         Ok(())
     }
 
     pub async fn add(&self, id: i64, text: &str, api_key: &str) -> Result<()> {
-        let vector_res = get_embedding(text, api_key).await;
+        let vector_res = embed_text(text, api_key).await;
         let vector = match vector_res {
             Ok(v) => v,
             Err(e) => {
@@ -227,14 +1023,24 @@ impl SimilaritySearch {
                 return Err(anyhow!("Failed to compute vector embedding: {}", e));
             }
         };
+        self.add_embedding(id, vector).await
+    }
 
+    /// Adds a vector computed with `embed_text`, returning once the change is logged.
+    pub async fn add_embedding(&self, id: i64, vector: Vec<f32>) -> Result<()> {
         match &self.1 {
             Some(sender) => {
-                if let Err(e) = sender.send(HnswCommand::Add(vector, id as usize)).await {
+                let (responder, mut receiver) = tokio::sync::mpsc::channel(1);
+                if let Err(e) = sender
+                    .send(HnswCommand::Add(vector, id as usize, responder))
+                    .await
+                {
                     error!("Failed to send HnswCommand::Add: {}", e);
                     return Err(anyhow!("Failed to send HnswCommand::Add: {}", e));
                 }
-                Ok(())
+                receiver.recv().await.ok_or(anyhow!(
+                    "Failed to receive add result, probably the remote peer is no longer available"
+                ))?
             }
             None => {
                 error!("Command sender is None");
@@ -248,12 +1054,22 @@ impl SimilaritySearch {
         query_text: &str,
         top_k: usize,
         api_key: &str,
+    ) -> Result<Vec<(usize, f32)>> {
+        self.search(query_text, SearchParameters::with_k(top_k), api_key)
+            .await
+    }
+
+    pub async fn search(
+        &self,
+        query_text: &str,
+        parameters: SearchParameters,
+        api_key: &str,
     ) -> Result<Vec<(usize, f32)>> {
         info!(
             "Performing similarity search in HNSW Index: Query={}",
             query_text
         );
-        let query_vector_res = get_embedding(query_text, api_key).await;
+        let query_vector_res = embed_text(query_text, api_key).await;
         let query_vector = match query_vector_res {
             Ok(v) => v,
             Err(e) => {
@@ -262,16 +1078,37 @@ impl SimilaritySearch {
             }
         };
         debug!("Computed query vector embedding: {:?}", query_vector);
+        self.search_embedding(query_vector, parameters).await
+    }
 
-        if top_k > MAX_NB_CONNECTION {
-            bail!("top_k exceeds MAX_NB_CONNECTION");
-        }
+    /// Searches with a query vector computed with `embed_text`.
+    pub async fn search_embedding(
+        &self,
+        query_vector: Vec<f32>,
+        parameters: SearchParameters,
+    ) -> Result<Vec<(usize, f32)>> {
+        self.search_embedding_filtered(query_vector, parameters, None)
+            .await
+    }
 
+    /// Searches for a vector computed with `embed_text`, returning only documents accepted
+    /// by `filter`.
+    pub async fn search_embedding_filtered(
+        &self,
+        query_vector: Vec<f32>,
+        parameters: SearchParameters,
+        filter: Option<VectorFilter>,
+    ) -> Result<Vec<(usize, f32)>> {
         let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
         self.1
             .as_ref()
             .unwrap()
-            .send(HnswCommand::Lookup(query_vector, top_k, sender))
+            .send(HnswCommand::Lookup(
+                query_vector,
+                parameters,
+                filter,
+                sender,
+            ))
             .await?;
         let candidates_res = receiver.recv().await.ok_or(anyhow!(
             "Failed to receive candidates, probably the remote peer is no longer available"
@@ -289,11 +1126,211 @@ impl SimilaritySearch {
     }
 }
 
+/// Segment 0 keeps the collection name so that single-segment collections are laid out
+/// exactly as before segments existed.
+fn segment_name(collection_name: &str, segment: usize) -> String {
+    if segment == 0 {
+        collection_name.to_string()
+    } else {
+        format!("{}_seg{}", collection_name, segment)
+    }
+}
+
+fn segment_count(dir_path: &Path, collection_name: &str) -> usize {
+    (1..)
+        .find(|segment| {
+            let name = segment_name(collection_name, *segment);
+            !dir_path.join(format!("{}.hnsw.data", name)).exists()
+                && !dir_path.join(format!("{}_new.hnsw.data", name)).exists()
+        })
+        .unwrap_or(1)
+}
+
+const SEGMENT_EXTENSIONS: [&str; 2] = ["hnsw.data", "hnsw.graph"];
+
+/// Dumps a segment under a temporary name and moves the synced files to
+/// `<basename>_new.*`, where they wait for the metadata to commit the save.
+fn dump_segment(db: &Hnsw<f32, DistCosine>, dir_path: &Path, basename: &str) -> Result<()> {
+    let temp_name = format!("{}_tmp", basename);
+    for extension in SEGMENT_EXTENSIONS {
+        remove_file_if_exists(&dir_path.join(format!("{}.{}", temp_name, extension)))?;
+    }
+    // file_dump picks another name if the requested one is taken
+    let dumped_name = db.file_dump(dir_path, &temp_name)?;
+    for extension in SEGMENT_EXTENSIONS {
+        let dumped_path = dir_path.join(format!("{}.{}", dumped_name, extension));
+        sync_file(&dumped_path)?;
+        std::fs::rename(
+            dumped_path,
+            dir_path.join(format!("{}_new.{}", basename, extension)),
+        )?;
+    }
+    Ok(())
+}
+
+fn sync_file(path: &Path) -> std::io::Result<()> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .sync_all()
+}
+
+// Makes renames durable; directories cannot be opened for syncing on Windows
+fn sync_dir(dir_path: &Path) {
+    #[cfg(unix)]
+    if let Err(e) = std::fs::File::open(dir_path).and_then(|dir| dir.sync_all()) {
+        error!("Failed to sync directory {}: {}", dir_path.display(), e);
+    }
+    #[cfg(not(unix))]
+    let _ = dir_path;
+}
+
+fn remove_file_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Pairs of (pending save, current) paths for every segment of a collection, followed by
+/// its metadata file and change log.
+pub fn collection_files(dir_path: &Path, collection_name: &str) -> Vec<(PathBuf, PathBuf)> {
+    let file_pair = |basename: &str, extension: &str| {
+        (
+            dir_path.join(format!("{}_new.{}", basename, extension)),
+            dir_path.join(format!("{}.{}", basename, extension)),
+        )
+    };
+    let mut files = Vec::new();
+    for segment in 0..segment_count(dir_path, collection_name) {
+        let basename = segment_name(collection_name, segment);
+        for extension in SEGMENT_EXTENSIONS {
+            files.push(file_pair(&basename, extension));
+        }
+    }
+    files.push(file_pair(collection_name, "meta.json"));
+    files.push(file_pair(collection_name, "wal"));
+    files
+}
+
+/// Moves the files of the last committed save into place and removes what an interrupted
+/// save left behind; the log still holds the changes such a save would have written.
+fn finalize_pending_save(dir_path: &Path, collection_name: &str) -> Result<()> {
+    // Collections from before the log existed were saved without a commit marker
+    let is_committed = dir_path
+        .join(format!("{}_new.meta.json", collection_name))
+        .exists()
+        || !dir_path.join(format!("{}.wal", collection_name)).exists();
+    // The metadata comes after the segments, so it is moved into place last
+    for (new_path, path) in collection_files(dir_path, collection_name) {
+        if !new_path.exists() {
+            continue;
+        }
+        if is_committed {
+            std::fs::rename(&new_path, &path)?;
+        } else {
+            warn!("Discarding uncommitted save file {}", new_path.display());
+            std::fs::remove_file(&new_path)?;
+        }
+    }
+    for segment in 0..segment_count(dir_path, collection_name) {
+        for extension in SEGMENT_EXTENSIONS {
+            remove_file_if_exists(&dir_path.join(format!(
+                "{}_tmp.{}",
+                segment_name(collection_name, segment),
+                extension
+            )))?;
+        }
+    }
+    remove_file_if_exists(&dir_path.join(format!("{}_tmp.meta.json", collection_name)))?;
+    sync_dir(dir_path);
+    Ok(())
+}
+
+/// Replaces the files of `target_collection` with those of `source_collection`. Both
+/// collections must be closed; any unsaved pending dump of the target is discarded.
+pub fn promote_collection(
+    db_path: &str,
+    source_collection: &str,
+    target_collection: &str,
+) -> Result<()> {
+    let dir_path = Path::new(db_path);
+    finalize_pending_save(dir_path, source_collection)?;
+    let source_files = collection_files(dir_path, source_collection);
+    for (_, source_path) in &source_files[..2] {
+        if !source_path.exists() {
+            bail!("Collection file {} is missing", source_path.display());
+        }
+    }
+    // The target may have more segments than the source, so clear it completely first
+    for (target_new_path, target_path) in collection_files(dir_path, target_collection) {
+        for path in [target_new_path, target_path] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+    }
+    for (_, source_path) in &source_files {
+        if !source_path.exists() {
+            // Collections saved before metadata existed have no metadata file
+            continue;
+        }
+        let file_name = source_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let target_path = dir_path.join(format!(
+            "{}{}",
+            target_collection,
+            &file_name[source_collection.len()..]
+        ));
+        std::fs::rename(source_path, target_path)?;
+    }
+    info!(
+        "Promoted HNSW collection {} to {}",
+        source_collection, target_collection
+    );
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
     use anyhow::Result;
+    use async_trait::async_trait;
+    use tokio::sync::Mutex;
+
+    use super::{
+        deterministic_embedding, embed_text, subscribe_worker_health, truncate_for_embedding,
+        Embedder, GraphParameters, SearchParameters, SimilaritySearch, SyncSimilaritySearch,
+        WorkerStatus, MAX_CHARS, TOPK,
+    };
+    use crate::engine::embedding_queue_engine::index_documents;
+
+    const SLOW_EMBEDDING_DELAY: Duration = Duration::from_millis(500);
 
-    use super::SimilaritySearch;
+    /// Stands in for a slow embeddings API round trip.
+    struct SlowEmbedder;
+
+    #[async_trait]
+    impl Embedder for SlowEmbedder {
+        async fn embed_texts(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            tokio::time::sleep(SLOW_EMBEDDING_DELAY).await;
+            Ok(texts
+                .iter()
+                .map(|text| deterministic_embedding(text))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn long_texts_are_cut_on_a_character_boundary() {
+        let text = "ł".repeat(MAX_CHARS + 10);
+        assert_eq!(truncate_for_embedding(&text).chars().count(), MAX_CHARS);
+        assert_eq!(truncate_for_embedding("short"), "short");
+    }
 
     #[tokio::test]
     async fn test_similarity_search() -> Result<()> {
@@ -311,4 +1348,338 @@ mod tests {
         assert_eq!(candidates, vec![(1, 0.0)]);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_search_wider_than_graph_connectivity() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let collection_name = "test_collection";
+        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        let point_count = 3 * TOPK;
+        for id in 0..point_count {
+            index
+                .add(id as i64, &format!("report number {} shared words", id), "")
+                .await?;
+        }
+
+        let candidates = index
+            .search(
+                "report number 7 shared words",
+                SearchParameters {
+                    k: point_count,
+                    ef_search: 8,
+                },
+                "",
+            )
+            .await?;
+        assert_eq!(candidates.len(), point_count);
+        assert_eq!(candidates[0].0, 7);
+
+        let stats = index.stats().await?;
+        assert_eq!(stats.graph, GraphParameters::default());
+        index.close().await?;
+        drop(index);
+
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        let candidates = index
+            .top_k("report number 7 shared words", 2 * TOPK, "")
+            .await?;
+        assert_eq!(candidates.len(), 2 * TOPK);
+        assert_eq!(index.stats().await?.graph, GraphParameters::default());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_beyond_segment_capacity() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let collection_name = "test_collection";
+        let graph = GraphParameters {
+            max_elements: 8,
+            ..GraphParameters::default()
+        };
+        let mut index = SimilaritySearch::open_with_parameters(
+            db_path.to_str().unwrap(),
+            collection_name,
+            graph,
+        )?;
+        let point_count = 20;
+        for id in 0..point_count {
+            index
+                .add(id as i64, &format!("captured window {}", id), "")
+                .await?;
+        }
+
+        let stats = index.stats().await?;
+        assert_eq!(stats.point_count, point_count);
+        assert_eq!(stats.segment_count, 3);
+        assert_eq!(stats.indexed_ids, (0..point_count).collect::<Vec<_>>());
+        for id in [0, 9, 19] {
+            let candidates = index
+                .top_k(&format!("captured window {}", id), 1, "")
+                .await?;
+            assert_eq!(candidates[0].0, id);
+        }
+        index.close().await?;
+        drop(index);
+
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        let stats = index.stats().await?;
+        assert_eq!(stats.point_count, point_count);
+        assert_eq!(stats.graph, graph);
+        let candidates = index.top_k("captured window 19", point_count, "").await?;
+        assert_eq!(candidates.len(), point_count);
+        assert_eq!(candidates[0].0, 19);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_readded_id_hides_its_copy_in_an_earlier_segment() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let collection_name = "test_collection";
+        let graph = GraphParameters {
+            max_elements: 2,
+            ..GraphParameters::default()
+        };
+        let mut index = SimilaritySearch::open_with_parameters(
+            db_path.to_str().unwrap(),
+            collection_name,
+            graph,
+        )?;
+        index.add(1, "draft budget spreadsheet", "").await?;
+        index.add(2, "team offsite agenda", "").await?;
+        // Lands in the second segment, next to a newer document
+        index.add(3, "customer interview notes", "").await?;
+        index.add(1, "final budget approved", "").await?;
+
+        let stats = index.stats().await?;
+        assert_eq!(stats.segment_count, 2);
+        assert_eq!(stats.indexed_ids, vec![1, 2, 3]);
+        let candidates = index.top_k("draft budget spreadsheet", 3, "").await?;
+        assert_eq!(candidates.len(), 3);
+        assert!(candidates
+            .iter()
+            .all(|(id, distance)| *id != 1 || *distance > 0.0));
+        index.close().await?;
+        drop(index);
+
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        let candidates = index.top_k("final budget approved", 1, "").await?;
+        assert_eq!(candidates, vec![(1, 0.0)]);
+        let candidates = index.top_k("draft budget spreadsheet", 3, "").await?;
+        assert!(candidates
+            .iter()
+            .all(|(id, distance)| *id != 1 || *distance > 0.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_save_compacts_tombstoned_points() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let collection_name = "test_collection";
+        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        for id in 1..=4 {
+            index
+                .add(id, &format!("weekly status update {}", id), "")
+                .await?;
+        }
+        index.tombstone(vec![1, 2]).await?;
+        index.sync().await?;
+
+        let stats = index.stats().await?;
+        assert_eq!(stats.point_count, 2);
+        assert!(stats.tombstoned_ids.is_empty());
+        assert_eq!(stats.indexed_ids, vec![3, 4]);
+        index.close().await?;
+        drop(index);
+
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        let candidates = index.top_k("weekly status update 1", 4, "").await?;
+        let mut ids: Vec<usize> = candidates.iter().map(|(id, _)| *id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![3, 4]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_not_blocked_by_slow_embedding() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), "test_collection")?;
+        index.add(1, "quarterly revenue report", "").await?;
+        let shared: SyncSimilaritySearch = Arc::new(Mutex::new(Some(index)));
+
+        let writer = tokio::spawn({
+            let shared = shared.clone();
+            async move {
+                let documents = [(2, "meeting notes".to_string())];
+                index_documents(&shared, &documents, &SlowEmbedder).await
+            }
+        });
+        // Let the capture start its slow embedding
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let started = Instant::now();
+        let query_vector = embed_text("quarterly revenue report", "").await?;
+        let candidates = shared
+            .lock()
+            .await
+            .as_ref()
+            .unwrap()
+            .search_embedding(query_vector, SearchParameters::with_k(1))
+            .await?;
+        let elapsed = started.elapsed();
+        assert_eq!(candidates[0].0, 1);
+        assert!(
+            elapsed < SLOW_EMBEDDING_DELAY / 2,
+            "search waited {:?} for the capture's embedding",
+            elapsed
+        );
+        assert!(!writer.is_finished());
+
+        assert!(writer.await?.is_empty());
+        let stats = shared.lock().await.as_ref().unwrap().stats().await?;
+        assert_eq!(stats.indexed_ids, vec![1, 2]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unsaved_inserts_survive_a_crash() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let collection_name = "test_collection";
+        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        index.add(1, "saved before the crash", "").await?;
+        index.sync().await?;
+        index.add(2, "logged but never saved", "").await?;
+        index.tombstone(vec![1]).await?;
+        // Stopping without a save is what a killed app leaves on disk
+        index.discard().await?;
+        // ...along with the temporary files of a save that was cut short
+        std::fs::write(
+            db_path.join(format!("{}_tmp.hnsw.data", collection_name)),
+            b"partial",
+        )?;
+        drop(index);
+
+        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        let stats = index.stats().await?;
+        assert_eq!(stats.indexed_ids, vec![2]);
+        assert_eq!(stats.tombstoned_ids, vec![1]);
+        assert!(!db_path
+            .join(format!("{}_tmp.hnsw.data", collection_name))
+            .exists());
+        let candidates = index.top_k("logged but never saved", 1, "").await?;
+        assert_eq!(candidates, vec![(2, 0.0)]);
+
+        // Once saved, the log is emptied and the snapshot alone holds the changes
+        index.close().await?;
+        assert_eq!(
+            std::fs::metadata(db_path.join(format!("{}.wal", collection_name)))?.len(),
+            0
+        );
+        drop(index);
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        assert_eq!(index.stats().await?.indexed_ids, vec![2]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_save_does_not_reuse_an_earlier_commit() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let collection_name = "test_collection";
+        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        index.add(1, "first saved document", "").await?;
+        index.sync().await?;
+        // A committed save is moved into place at once
+        assert!(db_path
+            .join(format!("{}.meta.json", collection_name))
+            .exists());
+        assert!(!db_path
+            .join(format!("{}_new.meta.json", collection_name))
+            .exists());
+
+        index.add(2, "logged after the save", "").await?;
+        index.discard().await?;
+        // A second save that stopped after dumping part of a segment
+        std::fs::write(
+            db_path.join(format!("{}_new.hnsw.data", collection_name)),
+            b"partial",
+        )?;
+        drop(index);
+
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        assert_eq!(index.stats().await?.indexed_ids, vec![1, 2]);
+        let candidates = index.top_k("first saved document", 1, "").await?;
+        assert_eq!(candidates, vec![(1, 0.0)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retried_add_is_logged_once() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let collection_name = "test_collection";
+        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        index
+            .add_then_crash_worker(1, "logged just before the crash")
+            .await?;
+        // Served by the restarted worker once it has retried the add
+        assert_eq!(index.stats().await?.point_count, 1);
+        index.discard().await?;
+        drop(index);
+
+        // Replaying the log inserts the point once
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        assert_eq!(index.stats().await?.point_count, 1);
+        let candidates = index.top_k("logged just before the crash", 1, "").await?;
+        assert_eq!(candidates, vec![(1, 0.0)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_worker_restarts_after_a_panic() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("test.db");
+        let collection_name = "crashing_collection";
+        let mut health = subscribe_worker_health();
+        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        index.add(1, "added before the panic", "").await?;
+
+        index.crash_worker().await?;
+        // Queued behind the crash and served by the restarted worker
+        index.add(2, "added while the worker was down", "").await?;
+        let candidates = index.top_k("added before the panic", 1, "").await?;
+        assert_eq!(candidates, vec![(1, 0.0)]);
+        let candidates = index
+            .top_k("added while the worker was down", 1, "")
+            .await?;
+        assert_eq!(candidates, vec![(2, 0.0)]);
+
+        let mut statuses = Vec::new();
+        while let Ok(event) = health.try_recv() {
+            if event.collection == collection_name {
+                statuses.push(event.status);
+            }
+        }
+        // The crash command is retried once and then dropped
+        assert_eq!(
+            statuses,
+            vec![
+                WorkerStatus::Restarting,
+                WorkerStatus::Running,
+                WorkerStatus::Restarting,
+                WorkerStatus::Running
+            ]
+        );
+
+        index.close().await?;
+        drop(index);
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
+        assert_eq!(index.stats().await?.indexed_ids, vec![1, 2]);
+        Ok(())
+    }
 }
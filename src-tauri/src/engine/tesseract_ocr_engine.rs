@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use image::DynamicImage;
+use log::debug;
+use rusty_tesseract::{get_tesseract_langs, get_tesseract_version, image_to_data, Args, Image};
+
+use crate::engine::ocr_layout_engine::WordBox;
+use crate::engine::ocr_preprocessing_engine::preprocess_image;
+use crate::engine::text_recognition_engine::{missing_languages, OcrEngine, OcrOptions};
+
+// `image_to_data` reports pages, blocks, paragraphs and lines as well as words
+const WORD_LEVEL: i32 = 5;
+
+/// Recognises text with the Tesseract executable installed on the system.
+pub struct TesseractOcrEngine {
+    /// Language packs installed when Tesseract was detected.
+    languages: Vec<String>,
+}
+
+impl TesseractOcrEngine {
+    /// Returns the engine when a Tesseract executable can be run. This runs Tesseract, so
+    /// callers keep the engine rather than detecting it for every screenshot.
+    pub fn detect() -> Result<Self, String> {
+        let version =
+            get_tesseract_version().map_err(|e| format!("Tesseract is not available: {}", e))?;
+        let languages = installed_languages()?;
+        debug!("Found Tesseract {} with {}", version.trim(), languages.join(", "));
+        Ok(TesseractOcrEngine { languages })
+    }
+}
+
+/// Returns the language packs Tesseract has installed, such as `eng` or `deu`.
+pub fn installed_languages() -> Result<Vec<String>, String> {
+    get_tesseract_langs().map_err(|e| format!("Tesseract is not available: {}", e))
+}
+
+impl OcrEngine for TesseractOcrEngine {
+    fn name(&self) -> &'static str {
+        "tesseract"
+    }
+
+    fn recognize(
+        &self,
+        image: &DynamicImage,
+        options: &OcrOptions,
+    ) -> Result<Vec<WordBox>, String> {
+        // Tesseract reads nothing when a language pack is missing, without saying which
+        let missing = missing_languages(&options.languages, &self.languages);
+        if !missing.is_empty() {
+            return Err(format!(
+                "No Tesseract language pack is installed for {}",
+                missing.join(", ")
+            ));
+        }
+        let (image, scale) = preprocess_image(image);
+        // Boxes are reported on the upscaled image but stored for the screenshot
+        let scale = scale as i32;
+        let tesseract_image = Image::from_dynamic_image(&image).map_err(|e| e.to_string())?;
+        let args = Args {
+            lang: options.languages.clone(),
+            config_variables: HashMap::from([]),
+            dpi: Some(300),
+            oem: Some(3),
+            psm: Some(options.page_segmentation_mode),
+        };
+        let data_output = image_to_data(&tesseract_image, &args).map_err(|e| e.to_string())?;
+        Ok(data_output
+            .data
+            .iter()
+            .filter(|data| data.level == WORD_LEVEL && !data.text.trim().is_empty())
+            .map(|data| WordBox {
+                block: data.block_num,
+                par: data.par_num,
+                line: data.line_num,
+                left: data.left / scale,
+                top: data.top / scale,
+                width: data.width / scale,
+                height: data.height / scale,
+                conf: data.conf,
+                text: data.text.trim().to_string(),
+            })
+            .collect())
+    }
+}
@@ -1,70 +1,236 @@
-use image::{DynamicImage, GrayImage};
-use image::io::Reader as ImageReader;
-use imageproc::contrast::adaptive_threshold;
-use rusty_tesseract::{Args, Image, image_to_data};
-use std::collections::HashMap;
-use std::fs;
+use image::DynamicImage;
 use std::path::Path;
-use log::info;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+use log::{error, info};
 use regex::Regex;
 
+use crate::engine::ocr_layout_engine::{layout_text, WordBox};
+use crate::engine::pure_rust_ocr_engine::{models_dir, PureRustOcrEngine};
+use crate::engine::tesseract_ocr_engine::{installed_languages, TesseractOcrEngine};
+
+pub const OCR_LANGUAGES_SETTING: &str = "ocr_languages";
+pub const OCR_PAGE_SEGMENTATION_MODE_SETTING: &str = "ocr_page_segmentation_mode";
+pub const OCR_MIN_CONFIDENCE_SETTING: &str = "ocr_min_confidence";
+pub const OCR_ENGINE_SETTING: &str = "ocr_engine";
+const DEFAULT_OCR_LANGUAGES: &str = "eng";
+// Fully automatic page segmentation, without orientation and script detection
+const DEFAULT_PAGE_SEGMENTATION_MODE: i32 = 3;
+const DEFAULT_MIN_CONFIDENCE: f32 = 65.0;
+const TESSERACT_DETECTION_RETRY: Duration = Duration::from_secs(10 * 60);
+
+/// The outcome of looking for Tesseract, and when it was looked for.
+type TesseractDetection = (Result<Arc<TesseractOcrEngine>, String>, Instant);
+
+lazy_static! {
+    // The models take a while to load, so they are loaded once and kept
+    static ref PURE_RUST_ENGINE: Mutex<Option<Arc<PureRustOcrEngine>>> = Mutex::new(None);
+    // Detecting Tesseract runs it, so the outcome is kept. When it is missing, detection is
+    // tried again now and then, as the installer may still be running.
+    static ref TESSERACT_ENGINE: Mutex<Option<TesseractDetection>> = Mutex::new(None);
+}
+
+/// A way of recognising the words in a screenshot.
+pub trait OcrEngine: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns the words in reading order, with their boxes in screenshot coordinates.
+    fn recognize(
+        &self,
+        image: &DynamicImage,
+        options: &OcrOptions,
+    ) -> Result<Vec<WordBox>, String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OcrEngineKind {
+    Tesseract,
+    PureRust,
+}
+
+impl OcrEngineKind {
+    /// The engines to try, in order: the preferred one first, then the other as a fallback.
+    /// Without a preference Tesseract goes first, as it reads more languages.
+    fn in_order_of_preference(preferred: Option<OcrEngineKind>) -> [OcrEngineKind; 2] {
+        match preferred {
+            Some(OcrEngineKind::PureRust) => [OcrEngineKind::PureRust, OcrEngineKind::Tesseract],
+            _ => [OcrEngineKind::Tesseract, OcrEngineKind::PureRust],
+        }
+    }
+}
+
+/// Text recognised in a screenshot, laid out as on screen, and the words it was built from.
+#[derive(Debug, Clone, Default)]
+pub struct RecognizedText {
+    pub text: String,
+    /// Every word Tesseract reported, including those below the confidence threshold.
+    pub words: Vec<WordBox>,
+}
+
+/// How Tesseract reads a screenshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrOptions {
+    /// Installed language packs joined with `+`, for example `eng+deu+pol`.
+    pub languages: String,
+    pub page_segmentation_mode: i32,
+    /// Words recognised with a lower confidence, from 0 to 100, are left out of the text.
+    pub min_confidence: f32,
+    /// Engine to use when it is available. None picks one automatically.
+    pub engine: Option<OcrEngineKind>,
+}
+
+impl Default for OcrOptions {
+    fn default() -> Self {
+        OcrOptions {
+            languages: DEFAULT_OCR_LANGUAGES.to_string(),
+            page_segmentation_mode: DEFAULT_PAGE_SEGMENTATION_MODE,
+            min_confidence: DEFAULT_MIN_CONFIDENCE,
+            engine: None,
+        }
+    }
+}
+
+impl OcrOptions {
+    /// Reads the options from their stored settings. Languages may be separated by `+`,
+    /// commas or spaces; the engine is `tesseract`, `pure_rust` or `auto`. Empty or invalid
+    /// values fall back to the defaults.
+    pub fn from_settings(
+        languages: &str,
+        page_segmentation_mode: &str,
+        min_confidence: &str,
+        engine: &str,
+    ) -> Self {
+        let defaults = OcrOptions::default();
+        let languages: Vec<&str> = languages
+            .split(|c: char| c == '+' || c == ',' || c.is_whitespace())
+            .filter(|language| !language.is_empty())
+            .filter(|language| {
+                language
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            })
+            .collect();
+        // 0 only detects orientation and 2 is not implemented, neither returns text
+        let page_segmentation_mode = match page_segmentation_mode.trim().parse::<i32>() {
+            Ok(mode) if (1..=13).contains(&mode) && mode != 2 => mode,
+            _ => defaults.page_segmentation_mode,
+        };
+        let min_confidence = match min_confidence.trim().parse::<f32>() {
+            Ok(confidence) if (0.0..=100.0).contains(&confidence) => confidence,
+            _ => defaults.min_confidence,
+        };
+        OcrOptions {
+            languages: if languages.is_empty() {
+                defaults.languages
+            } else {
+                languages.join("+")
+            },
+            page_segmentation_mode,
+            min_confidence,
+            engine: match engine.trim() {
+                "tesseract" => Some(OcrEngineKind::Tesseract),
+                "pure_rust" => Some(OcrEngineKind::PureRust),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Returns the languages in `languages`, joined with `+`, that have no installed pack.
+/// Tesseract reads nothing at all when any of them is missing.
+pub fn missing_languages(languages: &str, installed: &[String]) -> Vec<String> {
+    languages
+        .split('+')
+        .filter(|language| !installed.iter().any(|installed| installed == language))
+        .map(String::from)
+        .collect()
+}
+
+/// Lists the languages in the `ocr_languages` setting value that Tesseract has no pack
+/// for, so the settings can warn about them.
+#[tauri::command]
+pub fn check_ocr_languages(languages: String) -> Result<Vec<String>, String> {
+    let options = OcrOptions::from_settings(&languages, "", "", "");
+    Ok(missing_languages(&options.languages, &installed_languages()?))
+}
+
 // overall we need to implement custom machine learning algo to achieve the following improvements to OCR: reduce tab text, accuracy, and identifying the relevant elements
 // the other element is to just focus on the main working area. Element tree may be helpful, separation of UI lines, deep learning.
-pub fn get_text_from_image(path: &Path) -> String {
-    let mut text_results = Vec::new(); // Use a Vec to collect texts
-    let min_confidence: f32 = 65.0; // Hardcoded minimum confidence threshold
-
-    match fs::metadata(path) {
-        Ok(_) => {
-            let dynamic_image = ImageReader::open(path).unwrap().decode().unwrap();
-            let image: DynamicImage = preprocess_image(&dynamic_image);
-            let tesseract_image = Image::from_dynamic_image(&image).unwrap();
-
-            let my_args = Args {
-                lang: "eng".to_owned(),
-                config_variables: HashMap::from([]),
-                dpi: Some(300),
-                oem: Some(3),
-                psm: Some(3),
-            };
+pub fn get_text_from_image(
+    image: &DynamicImage,
+    options: &OcrOptions,
+    resource_dir: &Path,
+) -> RecognizedText {
+    let engines = OcrEngineKind::in_order_of_preference(options.engine)
+        .into_iter()
+        .map(|kind| load_engine(kind, resource_dir));
+    recognize_with_fallback(image, options, engines)
+}
 
-            if let Ok(data_output) = image_to_data(&tesseract_image, &my_args) {
-                for data in &data_output.data {
-                    if !data.text.trim().is_empty() && data.conf > min_confidence {
-                        let text = data.text.trim();
-                        text_results.push(text.to_string());
-                    }
-                }
+fn load_engine(kind: OcrEngineKind, resource_dir: &Path) -> Result<Arc<dyn OcrEngine>, String> {
+    match kind {
+        OcrEngineKind::Tesseract => {
+            let mut detected = TESSERACT_ENGINE.lock().unwrap();
+            let detect = match detected.as_ref() {
+                Some((Ok(_), _)) => false,
+                Some((Err(_), detected_at)) => detected_at.elapsed() >= TESSERACT_DETECTION_RETRY,
+                None => true,
+            };
+            if detect {
+                *detected = Some((TesseractOcrEngine::detect().map(Arc::new), Instant::now()));
             }
+            let engine = detected.as_ref().unwrap().0.clone()?;
+            Ok(engine)
         }
-        Err(error) => {
-            info!("File does not exist. {error}");
-            return String::new();
+        OcrEngineKind::PureRust => {
+            let mut loaded = PURE_RUST_ENGINE.lock().unwrap();
+            if loaded.is_none() {
+                *loaded = Some(Arc::new(PureRustOcrEngine::load(&models_dir(resource_dir))?));
+            }
+            Ok(loaded.as_ref().unwrap().clone())
         }
-    };
-
-    let combined_text = text_results.join(" ");
-    let cleaned_text = remove_unwanted_pattern(&combined_text,50);
-    cleaned_text
+    }
 }
 
-fn preprocess_image(image: &DynamicImage) -> DynamicImage {
-    let grayscale_image = image.to_luma8(); // Convert to grayscale
-    let block_radius = 15; // Adjust the block radius as needed
-    let inverted_image = invert_image(&grayscale_image); // Invert the grayscale image
-    let adaptive_threshold_image = adaptive_threshold(&inverted_image, block_radius); // Apply adaptive thresholding
-
-    let processed_image = DynamicImage::ImageLuma8(adaptive_threshold_image);
-    processed_image
+/// Recognises the image with the first engine that is available and succeeds.
+fn recognize_with_fallback<I>(
+    image: &DynamicImage,
+    options: &OcrOptions,
+    engines: I,
+) -> RecognizedText
+where
+    I: IntoIterator<Item = Result<Arc<dyn OcrEngine>, String>>,
+{
+    for engine in engines {
+        let engine = match engine {
+            Ok(engine) => engine,
+            Err(error) => {
+                info!("OCR engine unavailable: {error}");
+                continue;
+            }
+        };
+        match engine.recognize(image, options) {
+            Ok(words) => return words_to_text(words, options),
+            Err(error) => error!("OCR with {} failed: {error}", engine.name()),
+        }
+    }
+    error!("No OCR engine could read the screenshot");
+    RecognizedText::default()
 }
 
-fn invert_image(image: &GrayImage) -> GrayImage {
-    let mut inverted_image = image.clone();
-    for pixel in inverted_image.pixels_mut() {
-        let current_value = pixel.0[0];
-        pixel.0[0] = 255 - current_value;
+fn words_to_text(words: Vec<WordBox>, options: &OcrOptions) -> RecognizedText {
+    let confident_words: Vec<WordBox> = words
+        .iter()
+        .filter(|word| word.conf > options.min_confidence)
+        .cloned()
+        .collect();
+    let combined_text = layout_text(&confident_words);
+    let cleaned_text = remove_unwanted_pattern(&combined_text,50);
+    RecognizedText {
+        text: cleaned_text,
+        words,
     }
-    inverted_image
 }
 
 // this function removes the first four tabs from the OCR dealing specifically with browser tabs
@@ -72,6 +238,10 @@ fn remove_unwanted_pattern(text: &str, n: usize) -> String {
     let pattern = r"^.*?\|.*?\|.*?\|";
     let re = Regex::new(pattern).unwrap();
 
+    // a Markdown table at the top of the screen is content, not a row of tabs
+    if text.starts_with("| ") {
+        return text.to_string();
+    }
     if let Some(truncated_text) = text.get(..n) {
         if re.is_match(truncated_text) {
             let last_vertical_line_index = truncated_text.rfind('|').unwrap_or(0);
@@ -87,3 +257,197 @@ fn remove_unwanted_pattern(text: &str, n: usize) -> String {
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use image::DynamicImage;
+    use serde_derive::Deserialize;
+    use strsim::normalized_levenshtein;
+
+    use super::{
+        missing_languages, recognize_with_fallback, words_to_text, OcrEngine, OcrEngineKind,
+        OcrOptions,
+    };
+    use crate::engine::ocr_layout_engine::WordBox;
+    use crate::engine::pure_rust_ocr_engine::{models_dir, PureRustOcrEngine};
+    use crate::engine::tesseract_ocr_engine::TesseractOcrEngine;
+
+    // OCR may misread a character here and there, and may split paragraphs differently
+    const MIN_SIMILARITY: f64 = 0.9;
+
+    /// A screenshot in `tests/ocr_corpus` and the text it shows.
+    #[derive(Deserialize)]
+    struct Sample {
+        image: String,
+        languages: String,
+        #[serde(default)]
+        page_segmentation_mode: Option<i32>,
+        expected: String,
+    }
+
+    fn collapse_whitespace(text: &str) -> String {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Reads every image as the given words, or fails.
+    struct FakeOcrEngine(Result<Vec<&'static str>, String>);
+
+    impl OcrEngine for FakeOcrEngine {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn recognize(
+            &self,
+            _image: &DynamicImage,
+            _options: &OcrOptions,
+        ) -> Result<Vec<WordBox>, String> {
+            let words = self.0.clone()?;
+            Ok(words
+                .iter()
+                .enumerate()
+                .map(|(index, text)| WordBox {
+                    block: 1,
+                    par: 1,
+                    line: 1,
+                    left: index as i32 * 60,
+                    top: 0,
+                    width: 50,
+                    height: 20,
+                    conf: 90.0,
+                    text: text.to_string(),
+                })
+                .collect())
+        }
+    }
+
+    fn engine(result: Result<Vec<&'static str>, String>) -> Result<Arc<dyn OcrEngine>, String> {
+        Ok(Arc::new(FakeOcrEngine(result)))
+    }
+
+    #[test]
+    fn options_are_read_from_settings() {
+        assert_eq!(
+            OcrOptions::from_settings("eng, deu pol", "6", "50", "pure_rust"),
+            OcrOptions {
+                languages: "eng+deu+pol".to_string(),
+                page_segmentation_mode: 6,
+                min_confidence: 50.0,
+                engine: Some(OcrEngineKind::PureRust),
+            }
+        );
+        assert_eq!(
+            OcrOptions::from_settings("", "", "", ""),
+            OcrOptions::default()
+        );
+        assert_eq!(
+            OcrOptions::from_settings("../eng", "2", "150", "auto"),
+            OcrOptions::default()
+        );
+    }
+
+    #[test]
+    fn unavailable_or_failing_engines_fall_back_to_the_next() {
+        let image = DynamicImage::new_rgb8(100, 40);
+        let options = OcrOptions::default();
+        let engines = vec![
+            Err("Tesseract is not available".to_string()),
+            engine(Err("model error".to_string())),
+            engine(Ok(vec!["Hello", "world"])),
+            engine(Ok(vec!["Not", "reached"])),
+        ];
+        let recognized = recognize_with_fallback(&image, &options, engines);
+        assert_eq!(recognized.text, "Hello world");
+        assert_eq!(recognized.words.len(), 2);
+
+        let recognized = recognize_with_fallback(
+            &image,
+            &options,
+            vec![Err("Tesseract is not available".to_string())],
+        );
+        assert!(recognized.text.is_empty() && recognized.words.is_empty());
+    }
+
+    #[test]
+    fn missing_language_packs_are_reported() {
+        let installed = vec!["eng".to_string(), "osd".to_string()];
+        assert!(missing_languages("eng", &installed).is_empty());
+        assert_eq!(
+            missing_languages("eng+deu+pol", &installed),
+            vec!["deu".to_string(), "pol".to_string()]
+        );
+    }
+
+    /// Recognises the corpus samples `recognize` is given, returning how many it checked.
+    fn check_corpus<F>(include: impl Fn(&Sample) -> bool, recognize: F) -> usize
+    where
+        F: Fn(&DynamicImage, &OcrOptions) -> String,
+    {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("ocr_corpus");
+        let samples: Vec<Sample> =
+            serde_json::from_str(&fs::read_to_string(corpus.join("samples.json")).unwrap())
+                .unwrap();
+
+        let mut checked = 0;
+        let mut failures = Vec::new();
+        for sample in samples.iter().filter(|sample| include(sample)) {
+            let defaults = OcrOptions::default();
+            let options = OcrOptions {
+                languages: sample.languages.clone(),
+                page_segmentation_mode: sample
+                    .page_segmentation_mode
+                    .unwrap_or(defaults.page_segmentation_mode),
+                ..defaults
+            };
+            let image = image::open(corpus.join(&sample.image)).unwrap();
+            let text = recognize(&image, &options);
+            let similarity = normalized_levenshtein(
+                &collapse_whitespace(&sample.expected),
+                &collapse_whitespace(&text),
+            );
+            if similarity < MIN_SIMILARITY {
+                failures.push(format!(
+                    "{}: {:.2} similar, recognised {:?}",
+                    sample.image, similarity, text
+                ));
+            }
+            checked += 1;
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+        checked
+    }
+
+    #[test]
+    #[ignore = "needs Tesseract with the eng, deu and pol language packs"]
+    fn corpus_screenshots_are_recognised_by_tesseract() {
+        let engine = TesseractOcrEngine::detect().unwrap();
+        let checked = check_corpus(
+            |_| true,
+            |image, options| {
+                words_to_text(engine.recognize(image, options).unwrap(), options).text
+            },
+        );
+        assert!(checked > 0);
+    }
+
+    /// The built-in engine reads English only and has no page segmentation modes, so it is
+    /// given the English samples laid out as running text.
+    #[test]
+    fn corpus_screenshots_are_recognised_by_the_built_in_engine() {
+        let resource_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let engine = PureRustOcrEngine::load(&models_dir(resource_dir)).unwrap();
+        let checked = check_corpus(
+            |sample| sample.languages == "eng" && sample.page_segmentation_mode.is_none(),
+            |image, options| {
+                words_to_text(engine.recognize(image, options).unwrap(), options).text
+            },
+        );
+        assert!(checked > 0);
+    }
+}
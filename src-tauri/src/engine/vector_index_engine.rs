@@ -0,0 +1,539 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use log::{error, info, warn};
+use serde_derive::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::configuration::database::{self, VECTOR_COLLECTION_NAME};
+use crate::configuration::state::ServiceAccess;
+use crate::engine::embedding_cache_engine::{self, EmbeddingCacheStats};
+use crate::engine::embedding_queue_engine::notify_embedding_queue;
+use crate::engine::similarity_search_engine::{
+    collection_files, embed_text, promote_collection, subscribe_worker_health, GraphParameters,
+    IndexStats, SimilaritySearch,
+};
+use crate::entity::setting::Setting;
+use crate::repository::activity_log_repository::{
+    amplify_document_text, count_activity_full_text_after, get_activity_full_text_batch,
+    get_activity_full_text_for_indexing, get_live_activity_full_text_ids,
+};
+use crate::repository::settings_repository::{get_setting, insert_or_update_setting};
+use crate::repository::vector_db_repository::EMBEDDING_MODEL;
+
+pub const REBUILD_COLLECTION_NAME: &str = "activity_vectors_rebuild";
+const REBUILD_BATCH_SIZE: usize = 32;
+const REBUILD_CURSOR_SETTING: &str = "vector_index_rebuild_cursor";
+const REBUILD_PROGRESS_EVENT: &str = "vector_index_rebuild_progress";
+const HEALTH_EVENT: &str = "vector_index_health";
+
+static REBUILD_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Serialize, Debug)]
+pub struct RebuildProgress {
+    pub processed: usize,
+    pub failed: usize,
+    pub total: usize,
+    pub last_id: i64,
+    pub elapsed_secs: u64,
+    pub eta_secs: Option<u64>,
+    pub done: bool,
+    pub error: Option<String>,
+}
+
+/// Starts re-embedding every stored document into a fresh collection. Progress is emitted
+/// as `vector_index_rebuild_progress` events; the command returns as soon as the job starts.
+#[tauri::command]
+pub async fn rebuild_vector_index(app_handle: AppHandle) -> Result<(), String> {
+    spawn_rebuild(app_handle)
+}
+
+/// Continues a rebuild that was interrupted by a restart, if one was in progress.
+pub fn resume_vector_index_rebuild(app_handle: AppHandle) {
+    if read_cursor(&app_handle).is_some() {
+        info!("Resuming interrupted vector index rebuild");
+        if let Err(e) = spawn_rebuild(app_handle) {
+            error!("Failed to resume vector index rebuild: {}", e);
+        }
+    }
+}
+
+/// Forwards restarts of the index workers to the UI as `vector_index_health` events.
+pub fn forward_vector_index_health(app_handle: AppHandle) {
+    let mut health = subscribe_worker_health();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match health.recv().await {
+                Ok(event) => {
+                    if let Some(window) = app_handle.get_window("main") {
+                        if let Err(e) = window.emit(HEALTH_EVENT, event) {
+                            error!("Failed to emit vector index health: {}", e);
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Skipped {} vector index health events", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+fn spawn_rebuild(app_handle: AppHandle) -> Result<(), String> {
+    if REBUILD_IN_PROGRESS.swap(true, Ordering::SeqCst) {
+        return Err("A vector index rebuild is already running".to_string());
+    }
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = run_rebuild(&app_handle).await {
+            error!("Vector index rebuild failed: {}", e);
+            emit_progress(
+                &app_handle,
+                RebuildProgress {
+                    processed: 0,
+                    failed: 0,
+                    total: 0,
+                    last_id: read_cursor(&app_handle).unwrap_or(0),
+                    elapsed_secs: 0,
+                    eta_secs: None,
+                    done: true,
+                    error: Some(e),
+                },
+            );
+        }
+        REBUILD_IN_PROGRESS.store(false, Ordering::SeqCst);
+        // The embedding queue waits for the rebuild to finish
+        notify_embedding_queue();
+    });
+    Ok(())
+}
+
+pub fn is_rebuild_in_progress() -> bool {
+    REBUILD_IN_PROGRESS.load(Ordering::SeqCst)
+}
+
+async fn run_rebuild(app_handle: &AppHandle) -> Result<(), String> {
+    let db_path = database::get_vector_db_path(app_handle);
+    let api_key = app_handle
+        .db(|db| get_setting(db, "api_key_open_ai"))
+        .map_err(|e| e.to_string())?
+        .setting_value;
+
+    // Without a cursor any leftover collection belongs to an abandoned run and is discarded
+    let mut cursor = match read_cursor(app_handle) {
+        Some(cursor) => cursor,
+        None => {
+            remove_collection_files(&db_path, REBUILD_COLLECTION_NAME)
+                .map_err(|e| format!("Failed to clear previous rebuild: {}", e))?;
+            write_cursor(app_handle, 0)?;
+            0
+        }
+    };
+    info!("Rebuilding vector index from id > {}", cursor);
+
+    let mut rebuild = SimilaritySearch::open(&db_path, REBUILD_COLLECTION_NAME)
+        .map_err(|e| format!("Failed to open rebuild collection: {}", e))?;
+    let total = app_handle
+        .db(|db| count_activity_full_text_after(db, cursor))
+        .map_err(|e| e.to_string())? as usize;
+    if total == 0 && cursor == 0 {
+        rebuild.discard().await.map_err(|e| e.to_string())?;
+        clear_cursor(app_handle)?;
+        return Err("There are no documents to index".to_string());
+    }
+    let started_at = Instant::now();
+    let mut processed = 0;
+    let mut failed = 0;
+
+    loop {
+        let embedded = embed_next_batch(app_handle, &rebuild, &mut cursor, &api_key).await?;
+        if embedded.attempted == 0 {
+            break;
+        }
+        processed += embedded.attempted;
+        failed += embedded.failed;
+        rebuild
+            .sync()
+            .await
+            .map_err(|e| format!("Failed to save rebuild collection: {}", e))?;
+        write_cursor(app_handle, cursor)?;
+
+        let elapsed = started_at.elapsed().as_secs_f64();
+        let remaining = total.saturating_sub(processed);
+        emit_progress(
+            app_handle,
+            RebuildProgress {
+                processed,
+                failed,
+                total,
+                last_id: cursor,
+                elapsed_secs: elapsed as u64,
+                eta_secs: Some((elapsed / processed as f64 * remaining as f64) as u64),
+                done: false,
+                error: None,
+            },
+        );
+    }
+
+    // Documents that failed to embed or were captured after the last batch are added until
+    // the rebuild holds every document. The live index is only locked to check that none
+    // are left and to swap, so searches do not wait for the embeddings API
+    let hnsw = database::get_vector_db(app_handle)
+        .await
+        .map_err(|e| format!("Failed to get vector database: {}", e))?;
+    let mut hnsw_guard = loop {
+        let hnsw_guard = hnsw.lock().await;
+        let (indexed, missing) = missing_documents(app_handle, &rebuild).await?;
+        if missing.is_empty() {
+            processed = indexed;
+            break hnsw_guard;
+        }
+        drop(hnsw_guard);
+        let failed_ids = add_documents(&rebuild, &missing, &api_key).await?;
+        if !failed_ids.is_empty() {
+            rebuild
+                .sync()
+                .await
+                .map_err(|e| format!("Failed to save rebuild collection: {}", e))?;
+            return Err(format!(
+                "{} documents could not be embedded, so the current index was kept. \
+                 Run the rebuild again to retry them",
+                failed_ids.len()
+            ));
+        }
+    };
+    rebuild
+        .close()
+        .await
+        .map_err(|e| format!("Failed to save rebuild collection: {}", e))?;
+    if let Some(mut live) = hnsw_guard.take() {
+        live.discard()
+            .await
+            .map_err(|e| format!("Failed to stop live collection: {}", e))?;
+    }
+    promote_collection(&db_path, REBUILD_COLLECTION_NAME, VECTOR_COLLECTION_NAME)
+        .map_err(|e| format!("Failed to swap in rebuilt collection: {}", e))?;
+    *hnsw_guard = Some(
+        SimilaritySearch::open(&db_path, VECTOR_COLLECTION_NAME)
+            .map_err(|e| format!("Failed to reopen vector index: {}", e))?,
+    );
+    drop(hnsw_guard);
+    clear_cursor(app_handle)?;
+
+    info!("Vector index rebuild completed: {} documents", processed);
+    emit_progress(
+        app_handle,
+        RebuildProgress {
+            processed,
+            failed: 0,
+            total: processed,
+            last_id: cursor,
+            elapsed_secs: started_at.elapsed().as_secs(),
+            eta_secs: Some(0),
+            done: true,
+            error: None,
+        },
+    );
+    Ok(())
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CollectionFile {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RepairSummary {
+    pub reembedded: usize,
+    pub tombstoned: usize,
+    pub failed: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VectorIndexStatus {
+    pub model: Option<String>,
+    pub current_model: String,
+    pub graph: GraphParameters,
+    pub point_count: usize,
+    pub dimension: Option<usize>,
+    pub dimensions: BTreeMap<usize, usize>,
+    pub tombstoned_count: usize,
+    /// Indexed IDs whose document was deleted or no longer exists.
+    pub orphan_ids: Vec<i64>,
+    /// Documents that capture would have indexed but that have no vector.
+    pub unindexed_ids: Vec<i64>,
+    pub files: Vec<CollectionFile>,
+    pub embedding_cache: EmbeddingCacheStats,
+    pub repair: Option<RepairSummary>,
+}
+
+/// Compares the HNSW collection with `activity_full_text`. With `repair` set, missing
+/// documents are re-embedded and orphaned vectors are tombstoned before reporting.
+#[tauri::command]
+pub async fn vector_index_status(
+    app_handle: AppHandle,
+    repair: Option<bool>,
+) -> Result<VectorIndexStatus, String> {
+    let hnsw = database::get_vector_db(&app_handle)
+        .await
+        .map_err(|e| format!("Failed to get vector database: {}", e))?;
+    let mut stats = hnsw
+        .lock()
+        .await
+        .as_ref()
+        .ok_or("HNSW database not initialized!")?
+        .stats()
+        .await
+        .map_err(|e| e.to_string())?;
+    let (mut orphan_ids, mut unindexed_ids) = compare_with_documents(&app_handle, &stats)?;
+
+    let repair_summary = if repair.unwrap_or(false) {
+        let api_key = app_handle
+            .db(|db| get_setting(db, "api_key_open_ai"))
+            .map_err(|e| e.to_string())?
+            .setting_value;
+        let mut summary = RepairSummary::default();
+
+        // Embed without holding the index lock; captures and searches continue meanwhile
+        let mut embedded = Vec::new();
+        for id in &unindexed_ids {
+            let document = app_handle
+                .db(|db| get_activity_full_text_for_indexing(db, *id))
+                .map_err(|e| e.to_string())?;
+            if let Some((window_title, full_text)) = document {
+                let text = amplify_document_text(&window_title, &full_text);
+                match embed_text(&text, &api_key).await {
+                    Ok(vector) => embedded.push((*id, vector)),
+                    Err(e) => {
+                        error!("Failed to re-embed document {}: {}", id, e);
+                        summary.failed += 1;
+                    }
+                }
+            }
+        }
+
+        let hnsw_guard = hnsw.lock().await;
+        let index = hnsw_guard.as_ref().ok_or("HNSW database not initialized!")?;
+        if !orphan_ids.is_empty() {
+            index.tombstone(orphan_ids.iter().map(|id| *id as usize).collect())
+                .await
+                .map_err(|e| e.to_string())?;
+            summary.tombstoned = orphan_ids.len();
+        }
+        for (id, vector) in embedded {
+            match index.add_embedding(id, vector).await {
+                Ok(()) => summary.reembedded += 1,
+                Err(e) => {
+                    error!("Failed to add document {} to the index: {}", id, e);
+                    summary.failed += 1;
+                }
+            }
+        }
+        index.sync().await.map_err(|e| e.to_string())?;
+        info!("Vector index repair finished: {:?}", summary);
+
+        stats = index.stats().await.map_err(|e| e.to_string())?;
+        drop(hnsw_guard);
+        (orphan_ids, unindexed_ids) = compare_with_documents(&app_handle, &stats)?;
+        Some(summary)
+    } else {
+        None
+    };
+
+    let db_path = database::get_vector_db_path(&app_handle);
+    let files = collection_files(Path::new(&db_path), VECTOR_COLLECTION_NAME)
+        .iter()
+        .flat_map(|(new_path, path)| [path.clone(), new_path.clone()])
+        .filter_map(|path| {
+            let size_bytes = std::fs::metadata(&path).ok()?.len();
+            Some(CollectionFile {
+                name: path.file_name()?.to_string_lossy().to_string(),
+                size_bytes,
+            })
+        })
+        .collect();
+
+    Ok(VectorIndexStatus {
+        model: stats.model,
+        current_model: EMBEDDING_MODEL.to_string(),
+        graph: stats.graph,
+        point_count: stats.point_count,
+        dimension: stats
+            .dimensions
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(dimension, _)| *dimension),
+        dimensions: stats.dimensions,
+        tombstoned_count: stats.tombstoned_ids.len(),
+        orphan_ids,
+        unindexed_ids,
+        files,
+        embedding_cache: embedding_cache_engine::stats(),
+        repair: repair_summary,
+    })
+}
+
+fn compare_with_documents(
+    app_handle: &AppHandle,
+    stats: &IndexStats,
+) -> Result<(Vec<i64>, Vec<i64>), String> {
+    let live_ids: HashSet<i64> = app_handle
+        .db(|db| get_live_activity_full_text_ids(db, false))
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+    let indexable_ids = app_handle
+        .db(|db| get_live_activity_full_text_ids(db, true))
+        .map_err(|e| e.to_string())?;
+    let indexed_ids: HashSet<i64> = stats.indexed_ids.iter().map(|id| *id as i64).collect();
+
+    let mut orphan_ids: Vec<i64> = indexed_ids.difference(&live_ids).copied().collect();
+    orphan_ids.sort();
+    let mut unindexed_ids: Vec<i64> = indexable_ids
+        .into_iter()
+        .filter(|id| !indexed_ids.contains(id))
+        .collect();
+    unindexed_ids.sort();
+    Ok((orphan_ids, unindexed_ids))
+}
+
+struct BatchOutcome {
+    attempted: usize,
+    failed: usize,
+}
+
+/// Embeds the documents after the cursor and adds them to the rebuild collection. The cursor
+/// moves past documents that failed to embed, as they are retried before the swap, unless
+/// the whole batch failed: that points to the network or the API key, so the rebuild stops
+/// where it is and can be resumed.
+async fn embed_next_batch(
+    app_handle: &AppHandle,
+    rebuild: &SimilaritySearch,
+    cursor: &mut i64,
+    api_key: &str,
+) -> Result<BatchOutcome, String> {
+    let after_id = *cursor;
+    let batch = app_handle
+        .db(|db| get_activity_full_text_batch(db, after_id, REBUILD_BATCH_SIZE))
+        .map_err(|e| e.to_string())?;
+
+    let failed_ids = add_documents(rebuild, &batch, api_key).await?;
+    if !batch.is_empty() && failed_ids.len() == batch.len() {
+        return Err(format!(
+            "No document after id {} could be embedded. Check the network and the API key, \
+             then run the rebuild again to resume",
+            after_id
+        ));
+    }
+    if let Some((id, _, _)) = batch.last() {
+        *cursor = *id;
+    }
+    Ok(BatchOutcome {
+        attempted: batch.len(),
+        failed: failed_ids.len(),
+    })
+}
+
+/// Embeds `(id, window_title, full_text)` documents and adds them to the rebuild collection,
+/// returning the ids that could not be embedded.
+async fn add_documents(
+    rebuild: &SimilaritySearch,
+    documents: &[(i64, String, String)],
+    api_key: &str,
+) -> Result<Vec<i64>, String> {
+    let mut failed_ids = Vec::new();
+    for (id, window_title, full_text) in documents {
+        let text = amplify_document_text(window_title, full_text);
+        match embed_text(&text, api_key).await {
+            Ok(vector) => rebuild
+                .add_embedding(*id, vector)
+                .await
+                .map_err(|e| format!("Failed to add document {} to the rebuild: {}", id, e))?,
+            Err(e) => {
+                error!("Failed to re-embed document {}: {}", id, e);
+                failed_ids.push(*id);
+            }
+        }
+    }
+    Ok(failed_ids)
+}
+
+/// Number of documents in the rebuild collection, and the documents it is still missing.
+async fn missing_documents(
+    app_handle: &AppHandle,
+    rebuild: &SimilaritySearch,
+) -> Result<(usize, Vec<(i64, String, String)>), String> {
+    let stats = rebuild.stats().await.map_err(|e| e.to_string())?;
+    let indexed_ids: HashSet<i64> = stats.indexed_ids.iter().map(|id| *id as i64).collect();
+    let live_ids = app_handle
+        .db(|db| get_live_activity_full_text_ids(db, false))
+        .map_err(|e| e.to_string())?;
+    let mut missing = Vec::new();
+    for id in live_ids.into_iter().filter(|id| !indexed_ids.contains(id)) {
+        let document = app_handle
+            .db(|db| get_activity_full_text_for_indexing(db, id))
+            .map_err(|e| e.to_string())?;
+        if let Some((window_title, full_text)) = document {
+            missing.push((id, window_title, full_text));
+        }
+    }
+    Ok((indexed_ids.len(), missing))
+}
+
+fn remove_collection_files(db_path: &str, collection_name: &str) -> std::io::Result<()> {
+    for (new_path, path) in collection_files(Path::new(db_path), collection_name) {
+        for path in [new_path, path] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_cursor(app_handle: &AppHandle) -> Option<i64> {
+    app_handle
+        .db(|db| get_setting(db, REBUILD_CURSOR_SETTING))
+        .ok()
+        .and_then(|setting| setting.setting_value.parse().ok())
+}
+
+fn write_cursor(app_handle: &AppHandle, cursor: i64) -> Result<(), String> {
+    app_handle
+        .db(|db| {
+            insert_or_update_setting(
+                db,
+                Setting {
+                    setting_key: REBUILD_CURSOR_SETTING.to_string(),
+                    setting_value: cursor.to_string(),
+                },
+            )
+        })
+        .map_err(|e| format!("Failed to save rebuild cursor: {}", e))
+}
+
+fn clear_cursor(app_handle: &AppHandle) -> Result<(), String> {
+    app_handle
+        .db(|db| {
+            insert_or_update_setting(
+                db,
+                Setting {
+                    setting_key: REBUILD_CURSOR_SETTING.to_string(),
+                    setting_value: String::new(),
+                },
+            )
+        })
+        .map_err(|e| format!("Failed to clear rebuild cursor: {}", e))
+}
+
+fn emit_progress(app_handle: &AppHandle, progress: RebuildProgress) {
+    if let Some(window) = app_handle.get_window("main") {
+        if let Err(e) = window.emit(REBUILD_PROGRESS_EVENT, progress) {
+            error!("Failed to emit rebuild progress: {}", e);
+        }
+    }
+}
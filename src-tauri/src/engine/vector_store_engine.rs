@@ -0,0 +1,470 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rusqlite::Connection;
+use serde_derive::Serialize;
+use tauri::AppHandle;
+
+use crate::configuration::database;
+use crate::configuration::state::ServiceAccess;
+use crate::engine::similarity_search_engine::{
+    SearchParameters, SimilaritySearch, SyncSimilaritySearch,
+};
+use crate::repository::embedding_cache_repository::vector_from_bytes;
+use crate::repository::settings_repository::get_setting_value;
+use crate::repository::vector_db_repository::EMBEDDING_MODEL;
+use crate::repository::vector_store_repository::{
+    count_document_vectors_by_dimension, delete_document_vectors, get_document_vectors,
+    quantize_vector, save_document_vector, scan_document_vectors,
+};
+
+/// Where document vectors are kept: `hnsw` (the default), `sqlite` or `sqlite_quantized`.
+pub const VECTOR_STORE_SETTING: &str = "vector_store";
+
+// Quantized search rescores this many candidates per result with the exact vectors
+const QUANTIZED_RESCORE_FACTOR: usize = 4;
+
+/// Restricts a search to a subset of documents.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorFilter {
+    /// When set, only these documents are returned.
+    pub allowed_ids: Option<HashSet<i64>>,
+    pub excluded_ids: HashSet<i64>,
+}
+
+impl VectorFilter {
+    pub fn only(ids: impl IntoIterator<Item = i64>) -> Self {
+        VectorFilter {
+            allowed_ids: Some(ids.into_iter().collect()),
+            ..VectorFilter::default()
+        }
+    }
+
+    pub fn excluding(ids: impl IntoIterator<Item = i64>) -> Self {
+        VectorFilter {
+            excluded_ids: ids.into_iter().collect(),
+            ..VectorFilter::default()
+        }
+    }
+
+    pub fn accepts(&self, id: i64) -> bool {
+        !self.excluded_ids.contains(&id)
+            && self
+                .allowed_ids
+                .as_ref()
+                .map_or(true, |allowed_ids| allowed_ids.contains(&id))
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct VectorStoreStats {
+    pub backend: String,
+    pub model: Option<String>,
+    pub vector_count: usize,
+    /// Number of vectors per dimension; more than one entry means mixed models.
+    pub dimensions: BTreeMap<usize, usize>,
+}
+
+/// Storage and nearest-neighbour search over document embeddings. Vectors are computed
+/// with `embed_text` before calling in, and distances are cosine distances.
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    async fn add(&self, id: i64, vector: Vec<f32>) -> Result<()>;
+
+    /// Hides the given documents from future searches.
+    async fn remove(&self, ids: &[i64]) -> Result<()>;
+
+    /// Returns up to `parameters.k` documents accepted by `filter`, nearest first.
+    async fn search(
+        &self,
+        vector: &[f32],
+        parameters: SearchParameters,
+        filter: Option<&VectorFilter>,
+    ) -> Result<Vec<(i64, f32)>>;
+
+    /// Makes every change made so far durable.
+    async fn persist(&self) -> Result<()>;
+
+    async fn stats(&self) -> Result<VectorStoreStats>;
+}
+
+#[async_trait]
+impl VectorStore for SimilaritySearch {
+    async fn add(&self, id: i64, vector: Vec<f32>) -> Result<()> {
+        self.add_embedding(id, vector).await
+    }
+
+    async fn remove(&self, ids: &[i64]) -> Result<()> {
+        self.tombstone(ids.iter().map(|id| *id as usize).collect())
+            .await
+    }
+
+    async fn search(
+        &self,
+        vector: &[f32],
+        parameters: SearchParameters,
+        filter: Option<&VectorFilter>,
+    ) -> Result<Vec<(i64, f32)>> {
+        let candidates = self
+            .search_embedding_filtered(vector.to_vec(), parameters, filter.cloned())
+            .await?;
+        Ok(candidates
+            .into_iter()
+            .map(|(id, distance)| (id as i64, distance))
+            .collect())
+    }
+
+    async fn persist(&self) -> Result<()> {
+        self.sync().await
+    }
+
+    async fn stats(&self) -> Result<VectorStoreStats> {
+        let stats = SimilaritySearch::stats(self).await?;
+        Ok(VectorStoreStats {
+            backend: "hnsw".to_string(),
+            model: stats.model,
+            vector_count: stats.indexed_ids.len(),
+            dimensions: stats.dimensions,
+        })
+    }
+}
+
+/// The shared index of the app, locked for the duration of each call.
+#[async_trait]
+impl VectorStore for SyncSimilaritySearch {
+    async fn add(&self, id: i64, vector: Vec<f32>) -> Result<()> {
+        let hnsw_guard = self.lock().await;
+        VectorStore::add(open_index(&hnsw_guard)?, id, vector).await
+    }
+
+    async fn remove(&self, ids: &[i64]) -> Result<()> {
+        let hnsw_guard = self.lock().await;
+        VectorStore::remove(open_index(&hnsw_guard)?, ids).await
+    }
+
+    async fn search(
+        &self,
+        vector: &[f32],
+        parameters: SearchParameters,
+        filter: Option<&VectorFilter>,
+    ) -> Result<Vec<(i64, f32)>> {
+        let hnsw_guard = self.lock().await;
+        VectorStore::search(open_index(&hnsw_guard)?, vector, parameters, filter).await
+    }
+
+    async fn persist(&self) -> Result<()> {
+        let hnsw_guard = self.lock().await;
+        VectorStore::persist(open_index(&hnsw_guard)?).await
+    }
+
+    async fn stats(&self) -> Result<VectorStoreStats> {
+        let hnsw_guard = self.lock().await;
+        VectorStore::stats(open_index(&hnsw_guard)?).await
+    }
+}
+
+fn open_index(hnsw: &Option<SimilaritySearch>) -> Result<&SimilaritySearch> {
+    hnsw.as_ref()
+        .ok_or_else(|| anyhow!("Vector index is not open"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SqliteSearchMode {
+    /// Compares the query with every stored vector.
+    Exact,
+    /// Compares int8-quantized vectors, then rescores the best candidates exactly.
+    Quantized,
+}
+
+/// Keeps vectors as BLOBs next to `activity_full_text` and searches them by brute force.
+/// Results are exact, writes share transactions and backups with the documents, and it
+/// needs no index files; it is meant for corpora small enough to scan on every query.
+pub struct SqliteVectorStore {
+    db: Mutex<Connection>,
+    model: String,
+    mode: SqliteSearchMode,
+}
+
+impl SqliteVectorStore {
+    pub fn open(sqlite_path: &Path, model: &str, mode: SqliteSearchMode) -> Result<Self> {
+        let db = Connection::open(sqlite_path)?;
+        db.busy_timeout(Duration::from_secs(5))?;
+        Ok(SqliteVectorStore {
+            db: Mutex::new(db),
+            model: model.to_string(),
+            mode,
+        })
+    }
+
+    fn scan(
+        &self,
+        db: &Connection,
+        vector: &[f32],
+        k: usize,
+        filter: Option<&VectorFilter>,
+    ) -> Result<Vec<(i64, f32)>> {
+        let accepts = |id: i64| filter.map_or(true, |filter| filter.accepts(id));
+        let mut scored = Vec::new();
+        match self.mode {
+            SqliteSearchMode::Exact => {
+                scan_document_vectors(db, &self.model, vector.len(), false, |id, bytes| {
+                    if accepts(id) {
+                        scored.push((id, cosine_distance(vector, &vector_from_bytes(bytes))));
+                    }
+                })?;
+            }
+            SqliteSearchMode::Quantized => {
+                let query: Vec<f32> = quantize_vector(vector)
+                    .into_iter()
+                    .map(|value| value as f32)
+                    .collect();
+                scan_document_vectors(db, &self.model, vector.len(), true, |id, bytes| {
+                    if accepts(id) {
+                        let candidate: Vec<f32> =
+                            bytes.iter().map(|value| *value as i8 as f32).collect();
+                        scored.push((id, cosine_distance(&query, &candidate)));
+                    }
+                })?;
+                sort_by_distance(&mut scored);
+                scored.truncate(k * QUANTIZED_RESCORE_FACTOR);
+                let ids: Vec<i64> = scored.iter().map(|(id, _)| *id).collect();
+                scored = get_document_vectors(db, &self.model, &ids)?
+                    .into_iter()
+                    .map(|(id, candidate)| (id, cosine_distance(vector, &candidate)))
+                    .collect();
+            }
+        }
+        sort_by_distance(&mut scored);
+        scored.truncate(k);
+        Ok(scored)
+    }
+}
+
+#[async_trait]
+impl VectorStore for SqliteVectorStore {
+    async fn add(&self, id: i64, vector: Vec<f32>) -> Result<()> {
+        let db = self.db.lock().map_err(|e| anyhow!("{}", e))?;
+        save_document_vector(&db, id, &self.model, &vector)?;
+        Ok(())
+    }
+
+    async fn remove(&self, ids: &[i64]) -> Result<()> {
+        let db = self.db.lock().map_err(|e| anyhow!("{}", e))?;
+        delete_document_vectors(&db, ids)?;
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        vector: &[f32],
+        parameters: SearchParameters,
+        filter: Option<&VectorFilter>,
+    ) -> Result<Vec<(i64, f32)>> {
+        let db = self.db.lock().map_err(|e| anyhow!("{}", e))?;
+        self.scan(&db, vector, parameters.k, filter)
+    }
+
+    async fn persist(&self) -> Result<()> {
+        // Every write is committed as it is made
+        Ok(())
+    }
+
+    async fn stats(&self) -> Result<VectorStoreStats> {
+        let db = self.db.lock().map_err(|e| anyhow!("{}", e))?;
+        let dimensions = count_document_vectors_by_dimension(&db, &self.model)?;
+        Ok(VectorStoreStats {
+            backend: "sqlite".to_string(),
+            model: Some(self.model.clone()),
+            vector_count: dimensions.values().sum(),
+            dimensions,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorStoreBackend {
+    Hnsw,
+    Sqlite(SqliteSearchMode),
+}
+
+impl VectorStoreBackend {
+    /// Unknown values fall back to the HNSW index.
+    pub fn from_setting(value: &str) -> Self {
+        match value.trim() {
+            "sqlite" => VectorStoreBackend::Sqlite(SqliteSearchMode::Exact),
+            "sqlite_quantized" => VectorStoreBackend::Sqlite(SqliteSearchMode::Quantized),
+            _ => VectorStoreBackend::Hnsw,
+        }
+    }
+}
+
+pub fn vector_store_backend(app_handle: &AppHandle) -> VectorStoreBackend {
+    let setting = app_handle.db(|db| get_setting_value(db, VECTOR_STORE_SETTING));
+    VectorStoreBackend::from_setting(&setting)
+}
+
+/// Opens the store chosen with the `vector_store` setting. Documents are embedded into it by
+/// the embedding queue, searched by retrieval and removed from it when deleted.
+pub async fn open_vector_store(app_handle: &AppHandle) -> Result<Box<dyn VectorStore>, String> {
+    match vector_store_backend(app_handle) {
+        VectorStoreBackend::Hnsw => {
+            let hnsw = database::get_vector_db(app_handle)
+                .await
+                .map_err(|e| format!("Failed to get vector database: {}", e))?;
+            Ok(Box::new(hnsw))
+        }
+        VectorStoreBackend::Sqlite(mode) => {
+            let sqlite_path = database::get_sqlite_path(app_handle);
+            let store = SqliteVectorStore::open(&sqlite_path, EMBEDDING_MODEL, mode)
+                .map_err(|e| format!("Failed to open the SQLite vector store: {}", e))?;
+            Ok(Box::new(store))
+        }
+    }
+}
+
+/// Cosine distance as computed by the HNSW index, so scores from both stores compare.
+pub fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    let (dot, norm_a, norm_b) = a
+        .iter()
+        .zip(b)
+        .fold((0f32, 0f32, 0f32), |(dot, norm_a, norm_b), (x, y)| {
+            (dot + x * y, norm_a + x * x, norm_b + y * y)
+        });
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+    (1.0 - dot / (norm_a * norm_b).sqrt()).max(0.0)
+}
+
+fn sort_by_distance(scored: &mut [(i64, f32)]) {
+    scored.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use rusqlite::params;
+
+    use super::{
+        SqliteSearchMode, SqliteVectorStore, VectorFilter, VectorStore, VectorStoreBackend,
+    };
+    use crate::configuration::database::open_test_database;
+    use crate::engine::similarity_search_engine::{
+        deterministic_embedding, SearchParameters, SimilaritySearch,
+    };
+
+    const DOCUMENTS: [&str; 5] = [
+        "quarterly revenue report for the finance team",
+        "rust borrow checker lifetimes explained",
+        "holiday schedule and vacation planning",
+        "finance team budget spreadsheet",
+        "async rust tokio runtime internals",
+    ];
+
+    async fn fill(store: &dyn VectorStore) -> Result<()> {
+        for (id, text) in DOCUMENTS.iter().enumerate() {
+            store
+                .add(id as i64 + 1, deterministic_embedding(text))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn search(
+        store: &dyn VectorStore,
+        query: &str,
+        filter: Option<&VectorFilter>,
+    ) -> Result<Vec<i64>> {
+        Ok(store
+            .search(
+                &deterministic_embedding(query),
+                SearchParameters::with_k(2),
+                filter,
+            )
+            .await?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn stores_agree_on_search_filter_and_removal() -> Result<()> {
+        let test_database = open_test_database()?;
+        let (db, sqlite_path) = (&test_database.db, &test_database.path);
+        // Vectors reference their documents
+        for (id, text) in DOCUMENTS.iter().enumerate() {
+            db.execute(
+                "INSERT INTO activity_full_text (id, original_full_text) VALUES (?1, ?2)",
+                params![id as i64 + 1, text],
+            )?;
+        }
+        let index_path = test_database.dir.path().join("hnsw");
+        let stores: Vec<Box<dyn VectorStore>> = vec![
+            Box::new(SimilaritySearch::open(
+                index_path.to_str().unwrap(),
+                "vectors",
+            )?),
+            Box::new(SqliteVectorStore::open(
+                sqlite_path,
+                "test-model",
+                SqliteSearchMode::Exact,
+            )?),
+            Box::new(SqliteVectorStore::open(
+                sqlite_path,
+                "test-model",
+                SqliteSearchMode::Quantized,
+            )?),
+        ];
+
+        for store in &stores {
+            let store = store.as_ref();
+            fill(store).await?;
+            assert_eq!(search(store, "finance team", None).await?, vec![4, 1]);
+            assert_eq!(
+                search(store, "finance team", Some(&VectorFilter::excluding([4]))).await?[0],
+                1
+            );
+            assert_eq!(
+                search(store, "rust", Some(&VectorFilter::only([3, 5]))).await?[0],
+                5
+            );
+            store.persist().await?;
+        }
+
+        // The two SQLite stores share one table
+        stores[0].remove(&[4]).await?;
+        stores[1].remove(&[4]).await?;
+        for store in &stores {
+            let results = search(store.as_ref(), "finance team budget", None).await?;
+            assert!(!results.contains(&4));
+        }
+        let stats = stores[2].stats().await?;
+        assert_eq!(stats.vector_count, 4);
+        assert_eq!(stats.dimensions.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn backend_setting_defaults_to_hnsw() {
+        assert_eq!(
+            VectorStoreBackend::from_setting("sqlite"),
+            VectorStoreBackend::Sqlite(SqliteSearchMode::Exact)
+        );
+        assert_eq!(
+            VectorStoreBackend::from_setting(" sqlite_quantized "),
+            VectorStoreBackend::Sqlite(SqliteSearchMode::Quantized)
+        );
+        assert_eq!(
+            VectorStoreBackend::from_setting(""),
+            VectorStoreBackend::Hnsw
+        );
+        assert_eq!(
+            VectorStoreBackend::from_setting("faiss"),
+            VectorStoreBackend::Hnsw
+        );
+    }
+}
@@ -18,4 +18,10 @@ pub struct ActivityItem {
     pub keypress_count: u32,
     pub element_tree_dump: String,
     pub detected_actions: String,
+    /// Perceptual hash of the screenshot, used to skip OCR when the window is unchanged.
+    #[serde(default)]
+    pub screenshot_hash: Option<String>,
+    /// Word boxes reported by OCR, as JSON, so the layout can be rebuilt later.
+    #[serde(default)]
+    pub ocr_word_boxes: Option<String>,
 }
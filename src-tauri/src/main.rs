@@ -5,9 +5,8 @@ use std::env;
 use std::sync::Arc;
 
 use lazy_static::lazy_static;
-use log::info;
+use log::{error, info};
 use rusqlite::Connection;
-use serde_derive::Serialize;
 use tauri::utils::config::AppUrl;
 use tauri::SystemTray;
 use tauri::{AppHandle, Manager, State, SystemTrayEvent, WindowUrl};
@@ -23,9 +22,31 @@ use crate::configuration::database::drop_database_handle;
 use crate::configuration::state::{AppState, ServiceAccess};
 use crate::engine::chat_engine::{name_conversation, send_prompt_to_llm};
 use crate::engine::chat_engine_openai::{generate_conversation_name, send_prompt_to_openai};
-use crate::engine::clean_up_engine::clean_up;
-use crate::engine::monitoring_engine;
+use crate::engine::embedding_cache_engine::{self, EmbeddingCacheStats};
+use crate::engine::embedding_queue_engine::{self, embedding_queue_status};
+use crate::engine::monitoring_scheduler_engine::{
+    self, monitoring_status, pause_monitoring, resume_monitoring, start_monitoring,
+    IDLE_THRESHOLD_MINUTES_SETTING, TRAY_TOGGLE_ITEM,
+};
+use crate::engine::near_duplicate_engine::NEAR_DUPLICATE_THRESHOLD_SETTING;
+use crate::engine::perceptual_hash_engine::SCREENSHOT_CHANGE_THRESHOLD_SETTING;
+use crate::engine::screenshot_retention_engine::{
+    self, SCREENSHOT_FORMAT_SETTING, SCREENSHOT_MAX_WIDTH_SETTING,
+    SCREENSHOT_RETENTION_COUNT_SETTING, SCREENSHOT_RETENTION_SETTING,
+};
+use crate::engine::text_recognition_engine::{
+    check_ocr_languages, OcrOptions, OCR_ENGINE_SETTING, OCR_LANGUAGES_SETTING,
+    OCR_MIN_CONFIDENCE_SETTING, OCR_PAGE_SEGMENTATION_MODE_SETTING,
+};
+use crate::engine::relevance_scoring_engine::{
+    ENGAGEMENT_WEIGHT_SETTING, RECENCY_HALF_LIFE_DAYS_SETTING, RECENCY_WEIGHT_SETTING,
+};
 use crate::engine::similarity_search_engine::SyncSimilaritySearch;
+use crate::engine::vector_index_engine::{
+    forward_vector_index_health, rebuild_vector_index, resume_vector_index_rebuild,
+    vector_index_status,
+};
+use crate::engine::vector_store_engine::{self, VECTOR_STORE_SETTING};
 use crate::entity::activity_item::ActivityItem;
 use crate::entity::chat_item::{Chat, StoredMessage};
 use crate::entity::permission::Permission;
@@ -38,7 +59,9 @@ use crate::repository::permissions_repository::{get_permissions, update_permissi
 use crate::repository::project_repository::{
     delete_project, fetch_all_projects, add_blank_document, save_project, update_project,get_activity_text_from_project, update_activity_text, update_activity_name,
 };
-use crate::repository::settings_repository::{get_setting, get_settings, insert_or_update_setting};
+use crate::repository::settings_repository::{
+    get_setting_value, get_settings, insert_or_update_setting,
+};
 use tauri_plugin_autostart::MacosLauncher;
 
 mod bootstrap;
@@ -50,11 +73,6 @@ pub mod permissions;
 mod repository;
 pub mod window_details_collector;
 
-#[derive(Clone, Serialize)]
-struct Payload {
-    data: bool,
-}
-
 #[cfg(debug_assertions)]
 const USE_LOCALHOST_SERVER: bool = false;
 #[cfg(not(debug_assertions))]
@@ -111,12 +129,19 @@ async fn main() {
                 }
             }
             SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
-                "start_stop_recording" => {
-                    let wrapped_window = app.get_window("main");
-                    if let Some(window) = wrapped_window {
-                        window
-                            .emit("toggle_recording", Payload { data: true })
-                            .unwrap();
+                TRAY_TOGGLE_ITEM => {
+                    if let Err(e) = monitoring_scheduler_engine::toggle_monitoring(app) {
+                        error!("Failed to toggle monitoring: {}", e);
+                    }
+                }
+                "pause_15_minutes" => {
+                    if let Err(e) = pause_monitoring(app.clone(), Some(15 * 60)) {
+                        error!("Failed to pause monitoring: {}", e);
+                    }
+                }
+                "pause_1_hour" => {
+                    if let Err(e) = pause_monitoring(app.clone(), Some(60 * 60)) {
+                        error!("Failed to pause monitoring: {}", e);
                     }
                 }
                 "quit" => {
@@ -155,6 +180,15 @@ async fn main() {
             update_project_activity_text,
             add_project_blank_activity,
             update_project_activity_name,
+            get_embedding_cache_stats,
+            rebuild_vector_index,
+            vector_index_status,
+            embedding_queue_status,
+            start_monitoring,
+            pause_monitoring,
+            resume_monitoring,
+            monitoring_status,
+            check_ocr_languages,
         ])
         .manage(AppState {
             db: Default::default(),
@@ -187,6 +221,13 @@ async fn main() {
                     .to_str()
                     .unwrap(),
             );
+            let ocr_engine = OcrOptions::from_settings(
+                "",
+                "",
+                "",
+                &app_handle.db(|db| get_setting_value(db, OCR_ENGINE_SETTING)),
+            )
+            .engine;
             prerequisites::check_and_install_prerequisites(
                 app_handle
                     .path_resolver()
@@ -194,9 +235,14 @@ async fn main() {
                     .unwrap()
                     .to_str()
                     .unwrap(),
+                ocr_engine,
             );
-            clean_up(app_handle.path_resolver().app_data_dir().unwrap());
             setup_keypress_listener(&app_handle);
+            screenshot_retention_engine::start_retention_task(app_handle.clone());
+            forward_vector_index_health(app_handle.clone());
+            embedding_queue_engine::start_embedding_worker(app_handle.clone());
+            resume_vector_index_rebuild(app_handle.clone());
+            monitoring_scheduler_engine::start_monitoring_scheduler(app_handle.clone());
             init_app_permissions(app_handle);
             Ok(())
         })
@@ -208,9 +254,14 @@ async fn main() {
 fn build_system_tray() -> SystemTray {
     let quit = CustomMenuItem::new("quit".to_string(), "Quit");
     let start_stop_recording =
-        CustomMenuItem::new("start_stop_recording".to_string(), "Start/Stop");
+        CustomMenuItem::new(TRAY_TOGGLE_ITEM.to_string(), "Start monitoring");
+    let pause_15_minutes =
+        CustomMenuItem::new("pause_15_minutes".to_string(), "Pause for 15 minutes");
+    let pause_1_hour = CustomMenuItem::new("pause_1_hour".to_string(), "Pause for 1 hour");
     let tray_menu = SystemTrayMenu::new()
         .add_item(start_stop_recording)
+        .add_item(pause_15_minutes)
+        .add_item(pause_1_hour)
         .add_item(quit);
     SystemTray::new().with_menu(tray_menu)
 }
@@ -286,7 +337,101 @@ async fn update_settings(app_handle: AppHandle, settings: Settings) {
             },
         )
         .unwrap();
+        if let Some(reranker) = &settings.reranker {
+            insert_or_update_setting(
+                db,
+                Setting {
+                    setting_key: String::from("reranker"),
+                    setting_value: reranker.clone(),
+                },
+            )
+            .unwrap();
+        }
+        if let Some(threshold) = settings.near_duplicate_threshold {
+            insert_or_update_setting(
+                db,
+                Setting {
+                    setting_key: String::from(NEAR_DUPLICATE_THRESHOLD_SETTING),
+                    setting_value: threshold.to_string(),
+                },
+            )
+            .unwrap();
+        }
+        let relevance_settings = [
+            (RECENCY_HALF_LIFE_DAYS_SETTING, settings.recency_half_life_days),
+            (RECENCY_WEIGHT_SETTING, settings.recency_weight),
+            (ENGAGEMENT_WEIGHT_SETTING, settings.engagement_weight),
+        ];
+        for (key, value) in relevance_settings.iter() {
+            if let Some(value) = value {
+                insert_or_update_setting(
+                    db,
+                    Setting {
+                        setting_key: String::from(*key),
+                        setting_value: value.to_string(),
+                    },
+                )
+                .unwrap();
+            }
+        }
+        if let Some(minutes) = settings.idle_threshold_minutes {
+            insert_or_update_setting(
+                db,
+                Setting {
+                    setting_key: String::from(IDLE_THRESHOLD_MINUTES_SETTING),
+                    setting_value: minutes.to_string(),
+                },
+            )
+            .unwrap();
+        }
+        if let Some(threshold) = settings.screenshot_change_threshold {
+            insert_or_update_setting(
+                db,
+                Setting {
+                    setting_key: String::from(SCREENSHOT_CHANGE_THRESHOLD_SETTING),
+                    setting_value: threshold.to_string(),
+                },
+            )
+            .unwrap();
+        }
+        let capture_settings = [
+            (OCR_LANGUAGES_SETTING, settings.ocr_languages.clone()),
+            (
+                OCR_PAGE_SEGMENTATION_MODE_SETTING,
+                settings.ocr_page_segmentation_mode.map(|mode| mode.to_string()),
+            ),
+            (
+                OCR_MIN_CONFIDENCE_SETTING,
+                settings.ocr_min_confidence.map(|confidence| confidence.to_string()),
+            ),
+            (OCR_ENGINE_SETTING, settings.ocr_engine.clone()),
+            (VECTOR_STORE_SETTING, settings.vector_store.clone()),
+            (SCREENSHOT_RETENTION_SETTING, settings.screenshot_retention.clone()),
+            (
+                SCREENSHOT_RETENTION_COUNT_SETTING,
+                settings.screenshot_retention_count.map(|count| count.to_string()),
+            ),
+            (SCREENSHOT_FORMAT_SETTING, settings.screenshot_format.clone()),
+            (
+                SCREENSHOT_MAX_WIDTH_SETTING,
+                settings.screenshot_max_width.map(|width| width.to_string()),
+            ),
+        ];
+        for (key, value) in capture_settings.iter() {
+            if let Some(value) = value {
+                insert_or_update_setting(
+                    db,
+                    Setting {
+                        setting_key: String::from(*key),
+                        setting_value: value.clone(),
+                    },
+                )
+                .unwrap();
+            }
+        }
     });
+    // A new API key may unblock documents waiting to be embedded
+    embedding_queue_engine::notify_embedding_queue();
 }
 
 #[tauri::command]
@@ -349,49 +494,9 @@ async fn record_single_activity(
         return Ok(vec![]);
     }
 
-    let mut activity_item = monitoring_engine::start_a_monitoring_cycle(
-        app_handle.clone(),
-        app_handle
-            .path_resolver()
-            .app_data_dir()
-            .unwrap()
-            .to_str()
-            .unwrap(),
-    )
-    .await;
-    activity_item.user_id = String::from(user);
-    app_handle.db(|db| {
-        let setting = get_setting(db, "interval").expect("Failed on interval");
-        activity_item.interval_length = setting.setting_value.parse().unwrap_or(20);
-    });
-    info!("USER_ID: {}", activity_item.user_id);
-    app_handle
-        .db(|db| activity_log_repository::save_activity_item(&activity_item.clone(), db))
-        .expect("Failed to save activity log");
-    let last_insert_rowid = app_handle
-        .db(|db| activity_log_repository::save_activity_full_text(&activity_item.clone(), db))
-        .expect("Failed to save activity full text");
-
-    let settings =
-        app_handle.db(|db| get_setting(db, "api_key_open_ai").expect("Failed on api_key_open_ai"));
-    match last_insert_rowid {
-        Some(rowid) => {
-            info!("Getting ready to add record to OasysDB, row={}", rowid);
-            let mut oasys_db = database::get_vector_db(&app_handle)
-                .await
-                .expect("Database initialization failed!");
-            activity_log_repository::save_activity_full_text_into_vector_db(
-                &mut oasys_db,
-                &activity_item,
-                rowid,
-                &settings.setting_value,
-            )
-            .await
-            .unwrap_or(());
-        }
-        None => info!("No last insert rowid available"),
+    if let Err(e) = monitoring_scheduler_engine::record_activity(&app_handle, user).await {
+        error!("Failed to record activity: {}", e);
     }
-
     return Ok(get_latest_activity_log(app_handle.clone()));
 }
 
@@ -465,10 +570,16 @@ fn get_activity_history(
 }
 
 #[tauri::command]
-fn delete_activity(app_handle: AppHandle, id: i64) -> Result<bool, String> {
-    app_handle
+async fn delete_activity(app_handle: AppHandle, id: i64) -> Result<bool, String> {
+    let deleted = app_handle
         .db(|db: &Connection| crate::activity_log_repository::delete_activity(db, id))
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+    if deleted {
+        let store = vector_store_engine::open_vector_store(&app_handle).await?;
+        store.remove(&[id]).await.map_err(|e| e.to_string())?;
+        store.persist().await.map_err(|e| e.to_string())?;
+    }
+    Ok(deleted)
 }
 
 #[tauri::command]
@@ -522,6 +633,11 @@ fn update_project_activity_name(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_embedding_cache_stats() -> Result<EmbeddingCacheStats, ()> {
+    Ok(embedding_cache_engine::stats())
+}
+
 #[cfg(target_os = "macos")]
 #[tauri::command]
 fn prompt_for_accessibility_permissions() {
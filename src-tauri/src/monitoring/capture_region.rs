@@ -0,0 +1,169 @@
+use active_win_pos_rs::WindowPosition;
+
+// Clipped windows smaller than this are not worth cropping to, so the screen is used
+const MIN_CROP_SIZE: u32 = 32;
+
+/// A rectangle in desktop coordinates, as reported for screens and windows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The window's bounds, or None when the window has no area (the position of a window
+    /// that could not be read is all zeroes).
+    pub fn from_window(position: &WindowPosition) -> Option<Self> {
+        if !(position.width >= 1.0 && position.height >= 1.0) {
+            return None;
+        }
+        Some(Rect::new(
+            position.x.round() as i32,
+            position.y.round() as i32,
+            position.width.round() as u32,
+            position.height.round() as u32,
+        ))
+    }
+
+    fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= left as i64 || bottom <= top as i64 {
+            return None;
+        }
+        Some(Rect::new(
+            left,
+            top,
+            (right - left as i64) as u32,
+            (bottom - top as i64) as u32,
+        ))
+    }
+}
+
+/// Picks the screen showing most of the window. Without a window, or when it is on no
+/// screen, the primary screen is used, then the first one.
+pub fn select_screen(
+    screens: &[Rect],
+    primary: Option<usize>,
+    window: Option<Rect>,
+) -> Option<usize> {
+    let by_overlap = window.and_then(|window| {
+        screens
+            .iter()
+            .enumerate()
+            .filter_map(|(index, screen)| {
+                screen
+                    .intersect(&window)
+                    .map(|overlap| (index, overlap.area()))
+            })
+            .max_by_key(|(_, area)| *area)
+            .map(|(index, _)| index)
+    });
+    by_overlap
+        .or(primary.filter(|index| *index < screens.len()))
+        .or(if screens.is_empty() { None } else { Some(0) })
+}
+
+/// The area of `screen` to capture, relative to the screen's origin: the part of the window
+/// on that screen, or the whole screen when the window is unknown, elsewhere or too small.
+pub fn crop_region(screen: Rect, window: Option<Rect>) -> Rect {
+    let whole_screen = Rect::new(0, 0, screen.width, screen.height);
+    match window.and_then(|window| screen.intersect(&window)) {
+        Some(visible) if visible.width >= MIN_CROP_SIZE && visible.height >= MIN_CROP_SIZE => {
+            Rect::new(
+                visible.x - screen.x,
+                visible.y - screen.y,
+                visible.width,
+                visible.height,
+            )
+        }
+        _ => whole_screen,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use active_win_pos_rs::WindowPosition;
+
+    use super::{crop_region, select_screen, Rect};
+
+    // A laptop screen with a larger monitor to its left, placed slightly higher
+    fn screens() -> Vec<Rect> {
+        vec![
+            Rect::new(0, 0, 1440, 900),
+            Rect::new(-2560, -200, 2560, 1440),
+        ]
+    }
+
+    #[test]
+    fn window_on_the_secondary_screen_selects_it() {
+        let window = Rect::new(-2000, 100, 1200, 800);
+        assert_eq!(select_screen(&screens(), Some(0), Some(window)), Some(1));
+        assert_eq!(
+            crop_region(screens()[1], Some(window)),
+            Rect::new(560, 300, 1200, 800)
+        );
+    }
+
+    #[test]
+    fn window_across_screens_selects_the_larger_part_and_is_clipped() {
+        let window = Rect::new(-300, 100, 1000, 600);
+        assert_eq!(select_screen(&screens(), Some(1), Some(window)), Some(0));
+        assert_eq!(
+            crop_region(screens()[0], Some(window)),
+            Rect::new(0, 100, 700, 600)
+        );
+    }
+
+    #[test]
+    fn unknown_or_offscreen_windows_fall_back_to_the_primary_screen() {
+        assert_eq!(select_screen(&screens(), Some(1), None), Some(1));
+        let offscreen = Rect::new(5000, 5000, 800, 600);
+        assert_eq!(select_screen(&screens(), Some(1), Some(offscreen)), Some(1));
+        assert_eq!(select_screen(&screens(), None, Some(offscreen)), Some(0));
+        assert_eq!(select_screen(&[], Some(0), Some(offscreen)), None);
+        assert_eq!(
+            crop_region(screens()[0], Some(offscreen)),
+            Rect::new(0, 0, 1440, 900)
+        );
+    }
+
+    #[test]
+    fn slivers_and_empty_positions_capture_the_whole_screen() {
+        let sliver = Rect::new(1430, 0, 400, 900);
+        assert_eq!(
+            crop_region(screens()[0], Some(sliver)),
+            Rect::new(0, 0, 1440, 900)
+        );
+        assert_eq!(Rect::from_window(&WindowPosition::default()), None);
+        assert_eq!(
+            Rect::from_window(&WindowPosition::new(10.4, -20.6, 799.5, 600.2)),
+            Some(Rect::new(10, -21, 800, 600))
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use log::{debug, info};
+
+/// What the desktop session reports about the user being away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdleState {
+    /// Time since the last keyboard or mouse input.
+    pub idle_for: Duration,
+    /// The screen is locked, so there is nothing of the user's to capture.
+    pub locked: bool,
+}
+
+impl IdleState {
+    pub fn active() -> Self {
+        IdleState {
+            idle_for: Duration::from_secs(0),
+            locked: false,
+        }
+    }
+}
+
+pub trait IdleDetector: Send + Sync {
+    fn idle_state(&self) -> Result<IdleState, String>;
+}
+
+/// Returns the detector for this platform. Platforms without one always report the user
+/// as active, so every cycle captures as before.
+pub fn system_idle_detector() -> Box<dyn IdleDetector> {
+    #[cfg(target_os = "linux")]
+    {
+        Box::new(linux::LinuxIdleDetector)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Box::new(AlwaysActive)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+struct AlwaysActive;
+
+#[cfg(not(target_os = "linux"))]
+impl IdleDetector for AlwaysActive {
+    fn idle_state(&self) -> Result<IdleState, String> {
+        Ok(IdleState::active())
+    }
+}
+
+/// A period in which cycles were skipped because the user was away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdleGap {
+    pub started_at: DateTime<Local>,
+    pub ended_at: DateTime<Local>,
+    pub locked: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IdleDecision {
+    /// Capture this cycle. Carries the gap that just ended when the user came back.
+    Capture(Option<IdleGap>),
+    Skip,
+}
+
+/// Decides cycle by cycle, from what a detector reports, whether the user is away, and
+/// remembers since when.
+pub struct IdleTracker {
+    threshold: Duration,
+    gap: Option<(DateTime<Local>, bool)>,
+}
+
+impl IdleTracker {
+    pub fn new(threshold: Duration) -> Self {
+        IdleTracker {
+            threshold,
+            gap: None,
+        }
+    }
+
+    pub fn set_threshold(&mut self, threshold: Duration) {
+        self.threshold = threshold;
+    }
+
+    /// Start of the current idle period, if the user is away.
+    pub fn idle_since(&self) -> Option<DateTime<Local>> {
+        self.gap.map(|(started_at, _)| started_at)
+    }
+
+    /// Cycles are skipped once input has stopped for the threshold, and right away while
+    /// the screen is locked. A detector error counts as active so captures do not stop.
+    pub fn observe(
+        &mut self,
+        state: Result<IdleState, String>,
+        now: DateTime<Local>,
+    ) -> IdleDecision {
+        let state = state.unwrap_or_else(|err| {
+            debug!(
+                "Idle detection failed, assuming the user is active: {}",
+                err
+            );
+            IdleState::active()
+        });
+        if state.locked || state.idle_for >= self.threshold {
+            match self.gap.as_mut() {
+                Some((_, locked)) => *locked |= state.locked,
+                None => {
+                    let idle_for = chrono::Duration::from_std(state.idle_for)
+                        .unwrap_or_else(|_| chrono::Duration::zero());
+                    info!(
+                        "User is away (idle for {}s, locked: {}), skipping captures",
+                        state.idle_for.as_secs(),
+                        state.locked
+                    );
+                    self.gap = Some((now - idle_for, state.locked));
+                }
+            }
+            return IdleDecision::Skip;
+        }
+        IdleDecision::Capture(self.gap.take().map(|(started_at, locked)| {
+            info!("User is back after {}s", (now - started_at).num_seconds());
+            IdleGap {
+                started_at,
+                ended_at: now,
+                locked,
+            }
+        }))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::env;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use x11rb::connection::Connection as _;
+    use x11rb::protocol::screensaver::ConnectionExt;
+    use zbus::blocking::{Connection, Proxy};
+
+    use super::{IdleDetector, IdleState};
+
+    /// Reads the lock state and idle hint of the current session from logind over D-Bus,
+    /// and the time since the last input from the X11 screensaver extension when an X
+    /// display is available. Either source alone is enough.
+    pub struct LinuxIdleDetector;
+
+    impl IdleDetector for LinuxIdleDetector {
+        fn idle_state(&self) -> Result<IdleState, String> {
+            let session = logind_session_state();
+            let x11_idle = if env::var_os("DISPLAY").is_some() {
+                x11_idle_time()
+            } else {
+                Err("No X11 display".to_string())
+            };
+            match (session, x11_idle) {
+                (Ok((locked, logind_idle)), Ok(idle_for)) => Ok(IdleState {
+                    idle_for: idle_for.max(logind_idle),
+                    locked,
+                }),
+                (Ok((locked, idle_for)), Err(_)) => Ok(IdleState { idle_for, locked }),
+                (Err(_), Ok(idle_for)) => Ok(IdleState {
+                    idle_for,
+                    locked: false,
+                }),
+                (Err(logind_error), Err(x11_error)) => {
+                    Err(format!("logind: {}; X11: {}", logind_error, x11_error))
+                }
+            }
+        }
+    }
+
+    fn logind_session_state() -> Result<(bool, Duration), String> {
+        let connection = Connection::system().map_err(|e| e.to_string())?;
+        let session = Proxy::new(
+            &connection,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1/session/auto",
+            "org.freedesktop.login1.Session",
+        )
+        .map_err(|e| e.to_string())?;
+        let locked: bool = session
+            .get_property("LockedHint")
+            .map_err(|e| e.to_string())?;
+        let idle: bool = session
+            .get_property("IdleHint")
+            .map_err(|e| e.to_string())?;
+        if !idle {
+            return Ok((locked, Duration::from_secs(0)));
+        }
+        // IdleSinceHint is wall-clock time in microseconds
+        let idle_since: u64 = session
+            .get_property("IdleSinceHint")
+            .map_err(|e| e.to_string())?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?;
+        Ok((
+            locked,
+            now.checked_sub(Duration::from_micros(idle_since))
+                .unwrap_or_default(),
+        ))
+    }
+
+    fn x11_idle_time() -> Result<Duration, String> {
+        let (connection, screen) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        let root = connection.setup().roots[screen].root;
+        let info = connection
+            .screensaver_query_info(root)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        Ok(Duration::from_millis(info.ms_since_user_input as u64))
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::sync::Mutex;
+
+    use super::{IdleDetector, IdleState};
+
+    /// Reports whatever state the test sets.
+    pub struct FakeIdleDetector {
+        state: Mutex<Result<IdleState, String>>,
+    }
+
+    impl Default for FakeIdleDetector {
+        fn default() -> Self {
+            FakeIdleDetector {
+                state: Mutex::new(Ok(IdleState::active())),
+            }
+        }
+    }
+
+    impl FakeIdleDetector {
+        pub fn set(&self, state: Result<IdleState, String>) {
+            *self.state.lock().unwrap() = state;
+        }
+    }
+
+    impl IdleDetector for FakeIdleDetector {
+        fn idle_state(&self) -> Result<IdleState, String> {
+            self.state.lock().unwrap().clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{Local, TimeZone};
+
+    use super::fake::FakeIdleDetector;
+    use super::{IdleDecision, IdleDetector, IdleGap, IdleState, IdleTracker};
+
+    fn idle(minutes: u64, locked: bool) -> Result<IdleState, String> {
+        Ok(IdleState {
+            idle_for: Duration::from_secs(minutes * 60),
+            locked,
+        })
+    }
+
+    #[test]
+    fn cycles_are_skipped_while_away_and_the_gap_is_reported() {
+        let start = Local.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        let minutes = |n: i64| start + chrono::Duration::minutes(n);
+        let detector = FakeIdleDetector::default();
+        let mut tracker = IdleTracker::new(Duration::from_secs(5 * 60));
+
+        assert_eq!(
+            tracker.observe(detector.idle_state(), start),
+            IdleDecision::Capture(None)
+        );
+        detector.set(idle(3, false));
+        assert_eq!(
+            tracker.observe(detector.idle_state(), minutes(3)),
+            IdleDecision::Capture(None)
+        );
+
+        detector.set(idle(6, false));
+        assert_eq!(
+            tracker.observe(detector.idle_state(), minutes(6)),
+            IdleDecision::Skip
+        );
+        assert_eq!(tracker.idle_since(), Some(start));
+        detector.set(idle(8, true));
+        assert_eq!(
+            tracker.observe(detector.idle_state(), minutes(8)),
+            IdleDecision::Skip
+        );
+
+        detector.set(idle(0, false));
+        assert_eq!(
+            tracker.observe(detector.idle_state(), minutes(20)),
+            IdleDecision::Capture(Some(IdleGap {
+                started_at: start,
+                ended_at: minutes(20),
+                locked: true,
+            }))
+        );
+        assert_eq!(tracker.idle_since(), None);
+    }
+
+    #[test]
+    fn locked_screen_skips_at_once_and_errors_count_as_active() {
+        let now = Local.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap();
+        let detector = FakeIdleDetector::default();
+        let mut tracker = IdleTracker::new(Duration::from_secs(5 * 60));
+
+        detector.set(idle(0, true));
+        assert_eq!(
+            tracker.observe(detector.idle_state(), now),
+            IdleDecision::Skip
+        );
+
+        detector.set(Err("no session".to_string()));
+        match tracker.observe(detector.idle_state(), now + chrono::Duration::minutes(1)) {
+            IdleDecision::Capture(Some(gap)) => assert!(gap.locked),
+            decision => panic!("unexpected decision {:?}", decision),
+        }
+    }
+}
@@ -1,2 +1,4 @@
 pub mod take_screenshot;
+pub mod capture_region;
 pub mod active_windows;
+pub mod idle_detection;
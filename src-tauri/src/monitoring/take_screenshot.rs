@@ -1,25 +1,53 @@
-use chrono::{DateTime, Local};
+use active_win_pos_rs::WindowPosition;
+use image::{DynamicImage, RgbaImage};
+use log::{error, info};
 use screenshots::Screen;
-use std::path::Path;
-use std::path::PathBuf;
-use log::info;
 
-pub fn take_screenshot(screenshots_path: PathBuf, timestamp: DateTime<Local>) {
-    let screens = Screen::all().unwrap();
+use crate::monitoring::capture_region::{crop_region, select_screen, Rect};
 
-    let primary_screen = screens
+/// Captures the active window on the screen that shows it. The image stays in memory; whether
+/// it is written to disk is up to the screenshot retention setting. Returns None when nothing
+/// could be captured, for example with no screen attached.
+pub fn take_screenshot(window_position: &WindowPosition) -> Option<DynamicImage> {
+    let screens = match Screen::all() {
+        Ok(screens) => screens,
+        Err(error) => {
+            error!("Failed to list screens: {error}");
+            return None;
+        }
+    };
+    let bounds: Vec<Rect> = screens
         .iter()
-        .find(|&&screen| screen.display_info.is_primary == true)
-        .expect("No primary screen");
+        .map(|screen| {
+            let info = screen.display_info;
+            Rect::new(info.x, info.y, info.width, info.height)
+        })
+        .collect();
+    let primary = screens
+        .iter()
+        .position(|screen| screen.display_info.is_primary);
+    let window = Rect::from_window(window_position);
 
-    info!("capturer {primary_screen:?}");
+    let index = match select_screen(&bounds, primary, window) {
+        Some(index) => index,
+        None => {
+            info!("No screen available, skipping the screenshot");
+            return None;
+        }
+    };
+    let screen = screens[index];
+    let region = crop_region(bounds[index], window);
+    info!("capturer {screen:?}, region {region:?}");
 
-    let image = primary_screen.capture().unwrap();
+    let captured = match screen.capture_area(region.x, region.y, region.width, region.height) {
+        Ok(captured) => captured,
+        Err(error) => {
+            error!("Failed to capture the screen: {error}");
+            return None;
+        }
+    };
 
-    image.save(Path::new( format!(
-        "{}/{}.png",
-        screenshots_path.display(),
-        timestamp.format("%Y-%m-%d_%H-%M-%S").to_string(),
-    ).as_str()))
-        .expect("Failed to save screenshot!");
+    // `screenshots` builds on its own version of the image crate, so the pixels are moved over
+    let (width, height) = (captured.width(), captured.height());
+    RgbaImage::from_raw(width, height, captured.into_raw()).map(DynamicImage::ImageRgba8)
 }
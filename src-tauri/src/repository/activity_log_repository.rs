@@ -3,11 +3,12 @@ use std::error::Error;
 use chrono::Local;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
-use rusqlite::{named_params, Connection};
+use rusqlite::{named_params, params, Connection};
 use rusqlite_from_row::FromRow;
 use std::collections::HashSet;
 
-use crate::configuration::database::SyncVectorDatabase;
+use crate::engine::near_duplicate_engine::{simhash, DocumentFingerprint};
+use crate::engine::relevance_scoring_engine::DocumentSignals;
 use crate::entity::activity_item::ActivityItem;
 
 pub fn save_activity_item(
@@ -17,10 +18,12 @@ pub fn save_activity_item(
     let mut statement = db.prepare("INSERT INTO activity_logs
     (timestamp, user_id, ocr_text, window_title,
     window_app_name, os_details, similarity_percentage_to_previous_ocr_text, keypress_count,
-     full_activity_text, interval_length, editing_mode, element_tree_dump, detected_actions, original_ocr_text)
+     full_activity_text, interval_length, editing_mode, element_tree_dump, detected_actions, original_ocr_text,
+     screenshot_hash, ocr_word_boxes)
      VALUES (@timestamp, @user_id, @ocr_text, @window_title, @window_app_name, @os_details,
      @similarity_percentage_to_previous_ocr_text, @keypress_count,
-     @full_activity_text, @interval_length, @editing_mode, @element_tree_dump, @detected_actions, @original_ocr_text)")?;
+     @full_activity_text, @interval_length, @editing_mode, @element_tree_dump, @detected_actions, @original_ocr_text,
+     @screenshot_hash, @ocr_word_boxes)")?;
 
     statement.execute(named_params! {
         "@timestamp": activity_item.timestamp,
@@ -37,6 +40,8 @@ pub fn save_activity_item(
         "@interval_length": activity_item.interval_length,
         "@element_tree_dump": activity_item.element_tree_dump,
         "@detected_actions": activity_item.detected_actions,
+        "@screenshot_hash": activity_item.screenshot_hash,
+        "@ocr_word_boxes": activity_item.ocr_word_boxes,
     })?;
     Ok(())
 }
@@ -51,29 +56,33 @@ pub fn save_activity_full_text(
         UPDATE activity_full_text
         SET dateofentry = datetime('now'),
             edited_full_text = @activity_full_text,
+            simhash = @simhash,
             save_count = save_count + 1
         WHERE window_title = @window_title
           AND window_app_name = @window_app_name;
     ",
     )?;
 
+    let fingerprint = simhash(&activity_item.full_activity_text) as i64;
     let rows_affected = update_statement.execute(named_params![
         "@window_title": &activity_item.window_title,
         "@window_app_name": &activity_item.window_app_name,
         "@activity_full_text": &activity_item.full_activity_text,
+        "@simhash": fingerprint,
     ])?;
 
     // If no rows were updated, insert a new row
     if rows_affected == 0 {
         let mut insert_statement = db.prepare("
-            INSERT INTO activity_full_text (dateofentry, window_title, window_app_name, original_full_text, edited_full_text)
-            VALUES (datetime('now'), @window_title, @window_app_name, @activity_full_text, @activity_full_text);
+            INSERT INTO activity_full_text (dateofentry, window_title, window_app_name, original_full_text, edited_full_text, simhash)
+            VALUES (datetime('now'), @window_title, @window_app_name, @activity_full_text, @activity_full_text, @simhash);
         ")?;
 
         insert_statement.execute(named_params![
             "@window_title": &activity_item.window_title,
             "@window_app_name": &activity_item.window_app_name,
             "@activity_full_text": &activity_item.full_activity_text,
+            "@simhash": fingerprint,
         ])?;
     }
 
@@ -93,31 +102,81 @@ pub fn save_activity_full_text(
 
     Ok(None)
 }
-pub async fn save_activity_full_text_into_vector_db(
-    oasys_db: &SyncVectorDatabase,
-    activity_item: &ActivityItem,
-    last_insert_rowid: i64,
-    api_key: &str,
-) -> Result<(), Box<dyn Error>> {
-    let id = last_insert_rowid;
+pub fn amplify_document_text(window_title: &str, full_text: &str) -> String {
     let max_length = 5000;
-    let truncated_text = if activity_item.full_activity_text.len() > max_length {
-        &activity_item.full_activity_text[..max_length]
-    } else {
-        &activity_item.full_activity_text
-    };
+    // Cut on a character boundary, as slicing inside a multi-byte character panics
+    let truncated_text = full_text
+        .char_indices()
+        .nth(max_length)
+        .map_or(full_text, |(index, _)| &full_text[..index]);
 
     // Add the window_title to the beginning and end of the truncated_text
-    let amplified_text = format!(
+    format!(
         "Document Title: [{}] {} Document Title: [{}]",
-        activity_item.window_title, truncated_text, activity_item.window_title
+        window_title, truncated_text, window_title
+    )
+}
+
+pub fn get_activity_full_text_batch(
+    db: &Connection,
+    after_id: i64,
+    limit: usize,
+) -> Result<Vec<(i64, String, String)>, rusqlite::Error> {
+    let query = "SELECT id, window_title, edited_full_text
+                 FROM activity_full_text
+                 WHERE id > ? AND window_title != ''
+                 ORDER BY id ASC
+                 LIMIT ?";
+
+    let mut stmt = db.prepare(query)?;
+    let rows = stmt.query_map(params![after_id, limit], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+
+    rows.collect()
+}
+
+pub fn count_activity_full_text_after(db: &Connection, after_id: i64) -> Result<i64, rusqlite::Error> {
+    db.query_row(
+        "SELECT COUNT(*) FROM activity_full_text WHERE id > ? AND window_title != ''",
+        [after_id],
+        |row| row.get(0),
+    )
+}
+
+/// Returns the IDs of documents that are not deleted. When `indexable_only` is set, only
+/// documents saved often enough to be added to the vector index are returned.
+pub fn get_live_activity_full_text_ids(
+    db: &Connection,
+    indexable_only: bool,
+) -> Result<Vec<i64>, rusqlite::Error> {
+    let query = if indexable_only {
+        "SELECT id FROM activity_full_text WHERE window_title != '' AND save_count >= 2"
+    } else {
+        "SELECT id FROM activity_full_text WHERE window_title != ''"
+    };
+
+    let mut stmt = db.prepare(query)?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+pub fn get_activity_full_text_for_indexing(
+    db: &Connection,
+    id: i64,
+) -> Result<Option<(String, String)>, rusqlite::Error> {
+    let result = db.query_row(
+        "SELECT window_title, edited_full_text FROM activity_full_text
+         WHERE id = ? AND window_title != ''",
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
     );
 
-    let mut db_guard = oasys_db.lock().await;
-    let db = db_guard.as_mut().expect("Database initialization failed!");
-    db.add(id, &amplified_text, api_key).await?;
-    db.sync().await?;
-    Ok(())
+    match result {
+        Ok(document) => Ok(Some(document)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
 }
 
 pub fn get_all_activity_logs(db: &Connection) -> Result<Vec<ActivityItem>, rusqlite::Error> {
@@ -145,6 +204,8 @@ pub fn get_all_activity_logs(db: &Connection) -> Result<Vec<ActivityItem>, rusql
             keypress_count: row.get("keypress_count").unwrap_or(0),
             element_tree_dump: row.get("element_tree_dump")?,
             detected_actions: row.get("detected_actions")?,
+            screenshot_hash: row.get("screenshot_hash").unwrap_or(None),
+            ocr_word_boxes: row.get("ocr_word_boxes").unwrap_or(None),
         });
     }
 
@@ -206,9 +267,32 @@ pub fn get_empty_activity_item() -> ActivityItem {
         keypress_count: 0,
         element_tree_dump: "/".to_string(),
         detected_actions: "/".to_string(),
+        screenshot_hash: None,
+        ocr_word_boxes: None,
     }
 }
 
+/// Adds `seconds` to the time logged for the latest capture of a window, for a cycle in
+/// which the window did not change.
+pub fn extend_activity_dwell(
+    db: &Connection,
+    window_title: &str,
+    window_app_name: &str,
+    seconds: u32,
+) -> Result<usize, rusqlite::Error> {
+    db.execute(
+        "UPDATE activity_logs SET interval_length = COALESCE(interval_length, 0) + :seconds
+         WHERE rowid = (SELECT rowid FROM activity_logs
+                        WHERE window_title = :window_title AND window_app_name = :window_app_name
+                        ORDER BY timestamp DESC LIMIT 1)",
+        named_params! {
+            ":seconds": seconds,
+            ":window_title": window_title,
+            ":window_app_name": window_app_name,
+        },
+    )
+}
+
 pub fn get_activity_full_text_by_id(
     db: &Connection,
     id: i64,
@@ -240,9 +324,88 @@ pub fn get_activity_full_text_by_id(
     }
 }
 
+/// Returns the fingerprints of the given documents that are not deleted. Documents saved
+/// before fingerprints were recorded are fingerprinted now and updated.
+pub fn get_document_fingerprints(
+    db: &Connection,
+    ids: &[i64],
+) -> Result<Vec<DocumentFingerprint>, rusqlite::Error> {
+    let mut statement = db.prepare(
+        "SELECT simhash, dateofentry, edited_full_text FROM activity_full_text
+         WHERE id = ? AND window_title != ''",
+    )?;
+    let mut fingerprints = Vec::new();
+    for id in ids {
+        let mut rows = statement.query([id])?;
+        if let Some(row) = rows.next()? {
+            let stored: Option<i64> = row.get(0)?;
+            let dateofentry: String = row.get(1)?;
+            let fingerprint = match stored {
+                Some(fingerprint) => fingerprint as u64,
+                None => {
+                    let text: String = row.get(2)?;
+                    let fingerprint = simhash(&text);
+                    db.execute(
+                        "UPDATE activity_full_text SET simhash = ?1 WHERE id = ?2",
+                        params![fingerprint as i64, id],
+                    )?;
+                    fingerprint
+                }
+            };
+            fingerprints.push(DocumentFingerprint {
+                id: *id,
+                simhash: fingerprint,
+                dateofentry,
+            });
+        }
+    }
+    Ok(fingerprints)
+}
+
+/// Loads the recency and engagement signals of the given documents. Dwell time is the sum
+/// of capture intervals logged for the document's window.
+pub fn get_document_signals(
+    db: &Connection,
+    ids: &[i64],
+) -> Result<Vec<DocumentSignals>, rusqlite::Error> {
+    let mut statement = db.prepare(
+        "SELECT f.dateofentry, f.save_count, COALESCE(SUM(l.interval_length), 0)
+         FROM activity_full_text f
+         LEFT JOIN activity_logs l
+           ON l.window_title = f.window_title AND l.window_app_name = f.window_app_name
+         WHERE f.id = ? AND f.window_title != ''
+         GROUP BY f.id",
+    )?;
+    let mut signals = Vec::new();
+    for id in ids {
+        let mut rows = statement.query([id])?;
+        if let Some(row) = rows.next()? {
+            signals.push(DocumentSignals {
+                id: *id,
+                dateofentry: row.get(0)?,
+                save_count: row.get::<_, Option<i64>>(1)?.unwrap_or(0),
+                dwell_seconds: row.get(2)?,
+            });
+        }
+    }
+    Ok(signals)
+}
+
+/// Returns the ids of the `limit` most recently saved documents.
+pub fn get_recent_activity_ids(db: &Connection, limit: usize) -> Result<Vec<i64>, rusqlite::Error> {
+    let mut statement = db.prepare(
+        "SELECT id FROM activity_full_text
+         WHERE window_title != ''
+         ORDER BY dateofentry DESC
+         LIMIT ?",
+    )?;
+    let rows = statement.query_map([limit], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Returns the document whose window title best matches each keyword.
 pub fn get_additional_ids_from_sql_db(
     db: &Connection,
-    num_recent_entries: usize,
     keywords: &[String],
 ) -> Result<Vec<i64>, Box<dyn Error>> {
     let matcher = SkimMatcherV2::default();
@@ -277,14 +440,6 @@ pub fn get_additional_ids_from_sql_db(
         }
     }
 
-    // Get the most recent entries
-    let mut recent_entries_stmt =
-        db.prepare("SELECT id FROM activity_full_text ORDER BY dateofentry DESC LIMIT ?")?;
-    let recent_entries = recent_entries_stmt.query_map([num_recent_entries], |row| row.get(0))?;
-    for entry in recent_entries {
-        top_ids.insert(entry?);
-    }
-
     Ok(top_ids.into_iter().collect())
 }
 pub fn get_activity_history(
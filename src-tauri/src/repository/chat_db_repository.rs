@@ -59,6 +59,36 @@ pub fn update_chat(conn: &Connection, chat_id: i64, name: &str) -> Result<bool>
 pub fn delete_chat(db: &Connection, chat_id: i64) -> Result<bool, Error> {
     let rows_affected = db.execute("DELETE FROM chats WHERE id = ?", params![chat_id])?;
     db.execute("DELETE FROM messages WHERE chat_id = ?", params![chat_id])?;
+    db.execute("DELETE FROM chat_context_documents WHERE chat_id = ?", params![chat_id])?;
 
     Ok(rows_affected > 0)
-}
\ No newline at end of file
+}
+/// Documents retrieved earlier in the chat, most recently added first.
+pub fn get_chat_context_documents(db: &Connection, chat_id: i64) -> Result<Vec<(i64, f32)>, Error> {
+    let mut stmt = db.prepare(
+        "SELECT document_id, score FROM chat_context_documents
+         WHERE chat_id = ? ORDER BY added_at DESC, score DESC",
+    )?;
+    let documents = stmt.query_map(params![chat_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(documents.collect::<Result<_, _>>()?)
+}
+
+pub fn save_chat_context_document(
+    db: &Connection,
+    chat_id: i64,
+    document_id: i64,
+    score: f32,
+    query: &str,
+) -> Result<(), Error> {
+    let now = Local::now().to_rfc3339();
+    db.execute(
+        "INSERT INTO chat_context_documents (chat_id, document_id, score, query, added_at)
+         VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(chat_id, document_id) DO UPDATE SET
+            score = excluded.score,
+            query = excluded.query,
+            added_at = excluded.added_at",
+        params![chat_id, document_id, score, query, now],
+    )?;
+    Ok(())
+}
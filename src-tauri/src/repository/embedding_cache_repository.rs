@@ -0,0 +1,98 @@
+use chrono::Local;
+use rusqlite::{named_params, Connection, OptionalExtension};
+
+pub fn get_cached_embedding(
+    db: &Connection,
+    model: &str,
+    text_hash: &str,
+) -> Result<Option<Vec<f32>>, rusqlite::Error> {
+    let vector: Option<Vec<u8>> = db
+        .query_row(
+            "SELECT vector FROM embedding_cache WHERE model = @model AND text_hash = @text_hash",
+            named_params! {
+                "@model": model,
+                "@text_hash": text_hash,
+            },
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    match vector {
+        Some(bytes) => {
+            db.execute(
+                "UPDATE embedding_cache SET last_used_at = @last_used_at
+                 WHERE model = @model AND text_hash = @text_hash",
+                named_params! {
+                    "@last_used_at": Local::now().timestamp_millis(),
+                    "@model": model,
+                    "@text_hash": text_hash,
+                },
+            )?;
+            Ok(Some(vector_from_bytes(&bytes)))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn save_cached_embedding(
+    db: &Connection,
+    model: &str,
+    text_hash: &str,
+    vector: &[f32],
+) -> Result<(), rusqlite::Error> {
+    let mut statement = db.prepare(
+        "INSERT INTO embedding_cache (model, text_hash, dimension, vector, created_at, last_used_at)
+         VALUES (@model, @text_hash, @dimension, @vector, @created_at, @last_used_at)
+         ON CONFLICT(model, text_hash) DO UPDATE SET
+            dimension = excluded.dimension,
+            vector = excluded.vector,
+            last_used_at = excluded.last_used_at",
+    )?;
+
+    let now = Local::now();
+    statement.execute(named_params! {
+        "@model": model,
+        "@text_hash": text_hash,
+        "@dimension": vector.len() as i64,
+        "@vector": vector_to_bytes(vector),
+        "@created_at": now.to_rfc3339(),
+        "@last_used_at": now.timestamp_millis(),
+    })?;
+    Ok(())
+}
+
+pub fn evict_least_recently_used_embeddings(
+    db: &Connection,
+    max_entries: usize,
+) -> Result<usize, rusqlite::Error> {
+    let count: i64 = db.query_row("SELECT COUNT(*) FROM embedding_cache", [], |row| row.get(0))?;
+    let overflow = count - max_entries as i64;
+    if overflow <= 0 {
+        return Ok(0);
+    }
+
+    let rows_deleted = db.execute(
+        "DELETE FROM embedding_cache WHERE rowid IN (
+            SELECT rowid FROM embedding_cache ORDER BY last_used_at ASC LIMIT @overflow
+        )",
+        named_params! {
+            "@overflow": overflow,
+        },
+    )?;
+    Ok(rows_deleted)
+}
+
+pub fn count_cached_embeddings(db: &Connection) -> Result<i64, rusqlite::Error> {
+    db.query_row("SELECT COUNT(*) FROM embedding_cache", [], |row| row.get(0))
+}
+
+pub fn vector_to_bytes(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+pub fn vector_from_bytes(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
@@ -0,0 +1,17 @@
+use rusqlite::{named_params, Connection};
+
+use crate::monitoring::idle_detection::IdleGap;
+
+/// Records a period in which no activity was captured because the user was away.
+pub fn save_idle_period(db: &Connection, gap: &IdleGap) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "INSERT INTO idle_periods (started_at, ended_at, locked)
+         VALUES (@started_at, @ended_at, @locked)",
+        named_params! {
+            "@started_at": gap.started_at.to_rfc3339(),
+            "@ended_at": gap.ended_at.to_rfc3339(),
+            "@locked": gap.locked,
+        },
+    )?;
+    Ok(())
+}
@@ -5,3 +5,8 @@ pub mod permissions_repository;
 pub mod settings_repository;
 pub mod vector_db_repository;
 pub mod project_repository;
+pub mod embedding_cache_repository;
+pub mod vector_log_repository;
+pub mod vector_store_repository;
+pub mod pending_embedding_repository;
+pub mod idle_period_repository;
@@ -0,0 +1,142 @@
+use chrono::Local;
+use rusqlite::{named_params, Connection, OptionalExtension};
+use serde_derive::Serialize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEmbedding {
+    pub document_id: i64,
+    pub window_title: String,
+    pub full_text: String,
+    pub attempts: i64,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PendingEmbeddingCounts {
+    pub pending: i64,
+    /// Documents whose next attempt is due now.
+    pub due: i64,
+    /// Documents that failed at least once.
+    pub failing: i64,
+    pub oldest_enqueued_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// Queues a document for embedding. A document already in the queue keeps its place and
+/// its retry state.
+pub fn enqueue_pending_embedding(db: &Connection, document_id: i64) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "INSERT INTO pending_embeddings (document_id, enqueued_at)
+         VALUES (@document_id, @enqueued_at)
+         ON CONFLICT(document_id) DO NOTHING",
+        named_params! {
+            "@document_id": document_id,
+            "@enqueued_at": Local::now().to_rfc3339(),
+        },
+    )?;
+    Ok(())
+}
+
+/// Returns up to `limit` queued documents whose next attempt is due at `now_millis`,
+/// oldest first. Entries of documents deleted since they were queued are dropped.
+pub fn get_due_pending_embeddings(
+    db: &Connection,
+    now_millis: i64,
+    limit: usize,
+) -> Result<Vec<PendingEmbedding>, rusqlite::Error> {
+    db.execute(
+        "DELETE FROM pending_embeddings WHERE document_id NOT IN (
+            SELECT id FROM activity_full_text WHERE window_title != ''
+        )",
+        [],
+    )?;
+
+    let mut statement = db.prepare(
+        "SELECT pending_embeddings.document_id, activity_full_text.window_title,
+                activity_full_text.edited_full_text, pending_embeddings.attempts
+         FROM pending_embeddings
+         JOIN activity_full_text ON activity_full_text.id = pending_embeddings.document_id
+         WHERE pending_embeddings.next_attempt_at <= @now
+         ORDER BY pending_embeddings.document_id ASC
+         LIMIT @limit",
+    )?;
+    let rows = statement.query_map(
+        named_params! {
+            "@now": now_millis,
+            "@limit": limit as i64,
+        },
+        |row| {
+            Ok(PendingEmbedding {
+                document_id: row.get(0)?,
+                window_title: row.get(1)?,
+                full_text: row.get(2)?,
+                attempts: row.get(3)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+pub fn delete_pending_embeddings(
+    db: &Connection,
+    document_ids: &[i64],
+) -> Result<usize, rusqlite::Error> {
+    let mut statement = db.prepare("DELETE FROM pending_embeddings WHERE document_id = ?1")?;
+    let mut rows_deleted = 0;
+    for document_id in document_ids {
+        rows_deleted += statement.execute([document_id])?;
+    }
+    Ok(rows_deleted)
+}
+
+pub fn mark_pending_embedding_failed(
+    db: &Connection,
+    document_id: i64,
+    next_attempt_at_millis: i64,
+    error: &str,
+) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "UPDATE pending_embeddings
+         SET attempts = attempts + 1, next_attempt_at = @next_attempt_at, last_error = @error
+         WHERE document_id = @document_id",
+        named_params! {
+            "@document_id": document_id,
+            "@next_attempt_at": next_attempt_at_millis,
+            "@error": error,
+        },
+    )?;
+    Ok(())
+}
+
+pub fn count_pending_embeddings(
+    db: &Connection,
+    now_millis: i64,
+) -> Result<PendingEmbeddingCounts, rusqlite::Error> {
+    let (pending, due, failing, oldest_enqueued_at) = db.query_row(
+        "SELECT COUNT(*),
+                COALESCE(SUM(next_attempt_at <= @now), 0),
+                COALESCE(SUM(attempts > 0), 0),
+                MIN(enqueued_at)
+         FROM pending_embeddings",
+        named_params! {
+            "@now": now_millis,
+        },
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    let last_error = db
+        .query_row(
+            "SELECT last_error FROM pending_embeddings
+             WHERE last_error IS NOT NULL
+             ORDER BY next_attempt_at DESC
+             LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(PendingEmbeddingCounts {
+        pending,
+        due,
+        failing,
+        oldest_enqueued_at,
+        last_error,
+    })
+}
@@ -38,6 +38,13 @@ pub fn get_setting(db: &Connection, setting_key: &str) -> Result<Setting, rusqli
     return Ok(row);
 }
 
+/// The value of `setting_key`, or an empty string when it is unset or cannot be read.
+pub fn get_setting_value(db: &Connection, setting_key: &str) -> String {
+    get_setting(db, setting_key)
+        .map(|setting| setting.setting_value)
+        .unwrap_or_default()
+}
+
 pub fn get_settings(db: &Connection) -> Result<Vec<Setting>, rusqlite::Error> {
     let mut statement = db.prepare("SELECT * FROM settings")?;
     let mut rows = statement.query([])?;
@@ -1,6 +1,8 @@
 use std::error::Error;
 use async_openai::{types::CreateEmbeddingRequestArgs, Client, config::OpenAIConfig};
 
+pub const EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
 // Correct async function for computing vector embeddings
 pub async fn compute_vector_embedding(text: &str, api_key: &str) -> Result<Vec<f32>, Box<dyn Error>> {
     let config: OpenAIConfig = OpenAIConfig::new()
@@ -8,9 +10,38 @@ pub async fn compute_vector_embedding(text: &str, api_key: &str) -> Result<Vec<f
 
     let client = Client::with_config(config);
     let request = CreateEmbeddingRequestArgs::default()
-        .model("text-embedding-3-small")
+        .model(EMBEDDING_MODEL)
         .input([text])
         .build()?;
     let response = client.embeddings().create(request).await?;
     Ok(response.data[0].embedding.clone())
 }
+
+/// Embeds several texts with one request. The vectors are returned in the order of `texts`.
+pub async fn compute_vector_embeddings(
+    texts: &[String],
+    api_key: &str,
+) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+    let config: OpenAIConfig = OpenAIConfig::new().with_api_key(api_key);
+
+    let client = Client::with_config(config);
+    let request = CreateEmbeddingRequestArgs::default()
+        .model(EMBEDDING_MODEL)
+        .input(texts.to_vec())
+        .build()?;
+    let mut response = client.embeddings().create(request).await?;
+    if response.data.len() != texts.len() {
+        return Err(format!(
+            "Expected {} embeddings, received {}",
+            texts.len(),
+            response.data.len()
+        )
+        .into());
+    }
+    response.data.sort_by_key(|embedding| embedding.index);
+    Ok(response
+        .data
+        .into_iter()
+        .map(|embedding| embedding.embedding)
+        .collect())
+}
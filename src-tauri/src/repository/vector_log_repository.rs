@@ -0,0 +1,201 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use log::{info, warn};
+
+const RECORD_ADD: u8 = 1;
+const RECORD_TOMBSTONE: u8 = 2;
+// sequence, kind, id and dimension
+const RECORD_HEADER_LENGTH: usize = 8 + 1 + 8 + 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogRecord {
+    Add { id: usize, vector: Vec<f32> },
+    Tombstone { id: usize },
+}
+
+/// Append-only log of changes made to a vector collection since its last snapshot.
+///
+/// Each record is written as `[length: u32][body][checksum: u64]` and synced to disk before
+/// `append` returns, so a record is either fully on disk or detected as torn on replay.
+pub struct VectorLog {
+    file: File,
+    sequence: u64,
+}
+
+impl VectorLog {
+    /// Opens the log and returns the records found in it. A torn or corrupt tail, left by a
+    /// crash during an append, is cut off. Sequence numbers continue from the highest of
+    /// `last_sequence` and the records read.
+    pub fn open(path: &Path, last_sequence: u64) -> io::Result<(Self, Vec<(u64, LogRecord)>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while let Some((sequence, record, length)) = decode_record(&contents[offset..]) {
+            records.push((sequence, record));
+            offset += length;
+        }
+        if offset < contents.len() {
+            warn!(
+                "Discarding {} bytes of torn records at the end of {}",
+                contents.len() - offset,
+                path.display()
+            );
+            file.set_len(offset as u64)?;
+            file.sync_data()?;
+        }
+        file.seek(SeekFrom::Start(offset as u64))?;
+
+        let sequence = records
+            .iter()
+            .map(|(sequence, _)| *sequence)
+            .max()
+            .unwrap_or(0)
+            .max(last_sequence);
+        if !records.is_empty() {
+            info!("Read {} records from {}", records.len(), path.display());
+        }
+        Ok((VectorLog { file, sequence }, records))
+    }
+
+    /// Durably appends `record` and returns its sequence number.
+    pub fn append(&mut self, record: &LogRecord) -> io::Result<u64> {
+        let sequence = self.sequence + 1;
+        self.file.write_all(&encode_record(sequence, record))?;
+        self.file.sync_data()?;
+        self.sequence = sequence;
+        Ok(sequence)
+    }
+
+    /// Sequence number of the last record appended or read.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Empties the log once its records are covered by a snapshot.
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_data()
+    }
+}
+
+fn encode_record(sequence: u64, record: &LogRecord) -> Vec<u8> {
+    let (kind, id, vector): (u8, usize, &[f32]) = match record {
+        LogRecord::Add { id, vector } => (RECORD_ADD, *id, vector),
+        LogRecord::Tombstone { id } => (RECORD_TOMBSTONE, *id, &[]),
+    };
+    let mut body = Vec::with_capacity(RECORD_HEADER_LENGTH + vector.len() * 4);
+    body.extend_from_slice(&sequence.to_le_bytes());
+    body.push(kind);
+    body.extend_from_slice(&(id as u64).to_le_bytes());
+    body.extend_from_slice(&(vector.len() as u32).to_le_bytes());
+    for value in vector {
+        body.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut bytes = Vec::with_capacity(4 + body.len() + 8);
+    bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&body);
+    bytes.extend_from_slice(&checksum(&body).to_le_bytes());
+    bytes
+}
+
+/// Returns the record at the start of `bytes` and its encoded length, or `None` if the
+/// bytes do not hold a complete, intact record.
+fn decode_record(bytes: &[u8]) -> Option<(u64, LogRecord, usize)> {
+    let body_length = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+    let body = bytes.get(4..4 + body_length)?;
+    let stored_checksum = u64::from_le_bytes(
+        bytes
+            .get(4 + body_length..4 + body_length + 8)?
+            .try_into()
+            .ok()?,
+    );
+    if body_length < RECORD_HEADER_LENGTH || checksum(body) != stored_checksum {
+        return None;
+    }
+
+    let sequence = u64::from_le_bytes(body[0..8].try_into().ok()?);
+    let kind = body[8];
+    let id = u64::from_le_bytes(body[9..17].try_into().ok()?) as usize;
+    let dimension = u32::from_le_bytes(body[17..21].try_into().ok()?) as usize;
+    let values = &body[RECORD_HEADER_LENGTH..];
+    if values.len() != dimension * 4 {
+        return None;
+    }
+    let record = match kind {
+        RECORD_ADD => LogRecord::Add {
+            id,
+            vector: values
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect(),
+        },
+        RECORD_TOMBSTONE => LogRecord::Tombstone { id },
+        _ => return None,
+    };
+    Some((sequence, record, 4 + body_length + 8))
+}
+
+// FNV-1a; enough to tell a torn write from a complete one
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    use super::{LogRecord, VectorLog};
+
+    #[test]
+    fn replays_records_and_drops_torn_tail() -> std::io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("collection.wal");
+        let records = vec![
+            LogRecord::Add {
+                id: 4,
+                vector: vec![0.5, -1.0, 2.25],
+            },
+            LogRecord::Tombstone { id: 2 },
+        ];
+        {
+            let (mut log, replayed) = VectorLog::open(&path, 0)?;
+            assert!(replayed.is_empty());
+            for record in &records {
+                log.append(record)?;
+            }
+        }
+        // A crash in the middle of the next append
+        OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(&[40, 0, 0, 0, 3, 0])?;
+
+        let (mut log, replayed) = VectorLog::open(&path, 0)?;
+        assert_eq!(
+            replayed,
+            vec![(1, records[0].clone()), (2, records[1].clone())]
+        );
+        assert_eq!(log.append(&LogRecord::Tombstone { id: 9 })?, 3);
+        log.truncate()?;
+        drop(log);
+
+        let (log, replayed) = VectorLog::open(&path, 3)?;
+        assert!(replayed.is_empty());
+        assert_eq!(log.sequence(), 3);
+        Ok(())
+    }
+}
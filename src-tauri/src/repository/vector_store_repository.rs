@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+
+use chrono::Local;
+use rusqlite::{named_params, Connection};
+
+use crate::repository::embedding_cache_repository::{vector_from_bytes, vector_to_bytes};
+
+/// Stores the vector of a document along with its int8 quantization, replacing any
+/// previous vector. Call it on the connection that saved the document to keep both in
+/// the same transaction.
+pub fn save_document_vector(
+    db: &Connection,
+    document_id: i64,
+    model: &str,
+    vector: &[f32],
+) -> Result<(), rusqlite::Error> {
+    let mut statement = db.prepare(
+        "INSERT INTO activity_vectors (document_id, model, dimension, vector, quantized_vector, updated_at)
+         VALUES (@document_id, @model, @dimension, @vector, @quantized_vector, @updated_at)
+         ON CONFLICT(document_id) DO UPDATE SET
+            model = excluded.model,
+            dimension = excluded.dimension,
+            vector = excluded.vector,
+            quantized_vector = excluded.quantized_vector,
+            updated_at = excluded.updated_at",
+    )?;
+
+    let quantized_vector: Vec<u8> = quantize_vector(vector)
+        .into_iter()
+        .map(|value| value as u8)
+        .collect();
+    statement.execute(named_params! {
+        "@document_id": document_id,
+        "@model": model,
+        "@dimension": vector.len() as i64,
+        "@vector": vector_to_bytes(vector),
+        "@quantized_vector": quantized_vector,
+        "@updated_at": Local::now().to_rfc3339(),
+    })?;
+    Ok(())
+}
+
+pub fn delete_document_vectors(
+    db: &Connection,
+    document_ids: &[i64],
+) -> Result<usize, rusqlite::Error> {
+    let mut statement = db.prepare("DELETE FROM activity_vectors WHERE document_id = ?1")?;
+    let mut rows_deleted = 0;
+    for document_id in document_ids {
+        rows_deleted += statement.execute([document_id])?;
+    }
+    Ok(rows_deleted)
+}
+
+/// Calls `visit` with the id and raw vector of every document embedded with `model` at
+/// `dimension`. With `quantized` the bytes are the int8 quantization instead of the f32s.
+pub fn scan_document_vectors(
+    db: &Connection,
+    model: &str,
+    dimension: usize,
+    quantized: bool,
+    mut visit: impl FnMut(i64, &[u8]),
+) -> Result<(), rusqlite::Error> {
+    let query = if quantized {
+        "SELECT document_id, quantized_vector FROM activity_vectors
+         WHERE model = @model AND dimension = @dimension"
+    } else {
+        "SELECT document_id, vector FROM activity_vectors
+         WHERE model = @model AND dimension = @dimension"
+    };
+    let mut statement = db.prepare(query)?;
+    let mut rows = statement.query(named_params! {
+        "@model": model,
+        "@dimension": dimension as i64,
+    })?;
+    while let Some(row) = rows.next()? {
+        let document_id: i64 = row.get(0)?;
+        let bytes = row.get_ref(1)?.as_blob()?;
+        visit(document_id, bytes);
+    }
+    Ok(())
+}
+
+pub fn get_document_vectors(
+    db: &Connection,
+    model: &str,
+    document_ids: &[i64],
+) -> Result<Vec<(i64, Vec<f32>)>, rusqlite::Error> {
+    let mut statement = db.prepare(
+        "SELECT vector FROM activity_vectors WHERE document_id = @document_id AND model = @model",
+    )?;
+    let mut vectors = Vec::new();
+    for document_id in document_ids {
+        let mut rows = statement.query(named_params! {
+            "@document_id": document_id,
+            "@model": model,
+        })?;
+        if let Some(row) = rows.next()? {
+            let bytes: Vec<u8> = row.get(0)?;
+            vectors.push((*document_id, vector_from_bytes(&bytes)));
+        }
+    }
+    Ok(vectors)
+}
+
+/// Indexable documents that have no vector of `model`, for example after switching stores.
+pub fn get_document_ids_without_vector(
+    db: &Connection,
+    model: &str,
+) -> Result<Vec<i64>, rusqlite::Error> {
+    let mut statement = db.prepare(
+        "SELECT id FROM activity_full_text
+         WHERE window_title != '' AND save_count >= 2
+           AND id NOT IN (SELECT document_id FROM activity_vectors WHERE model = ?1)",
+    )?;
+    let rows = statement.query_map([model], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// Number of stored vectors of `model` per dimension.
+pub fn count_document_vectors_by_dimension(
+    db: &Connection,
+    model: &str,
+) -> Result<BTreeMap<usize, usize>, rusqlite::Error> {
+    let mut statement = db.prepare(
+        "SELECT dimension, COUNT(*) FROM activity_vectors WHERE model = ?1 GROUP BY dimension",
+    )?;
+    let counts = statement
+        .query_map([model], |row| {
+            Ok((
+                row.get::<_, i64>(0)? as usize,
+                row.get::<_, i64>(1)? as usize,
+            ))
+        })?
+        .collect::<Result<BTreeMap<_, _>, _>>()?;
+    Ok(counts)
+}
+
+/// Symmetric int8 quantization scaled to the largest component. Cosine similarity is
+/// scale-invariant, so quantized vectors can be compared without storing the scale.
+pub fn quantize_vector(vector: &[f32]) -> Vec<i8> {
+    let max = vector.iter().fold(0f32, |max, value| max.max(value.abs()));
+    if max == 0.0 {
+        return vec![0; vector.len()];
+    }
+    vector
+        .iter()
+        .map(|value| (value / max * 127.0).round() as i8)
+        .collect()
+}
@@ -3,9 +3,8 @@ import { createContext, useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api";
 import { listen } from "@tauri-apps/api/event";
 import dayjs from "dayjs";
-import type { ActivityLogItem } from "./types";
+import type { ActivityLogItem, MonitoringStatus } from "./types";
 import { buildActivityLogFromResponse } from "./utils";
-import { useGlobalSettings } from "../SettingsProvider";
 import { useUser } from "@/state/userState";
 
 type RecordingState = {
@@ -38,7 +37,6 @@ export const RecordingStateContext = createContext<RecordingStateContextType>({
 });
 
 export const RecordingStateProvider: FC<PropsWithChildren> = ({ children }) => {
-  const { settings } = useGlobalSettings();
   const { user } = useUser();
 
   const [isRecording, setRecording] = useState<RecordingState["isRecording"]>(
@@ -56,20 +54,23 @@ export const RecordingStateProvider: FC<PropsWithChildren> = ({ children }) => {
   );
 
   // ===== Handle recording =====
-  let recordActivityInterval: NodeJS.Timeout;
+  // The backend scheduler runs the capture cycles; the webview only mirrors its state
+  const applyMonitoringStatus = (status: MonitoringStatus) => {
+    const running = status.state === "running";
+    setRecording(running);
+    setStartRecordingTime((prevTime) =>
+      running ? prevTime || dayjs().unix() : 0
+    );
+  };
+
   useEffect(() => {
-    if (isRecording) {
-      recordActivityInterval = setInterval(() => {
-        console.log("taking a screenshot in interval");
-        recordSingleActivity();
-      }, +settings.interval * 1000);
-    } else {
-      if (recordActivityInterval) {
-        clearInterval(recordActivityInterval);
-      }
+    if (!user.id) {
+      return;
     }
-    return () => clearInterval(recordActivityInterval);
-  }, [isRecording]);
+    invoke<MonitoringStatus>("start_monitoring", { userId: user.id }).then(
+      applyMonitoringStatus
+    );
+  }, [user.id]);
 
   // ===== Update recording time =====
   useEffect(() => {
@@ -83,39 +84,53 @@ export const RecordingStateProvider: FC<PropsWithChildren> = ({ children }) => {
   }, [startRecordingTime]);
 
   useEffect(() => {
-    toggleRecording(true);
-    const unlisten = listen<string>("toggle_recording", (event) => {
-      toggleRecording();
-    });
+    invoke<MonitoringStatus>("monitoring_status").then(applyMonitoringStatus);
+    const unlistenStatus = listen<MonitoringStatus>(
+      "monitoring_status",
+      (event) => {
+        applyMonitoringStatus(event.payload);
+      }
+    );
+    // Each cycle sends only the row it added
+    const unlistenActivity = listen<ActivityLogItem>(
+      "activity_recorded",
+      (event) => {
+        setActivityLog((prevLog) => [...prevLog, event.payload]);
+        setActivityTitle(event.payload.window_title);
+      }
+    );
 
     return () => {
-      unlisten.then((f) => f());
+      unlistenStatus.then((f) => f());
+      unlistenActivity.then((f) => f());
     };
   }, []);
 
   const toggleRecording: ToggleRecording = (newIsRecording) => {
-    if (newIsRecording) {
-      setRecording(newIsRecording);
+    const shouldRecord = newIsRecording ?? !isRecording;
+    if (shouldRecord) {
+      invoke<MonitoringStatus>("resume_monitoring")
+        .catch(() =>
+          invoke<MonitoringStatus>("start_monitoring", { userId: user.id })
+        )
+        .then(applyMonitoringStatus);
     } else {
-      setRecording((prevState) => !prevState);
-      setStartRecordingTime(0);
-
-      if (isRecording) {
-        console.log("activity to close session!");
-        recordSingleActivity();
-      }
+      console.log("activity to close session!");
+      recordSingleActivity();
+      invoke<MonitoringStatus>("pause_monitoring").then(applyMonitoringStatus);
     }
-    setStartRecordingTime(isRecording ? 0 : dayjs().unix());
+  };
+
+  const updateActivityLog = (response: unknown) => {
+    let activityLog = buildActivityLogFromResponse(response);
+    setActivityLog(activityLog);
+    setActivityTitle(activityLog[activityLog.length - 1].window_title);
   };
 
   const recordSingleActivity = () => {
     invoke("record_single_activity", {
       user: user.id,
-    }).then((response) => {
-      let activityLog = buildActivityLogFromResponse(response);
-      setActivityLog(activityLog);
-      setActivityTitle(activityLog[activityLog.length - 1].window_title);
-    });
+    }).then(updateActivityLog);
   };
 
   const setTitle: SetTitle = (title) => {
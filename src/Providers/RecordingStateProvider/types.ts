@@ -14,3 +14,14 @@ export const settingDbItemZod = z.object({
 });
 export const settingDbItemsZod = settingDbItemZod.array();
 export type SettingDbItem = z.infer<typeof settingDbItemZod>;
+
+export type MonitoringStatus = {
+  state: "stopped" | "running" | "paused";
+  user_id: string;
+  paused_until: string | null;
+  last_cycle_at: string | null;
+  next_cycle_at: string | null;
+  cycles: number;
+  skipped_cycles: number;
+  idle_since: string | null;
+};
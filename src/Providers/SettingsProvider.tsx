@@ -27,6 +27,9 @@ type Update = {
 };
 
 type ApiChoice = "claude" | "openai";
+export type OcrEngine = "auto" | "tesseract" | "pure_rust";
+export type ScreenshotRetention = "none" | "last" | "days";
+export type ScreenshotFormat = "png" | "jpeg" | "webp";
 export type Settings = {
   is_dev_mode: boolean;
   interval: string;
@@ -34,6 +37,17 @@ export type Settings = {
   api_choice: ApiChoice;
   api_key_claude: string;
   api_key_open_ai: string;
+  // Left out until set, so the backend keeps its defaults
+  ocr_languages?: string;
+  ocr_page_segmentation_mode?: number;
+  ocr_min_confidence?: number;
+  ocr_engine?: OcrEngine;
+  idle_threshold_minutes?: number;
+  screenshot_change_threshold?: number;
+  screenshot_retention?: ScreenshotRetention;
+  screenshot_retention_count?: number;
+  screenshot_format?: ScreenshotFormat;
+  screenshot_max_width?: number;
 };
 
 type SettingsContextType = {
@@ -61,6 +75,14 @@ export const SettingsProvider: FC<PropsWithChildren> = ({ children }) => {
     return "";
   };
 
+  const getNumberSetting = (
+    settings: SettingDbItem[],
+    settingKey: string
+  ): number | undefined => {
+    const value = parseFloat(getSettingOrEmpty(settings, settingKey));
+    return isNaN(value) ? undefined : value;
+  };
+
   const buildSettings = (response: SettingDbItem[]): Settings => {
     console.log(getSettingOrEmpty(response, "interval"));
     return {
@@ -71,6 +93,35 @@ export const SettingsProvider: FC<PropsWithChildren> = ({ children }) => {
         (getSettingOrEmpty(response, "api_choice") as ApiChoice) || "claude",
       api_key_claude: getSettingOrEmpty(response, "api_key_claude") || "",
       api_key_open_ai: getSettingOrEmpty(response, "api_key_open_ai") || "",
+      ocr_languages: getSettingOrEmpty(response, "ocr_languages") || undefined,
+      ocr_page_segmentation_mode: getNumberSetting(
+        response,
+        "ocr_page_segmentation_mode"
+      ),
+      ocr_min_confidence: getNumberSetting(response, "ocr_min_confidence"),
+      ocr_engine:
+        (getSettingOrEmpty(response, "ocr_engine") as OcrEngine) || undefined,
+      idle_threshold_minutes: getNumberSetting(
+        response,
+        "idle_threshold_minutes"
+      ),
+      screenshot_change_threshold: getNumberSetting(
+        response,
+        "screenshot_change_threshold"
+      ),
+      screenshot_retention:
+        (getSettingOrEmpty(
+          response,
+          "screenshot_retention"
+        ) as ScreenshotRetention) || undefined,
+      screenshot_retention_count: getNumberSetting(
+        response,
+        "screenshot_retention_count"
+      ),
+      screenshot_format:
+        (getSettingOrEmpty(response, "screenshot_format") as ScreenshotFormat) ||
+        undefined,
+      screenshot_max_width: getNumberSetting(response, "screenshot_max_width"),
     };
   };
 
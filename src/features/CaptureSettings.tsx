@@ -0,0 +1,283 @@
+import { useEffect, useState } from "react";
+import {
+  Box,
+  Flex,
+  Text,
+  Select,
+  VStack,
+  Input,
+  Button,
+  useToast,
+} from "@chakra-ui/react";
+import { invoke } from "@tauri-apps/api";
+import {
+  useGlobalSettings,
+  type OcrEngine,
+  type ScreenshotFormat,
+  type ScreenshotRetention,
+} from "../Providers/SettingsProvider";
+
+type LocalSettings = {
+  ocrLanguages: string;
+  ocrPageSegmentationMode: string;
+  ocrMinConfidence: string;
+  ocrEngine: OcrEngine;
+  idleThresholdMinutes: string;
+  screenshotChangeThreshold: string;
+  screenshotRetention: ScreenshotRetention;
+  screenshotRetentionCount: string;
+  screenshotFormat: ScreenshotFormat;
+  screenshotMaxWidth: string;
+};
+
+const toText = (value?: number) => (value === undefined ? "" : `${value}`);
+
+// An empty field leaves the setting out, so the backend keeps its default
+const toNumber = (value: string) => {
+  const parsed = parseFloat(value);
+  return isNaN(parsed) ? undefined : parsed;
+};
+
+// The backend stores these as whole numbers and rejects fractions
+const toInteger = (value: string) => {
+  const parsed = toNumber(value);
+  return parsed === undefined ? undefined : Math.max(0, Math.round(parsed));
+};
+
+export const CaptureSettings = () => {
+  const toast = useToast();
+  const { settings, update } = useGlobalSettings();
+  const buildLocalSettings = (): LocalSettings => ({
+    ocrLanguages: settings.ocr_languages || "",
+    ocrPageSegmentationMode: toText(settings.ocr_page_segmentation_mode),
+    ocrMinConfidence: toText(settings.ocr_min_confidence),
+    ocrEngine: settings.ocr_engine || "auto",
+    idleThresholdMinutes: toText(settings.idle_threshold_minutes),
+    screenshotChangeThreshold: toText(settings.screenshot_change_threshold),
+    screenshotRetention: settings.screenshot_retention || "none",
+    screenshotRetentionCount: toText(settings.screenshot_retention_count),
+    screenshotFormat: settings.screenshot_format || "png",
+    screenshotMaxWidth: toText(settings.screenshot_max_width),
+  });
+  const [localSettings, setLocalSettings] =
+    useState<LocalSettings>(buildLocalSettings);
+  const [missingLanguages, setMissingLanguages] = useState<string[]>([]);
+  const [languageError, setLanguageError] = useState("");
+
+  useEffect(() => {
+    setLocalSettings(buildLocalSettings());
+  }, [settings]);
+
+  // Tesseract reads nothing when any of the languages has no installed pack
+  const checkLanguages = (languages: string) => {
+    invoke<string[]>("check_ocr_languages", { languages })
+      .then((missing) => {
+        setMissingLanguages(missing);
+        setLanguageError("");
+      })
+      .catch((error) => {
+        setMissingLanguages([]);
+        setLanguageError(`${error}`);
+      });
+  };
+
+  useEffect(() => {
+    checkLanguages(settings.ocr_languages || "");
+  }, [settings.ocr_languages]);
+
+  const onChange =
+    (key: keyof LocalSettings) =>
+    (
+      event: React.ChangeEvent<HTMLInputElement | HTMLSelectElement>
+    ) => {
+      const value = event.target.value;
+      setLocalSettings((prevState) => ({ ...prevState, [key]: value }));
+    };
+
+  const onSave = async () => {
+    await update({
+      ...settings,
+      ocr_languages: localSettings.ocrLanguages.trim() || undefined,
+      ocr_page_segmentation_mode: toInteger(
+        localSettings.ocrPageSegmentationMode
+      ),
+      ocr_min_confidence: toNumber(localSettings.ocrMinConfidence),
+      ocr_engine: localSettings.ocrEngine,
+      idle_threshold_minutes: toInteger(localSettings.idleThresholdMinutes),
+      screenshot_change_threshold: toInteger(
+        localSettings.screenshotChangeThreshold
+      ),
+      screenshot_retention: localSettings.screenshotRetention,
+      screenshot_retention_count: toInteger(
+        localSettings.screenshotRetentionCount
+      ),
+      screenshot_format: localSettings.screenshotFormat,
+      screenshot_max_width: toInteger(localSettings.screenshotMaxWidth),
+    });
+    toast({
+      title: "Settings saved successfully",
+      status: "success",
+      duration: 2000,
+      isClosable: true,
+    });
+  };
+
+  const renderRow = (label: string, control: React.ReactNode) => (
+    <Flex alignItems="center" mb={2}>
+      <Flex flex={1}>
+        <Text fontSize="md" mr={4}>
+          {label}
+        </Text>
+      </Flex>
+      <Flex flex={2}>{control}</Flex>
+    </Flex>
+  );
+
+  return (
+    <Box>
+      <VStack spacing={8} align="stretch">
+        <Box>
+          {renderRow(
+            "OCR Languages:",
+            <Input
+              placeholder="eng"
+              value={localSettings.ocrLanguages}
+              onChange={onChange("ocrLanguages")}
+              onBlur={() => checkLanguages(localSettings.ocrLanguages)}
+            />
+          )}
+          {missingLanguages.length > 0 && (
+            <Text fontSize="sm" color="red.500" mb={2}>
+              No Tesseract language pack is installed for{" "}
+              {missingLanguages.join(", ")}. Install it or remove the language,
+              otherwise Tesseract recognises no text.
+            </Text>
+          )}
+          {languageError && (
+            <Text fontSize="sm" color="orange.500" mb={2}>
+              {languageError}
+            </Text>
+          )}
+          {renderRow(
+            "OCR Engine:",
+            <Select
+              value={localSettings.ocrEngine}
+              onChange={onChange("ocrEngine")}
+            >
+              <option value="auto">Automatic</option>
+              <option value="tesseract">Tesseract</option>
+              <option value="pure_rust">Built-in (English only)</option>
+            </Select>
+          )}
+          {renderRow(
+            "Page Segmentation Mode:",
+            <Input
+              type="number"
+              placeholder="3"
+              value={localSettings.ocrPageSegmentationMode}
+              onChange={onChange("ocrPageSegmentationMode")}
+            />
+          )}
+          {renderRow(
+            "Minimum Confidence:",
+            <Input
+              type="number"
+              placeholder="65"
+              value={localSettings.ocrMinConfidence}
+              onChange={onChange("ocrMinConfidence")}
+            />
+          )}
+          <Text fontSize="sm" color="gray.500">
+            Languages are Tesseract language packs such as eng, deu or pol,
+            separated by +. Words recognised with a lower confidence, from 0 to
+            100, are left out.
+          </Text>
+        </Box>
+
+        <Box>
+          {renderRow(
+            "Idle Threshold (minutes):",
+            <Input
+              type="number"
+              placeholder="5"
+              value={localSettings.idleThresholdMinutes}
+              onChange={onChange("idleThresholdMinutes")}
+            />
+          )}
+          {renderRow(
+            "Screen Change Threshold:",
+            <Input
+              type="number"
+              placeholder="2"
+              value={localSettings.screenshotChangeThreshold}
+              onChange={onChange("screenshotChangeThreshold")}
+            />
+          )}
+          <Text fontSize="sm" color="gray.500">
+            Capturing pauses after this long without input. Screens that differ
+            from the previous capture by no more bits than the change threshold
+            are not read again.
+          </Text>
+        </Box>
+
+        <Box>
+          {renderRow(
+            "Keep Screenshots:",
+            <Select
+              value={localSettings.screenshotRetention}
+              onChange={onChange("screenshotRetention")}
+            >
+              <option value="none">None</option>
+              <option value="last">The most recent</option>
+              <option value="days">For a number of days</option>
+            </Select>
+          )}
+          {localSettings.screenshotRetention !== "none" && (
+            <>
+              {renderRow(
+                localSettings.screenshotRetention === "last"
+                  ? "Number of Screenshots:"
+                  : "Number of Days:",
+                <Input
+                  type="number"
+                  value={localSettings.screenshotRetentionCount}
+                  onChange={onChange("screenshotRetentionCount")}
+                />
+              )}
+              {renderRow(
+                "Format:",
+                <Select
+                  value={localSettings.screenshotFormat}
+                  onChange={onChange("screenshotFormat")}
+                >
+                  <option value="png">PNG</option>
+                  <option value="jpeg">JPEG</option>
+                  <option value="webp">WebP</option>
+                </Select>
+              )}
+              {renderRow(
+                "Maximum Width:",
+                <Input
+                  type="number"
+                  placeholder="Full size"
+                  value={localSettings.screenshotMaxWidth}
+                  onChange={onChange("screenshotMaxWidth")}
+                />
+              )}
+            </>
+          )}
+          <Text fontSize="sm" color="gray.500">
+            Screenshots are only needed to read their text, so by default none
+            are kept on disk.
+          </Text>
+
+          <Flex flex={1} justifyContent="flex-end">
+            <Button colorScheme="blue" size="md" onClick={onSave}>
+              Save
+            </Button>
+          </Flex>
+        </Box>
+      </VStack>
+    </Box>
+  );
+};
@@ -1,4 +1,5 @@
 export { PrivacySettings } from "./PrivacySettings";
 export { HistorySettings } from "./HistorySettings";
 export { GeneralSettings } from "./GeneralSettings";
+export { CaptureSettings } from "./CaptureSettings";
 export { Projects } from "./Projects";
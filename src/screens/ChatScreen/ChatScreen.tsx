@@ -385,6 +385,7 @@ export const ChatScreen: FC = () => {
         await invoke("send_prompt_to_openai", {
           conversationHistory: fullConversation,
           isFirstMessage,
+          chatId,
           combinedActivityText: (await getSelectedProjectActivityText()) + "\n" + selectedActivityTexts.join("\n\n"),
 
         });
@@ -392,6 +393,7 @@ export const ChatScreen: FC = () => {
         await invoke("send_prompt_to_llm", {
           conversationHistory: fullConversation,
           isFirstMessage,
+          chatId,
           combinedActivityText: (await getSelectedProjectActivityText()) + "\n" + selectedActivityTexts.join("\n\n"),
 
         });
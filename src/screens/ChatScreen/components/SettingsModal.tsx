@@ -15,6 +15,7 @@ import {
   PrivacySettings,
   HistorySettings,
   GeneralSettings,
+  CaptureSettings,
 } from "../../../features";
 
 interface SettingsModalProps {
@@ -34,6 +35,8 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
     switch (activeCategory) {
       case "general":
         return <GeneralSettings />;
+      case "capture":
+        return <CaptureSettings />;
       case "privacy":
         return <PrivacySettings />;
       case "history":
@@ -87,6 +90,18 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
                   General
                 </Button>
               </Box>
+              <Box mb={4}>
+                <Button
+                  variant={activeCategory === "capture" ? "solid" : "ghost"}
+                  colorScheme="blue"
+                  size="sm"
+                  onClick={() => setActiveCategory("capture")}
+                  width="100%"
+                  justifyContent="flex-start"
+                >
+                  Capture
+                </Button>
+              </Box>
               <Box mb={4}>
                 <Button
                   variant={activeCategory === "privacy" ? "solid" : "ghost"}